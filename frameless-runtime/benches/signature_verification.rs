@@ -0,0 +1,98 @@
+//! Compares verifying the signatures of a many-input transaction one by one, as done by the
+//! transaction pool, with verifying them as one batch, as done by `execute_block`.
+//!
+//! Run with `cargo bench -p frameless-runtime`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use frameless_runtime::utxo;
+use parity_scale_codec::Encode;
+use sp_core::{
+	sr25519::Pair,
+	testing::TaskExecutor,
+	traits::TaskExecutorExt,
+	Pair as PairT, H256, H512,
+};
+use sp_runtime::traits::{BlakeTwo256, Hash};
+
+const GENESIS_HASH: [u8; 32] = [7u8; 32];
+
+/// Externalities holding `inputs` UTXOs owned by `pair` and a transaction spending all of them
+fn setup(pair: &Pair, inputs: u32) -> (sp_io::TestExternalities, utxo::Transaction) {
+	let owner = H256::from(pair.public().0);
+	let utxos: Vec<_> = (1..=inputs)
+		.map(|value| utxo::TransactionOutput {
+			value: value as utxo::Value,
+			pubkey: owner,
+			lock: utxo::Lock::Pubkey,
+			data: None,
+			confidential: None,
+		})
+		.collect();
+
+	let mut ext = sp_io::TestExternalities::default();
+	ext.register_extension(TaskExecutorExt::new(TaskExecutor::new()));
+	ext.execute_with(|| {
+		sp_io::storage::set(&utxo::GENESIS_HASH_KEY, &H256::from(GENESIS_HASH).encode());
+		for utxo in utxos.iter() {
+			sp_io::storage::set(&BlakeTwo256::hash_of(utxo).encode(), &utxo.encode());
+		}
+	});
+
+	let mut transaction = utxo::Transaction {
+		inputs: utxos
+			.iter()
+			.map(|utxo| utxo::TransactionInput {
+				outpoint: BlakeTwo256::hash_of(utxo),
+				..Default::default()
+			})
+			.collect(),
+		outputs: vec![utxo::TransactionOutput {
+			value: 1,
+			pubkey: owner,
+			lock: utxo::Lock::Pubkey,
+			data: None,
+			confidential: None,
+		}],
+		..Default::default()
+	};
+	for index in 0..transaction.inputs.len() {
+		let payload = utxo::get_signing_payload(
+			&transaction,
+			index,
+			&H256::from(GENESIS_HASH),
+			frameless_runtime::VERSION.transaction_version,
+		)
+		.unwrap();
+		transaction.inputs[index].sigscript = H512::from(pair.sign(&payload));
+	}
+
+	(ext, transaction)
+}
+
+fn signature_verification(c: &mut Criterion) {
+	let pair = Pair::from_string("//Alice", None).unwrap();
+	let mut group = c.benchmark_group("validate_transaction");
+
+	for inputs in [16u32, 64, 256] {
+		let (mut ext, transaction) = setup(&pair, inputs);
+
+		group.bench_with_input(BenchmarkId::new("individual", inputs), &transaction, |b, tx| {
+			b.iter(|| ext.execute_with(|| utxo::validate_transaction(tx).unwrap()))
+		});
+
+		group.bench_with_input(BenchmarkId::new("batched", inputs), &transaction, |b, tx| {
+			b.iter(|| {
+				ext.execute_with(|| {
+					sp_io::crypto::start_batch_verify();
+					utxo::validate_transaction(tx).unwrap();
+					assert!(sp_io::crypto::finish_batch_verify());
+				})
+			})
+		});
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, signature_verification);
+criterion_main!(benches);
@@ -0,0 +1,332 @@
+//! Commitment to the UTXO set, independent of the rest of the state.
+//!
+//! The UTXO set is kept in a sparse Merkle tree of height 256 whose leaves are indexed by
+//! outpoint. The leaf of an outpoint is the hash of the outpoint and its `TransactionOutput`.
+//! Empty subtrees hash to zero and are not stored, and a subtree holding a single UTXO hashes
+//! to its leaf and is stored as a `Node::Leaf` where it starts. Only the nodes above the point
+//! where the paths of the UTXOs part are stored, so a UTXO adds about two nodes and an update
+//! touches about `log2` of the size of the set of them. Outpoints are hashes, so the paths
+//! cannot be made much longer without grinding them. The root is committed in the header
+//! digest of every block.
+
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_runtime::{
+	traits::{BlakeTwo256, Hash},
+	ConsensusEngineId, Digest, DigestItem,
+};
+use sp_std::prelude::*;
+
+use crate::utxo::TransactionOutput;
+
+/// Engine id of the digest item holding the UTXO set root
+pub const UTXO_ENGINE_ID: ConsensusEngineId = *b"utxo";
+
+/// Prefix of the storage keys of tree nodes. Keys are longer than 32 bytes so they are
+/// never mistaken for UTXOs.
+pub const NODE_PREFIX: [u8; 15] = *b"utxo_commitment";
+
+/// Height of the tree, one level per bit of an outpoint
+pub const TREE_HEIGHT: u16 = 256;
+
+/// Number of levels an update is expected to touch at most. Reaching below it takes a set of
+/// far more UTXOs than fit into state, or outpoints ground to share a long prefix.
+pub const MAX_EXPECTED_DEPTH: u16 = 64;
+
+/// Proof that an outpoint holds a given UTXO, or that it is not in the UTXO set
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct UtxoProof {
+	/// The UTXO at the outpoint, `None` to prove it is not in the set
+	pub utxo: Option<TransactionOutput>,
+
+	/// Outpoint and UTXO hash of the leaf found where the path ends when proving that the
+	/// outpoint is not in the set, `None` if the path ends in an empty subtree
+	pub other_leaf: Option<(H256, H256)>,
+
+	/// Non-empty siblings of the path from where it ends to the root with their height,
+	/// in increasing height. The path ends at the height of the first one. Missing heights
+	/// are empty subtrees.
+	pub siblings: Vec<(u16, H256)>,
+}
+
+sp_api::decl_runtime_apis! {
+	/// Commitment to the UTXO set for light clients
+	pub trait UtxoCommitmentApi {
+		/// Root of the UTXO set at this block
+		fn utxo_root() -> H256;
+
+		/// Proof for `outpoint` against `utxo_root`
+		fn prove_outpoint(outpoint: H256) -> UtxoProof;
+	}
+}
+
+/// Where tree nodes are kept: runtime storage, or the genesis storage map while building it
+pub trait NodeStore {
+	fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+	fn set(&mut self, key: &[u8], value: &[u8]);
+	fn clear(&mut self, key: &[u8]);
+}
+
+/// Nodes kept in the storage of the running runtime
+pub struct RuntimeStore;
+
+impl NodeStore for RuntimeStore {
+	fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+		sp_io::storage::get(key)
+	}
+
+	fn set(&mut self, key: &[u8], value: &[u8]) {
+		sp_io::storage::set(key, value)
+	}
+
+	fn clear(&mut self, key: &[u8]) {
+		sp_io::storage::clear(key)
+	}
+}
+
+#[cfg(feature = "std")]
+impl NodeStore for std::collections::BTreeMap<Vec<u8>, Vec<u8>> {
+	fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+		std::collections::BTreeMap::get(self, key).cloned()
+	}
+
+	fn set(&mut self, key: &[u8], value: &[u8]) {
+		self.insert(key.to_vec(), value.to_vec());
+	}
+
+	fn clear(&mut self, key: &[u8]) {
+		self.remove(key);
+	}
+}
+
+/// Node stored in the tree. Empty subtrees are not stored.
+#[derive(PartialEq, Eq, Clone, Copy, Encode, Decode, Debug, TypeInfo)]
+pub enum Node {
+	/// Subtree holding a single UTXO. It is stored at the highest height where the UTXO is
+	/// alone and its hash is the leaf hash, so the path below it is never stored.
+	Leaf { outpoint: H256, utxo_hash: H256 },
+
+	/// Subtree holding two or more UTXOs, with the hash of its children
+	Branch(H256),
+}
+
+impl Node {
+	pub fn hash(&self) -> H256 {
+		match self {
+			Node::Leaf { outpoint, utxo_hash } => leaf_node_hash(outpoint, utxo_hash),
+			Node::Branch(hash) => *hash,
+		}
+	}
+}
+
+/// Domain separator of leaf hashes, which are never mistaken for the hash of two children
+const LEAF_PREFIX: u8 = 0;
+
+/// Leaf of an outpoint holding `utxo`
+pub fn leaf_hash(outpoint: &H256, utxo: &TransactionOutput) -> H256 {
+	leaf_node_hash(outpoint, &BlakeTwo256::hash_of(utxo))
+}
+
+fn leaf_node_hash(outpoint: &H256, utxo_hash: &H256) -> H256 {
+	BlakeTwo256::hash_of(&(LEAF_PREFIX, outpoint, utxo_hash))
+}
+
+/// Parent of two nodes, zero if both are empty
+pub fn node_hash(left: &H256, right: &H256) -> H256 {
+	if left.is_zero() && right.is_zero() {
+		return H256::zero()
+	}
+	BlakeTwo256::hash_of(&(left, right))
+}
+
+/// Bit of `path` deciding between the children of the node at `height + 1`, counted from
+/// the most significant bit
+fn is_right(path: &H256, height: u16) -> bool {
+	let index = (TREE_HEIGHT - 1 - height) as usize;
+	path.as_bytes()[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+/// Storage key of the node at `height` on the path to `path`
+fn node_key(height: u16, path: &H256) -> Vec<u8> {
+	(NODE_PREFIX, height, prefix(height, path)).encode()
+}
+
+/// `path` with the bits below `height` cleared, only the bits above address the node
+fn prefix(height: u16, path: &H256) -> H256 {
+	let mut prefix = *path;
+	for index in (TREE_HEIGHT - height) as usize..TREE_HEIGHT as usize {
+		prefix.as_bytes_mut()[index / 8] &= !(0x80 >> (index % 8));
+	}
+	prefix
+}
+
+/// Path to the sibling of the node at `height` on the path to `path`
+fn sibling_path(path: &H256, height: u16) -> H256 {
+	let index = (TREE_HEIGHT - 1 - height) as usize;
+	let mut sibling = *path;
+	sibling.as_bytes_mut()[index / 8] ^= 0x80 >> (index % 8);
+	sibling
+}
+
+fn get_node<S: NodeStore>(store: &S, height: u16, path: &H256) -> Option<Node> {
+	store
+		.get(&node_key(height, path))
+		.and_then(|raw_node| Node::decode(&mut &raw_node[..]).ok())
+}
+
+fn set_node<S: NodeStore>(store: &mut S, height: u16, path: &H256, node: Option<Node>) {
+	match node {
+		Some(node) => store.set(&node_key(height, path), &node.encode()),
+		None => store.clear(&node_key(height, path)),
+	}
+}
+
+/// Hash of the node at `height` on the path to `path`, given its child on the path and the
+/// child's sibling
+fn parent_hash(path: &H256, height: u16, child: Option<Node>, sibling: Option<Node>) -> H256 {
+	let child = child.map(|node| node.hash()).unwrap_or_default();
+	let sibling = sibling.map(|node| node.hash()).unwrap_or_default();
+	if is_right(path, height - 1) {
+		node_hash(&sibling, &child)
+	} else {
+		node_hash(&child, &sibling)
+	}
+}
+
+/// Add `leaf` for `outpoint` to the subtree at `height`, returning the new node there.
+/// Only the branches down to where the leaf fits are read and written, a leaf in its way is
+/// moved down until their paths part.
+fn insert_at<S: NodeStore>(store: &mut S, height: u16, outpoint: &H256, leaf: Node) -> Node {
+	let node = match get_node(store, height, outpoint) {
+		None => leaf,
+		Some(Node::Leaf { outpoint: other, .. }) if other == *outpoint => leaf,
+		Some(existing) => {
+			if let Node::Leaf { outpoint: other, .. } = existing {
+				set_node(store, height - 1, &other, Some(existing));
+			}
+			let child = insert_at(store, height - 1, outpoint, leaf);
+			let sibling = get_node(store, height - 1, &sibling_path(outpoint, height - 1));
+			Node::Branch(parent_hash(outpoint, height, Some(child), sibling))
+		},
+	};
+	set_node(store, height, outpoint, Some(node));
+	node
+}
+
+/// Remove the leaf of `outpoint` from the subtree at `height`, returning the new node there.
+/// A leaf left alone in a branch moves up in its place and the nodes below are deleted.
+fn remove_at<S: NodeStore>(store: &mut S, height: u16, outpoint: &H256) -> Option<Node> {
+	let node = match get_node(store, height, outpoint)? {
+		Node::Leaf { outpoint: other, .. } if other == *outpoint => None,
+		leaf @ Node::Leaf { .. } => return Some(leaf),
+		Node::Branch(_) => {
+			let child = remove_at(store, height - 1, outpoint);
+			let sibling_path = sibling_path(outpoint, height - 1);
+			let sibling = get_node(store, height - 1, &sibling_path);
+			match (child, sibling) {
+				(None, None) => None,
+				(Some(leaf @ Node::Leaf { .. }), None) => {
+					set_node(store, height - 1, outpoint, None);
+					Some(leaf)
+				},
+				(None, Some(leaf @ Node::Leaf { .. })) => {
+					set_node(store, height - 1, &sibling_path, None);
+					Some(leaf)
+				},
+				(child, sibling) =>
+					Some(Node::Branch(parent_hash(outpoint, height, child, sibling))),
+			}
+		},
+	};
+	set_node(store, height, outpoint, node);
+	node
+}
+
+/// Add a UTXO to the tree
+pub fn insert<S: NodeStore>(store: &mut S, outpoint: &H256, utxo: &TransactionOutput) {
+	let leaf = Node::Leaf { outpoint: *outpoint, utxo_hash: BlakeTwo256::hash_of(utxo) };
+	insert_at(store, TREE_HEIGHT, outpoint, leaf);
+}
+
+/// Remove a spent UTXO from the tree
+pub fn remove<S: NodeStore>(store: &mut S, outpoint: &H256) {
+	remove_at(store, TREE_HEIGHT, outpoint);
+}
+
+/// Root of the tree
+pub fn root<S: NodeStore>(store: &S) -> H256 {
+	get_node(store, TREE_HEIGHT, &H256::zero())
+		.map(|node| node.hash())
+		.unwrap_or_default()
+}
+
+/// Proof for `outpoint`, holding `utxo` if it is in the set
+pub fn prove<S: NodeStore>(
+	store: &S,
+	outpoint: &H256,
+	utxo: Option<TransactionOutput>,
+) -> UtxoProof {
+	let mut siblings = Vec::new();
+	let mut height = TREE_HEIGHT;
+	while let Some(Node::Branch(_)) = get_node(store, height, outpoint) {
+		height -= 1;
+		if let Some(sibling) = get_node(store, height, &sibling_path(outpoint, height)) {
+			siblings.push((height, sibling.hash()));
+		}
+	}
+	siblings.reverse();
+	let other_leaf = match get_node(store, height, outpoint) {
+		Some(Node::Leaf { outpoint: other, utxo_hash }) if other != *outpoint =>
+			Some((other, utxo_hash)),
+		_ => None,
+	};
+	UtxoProof { utxo, other_leaf, siblings }
+}
+
+/// Check `proof` for `outpoint` against the UTXO set root `root`
+pub fn verify_proof(root: &H256, outpoint: &H256, proof: &UtxoProof) -> bool {
+	if proof.siblings.iter().any(|(_, sibling)| sibling.is_zero()) {
+		return false
+	}
+	// The path ends where the lowest sibling is, at the root without siblings
+	let end = proof.siblings.first().map(|(height, _)| *height).unwrap_or(TREE_HEIGHT);
+	let mut node = match (&proof.utxo, &proof.other_leaf) {
+		(Some(utxo), None) => leaf_hash(outpoint, utxo),
+		(None, None) => H256::zero(),
+		(None, Some((other, utxo_hash)))
+			if other != outpoint && prefix(end, other) == prefix(end, outpoint) =>
+			leaf_node_hash(other, utxo_hash),
+		_ => return false,
+	};
+	let mut siblings = proof.siblings.iter().peekable();
+	for height in end..TREE_HEIGHT {
+		let sibling = match siblings.peek() {
+			Some((sibling_height, sibling)) if *sibling_height == height => {
+				siblings.next();
+				*sibling
+			},
+			_ => H256::zero(),
+		};
+		node = if is_right(outpoint, height) {
+			node_hash(&sibling, &node)
+		} else {
+			node_hash(&node, &sibling)
+		};
+	}
+	// Siblings out of order or above the root are not part of a valid proof
+	siblings.next().is_none() && node == *root
+}
+
+/// UTXO set root committed in a header digest, if any
+pub fn find_root(digest: &Digest) -> Option<H256> {
+	digest.logs().iter().find_map(|item| item.consensus_try_to::<H256>(&UTXO_ENGINE_ID))
+}
+
+/// Digest item committing to `root`
+pub fn digest_item(root: H256) -> DigestItem {
+	DigestItem::Consensus(UTXO_ENGINE_ID, root.encode())
+}
@@ -0,0 +1,288 @@
+//! Confidential amounts.
+//!
+//! A confidential output hides its value in a Pedersen commitment `C = v·H + r·G` over the
+//! ristretto group, where `r` is a secret blinding factor and `H` is a generator nobody knows
+//! the discrete log of with respect to `G`. Commitments add up like the values they hide, so
+//! a transaction balances when its input commitments minus its output commitments and its
+//! fee commit to zero value, i.e. when the blinding factors cancel out as well. Plain values
+//! take part as commitments without blinding, `v·H`.
+//!
+//! Values are taken modulo the group order, so every confidential output carries a range
+//! proof showing it commits to a value below `2^RANGE_BITS`. The proof commits to each bit of
+//! the value separately, proves with a two key ring signature that each of these commits to
+//! zero or one, and the bit commitments weighted by their powers of two have to add up to the
+//! output commitment.
+//!
+//! Proofs are deterministic: the bit blinding factors and nonces are derived from the
+//! blinding factor and the value, so proving needs no randomness and works without `std`.
+
+use curve25519_dalek::{
+	constants::RISTRETTO_BASEPOINT_TABLE,
+	ristretto::{CompressedRistretto, RistrettoPoint},
+	scalar::Scalar,
+	traits::Identity,
+};
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_std::prelude::*;
+
+use crate::{
+	ensure, fail,
+	utxo::{TransactionOutput, Value},
+};
+
+/// Number of bits of a confidential value, it is below `2^RANGE_BITS`
+pub const RANGE_BITS: usize = 64;
+
+/// Domain separator of the hash the value generator `H` is derived from
+const GENERATOR_CONTEXT: &[u8] = b"frameless-utxo-value-generator";
+
+/// Domain separator of the challenges of range proofs
+const CHALLENGE_CONTEXT: &[u8] = b"frameless-utxo-range-proof";
+
+/// Domain separator of the secrets derived while proving
+const NONCE_CONTEXT: &[u8] = b"frameless-utxo-range-proof-nonce";
+
+/// Amount of a confidential output
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
+pub struct Confidential {
+	/// Pedersen commitment to the value, a compressed ristretto point
+	pub commitment: H256,
+
+	/// Proof that `commitment` hides a value below `2^RANGE_BITS`
+	pub range_proof: RangeProof,
+}
+
+/// Range proof of a commitment, one `BitProof` per bit starting with the least significant
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
+pub struct RangeProof {
+	pub bits: Vec<BitProof>,
+}
+
+/// Ring signature proving that `commitment` hides zero or one. The ring holds the keys
+/// `commitment` and `commitment - H`, the prover knows the blinding factor of one of them.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
+pub struct BitProof {
+	/// Commitment to the bit
+	pub commitment: H256,
+
+	/// Challenge of the first key of the ring
+	pub challenge: H256,
+
+	/// Response for the key `commitment`
+	pub response_zero: H256,
+
+	/// Response for the key `commitment - H`
+	pub response_one: H256,
+}
+
+impl Confidential {
+	/// Commit to `value` with the blinding factor `blinding` and prove its range
+	pub fn new(value: u64, blinding: &Scalar) -> Self {
+		let commitment = commit(value.into(), blinding).compress();
+		let bits = bit_blindings(value, blinding)
+			.into_iter()
+			.enumerate()
+			.map(|(index, bit_blinding)| {
+				prove_bit(
+					&commitment,
+					index,
+					value >> index & 1 == 1,
+					&bit_blinding,
+					value,
+					blinding,
+				)
+			})
+			.collect();
+		Self { commitment: H256(commitment.to_bytes()), range_proof: RangeProof { bits } }
+	}
+
+	/// Check the range proof and return the commitment
+	pub fn verify(&self) -> Result<RistrettoPoint, &'static str> {
+		let commitment = point(&self.commitment)?;
+		let bits = &self.range_proof.bits;
+		ensure!(bits.len() == RANGE_BITS, "Range proof has the wrong number of bits");
+
+		let value_generator = value_generator();
+		let mut weighted_sum = RistrettoPoint::identity();
+		for (index, bit) in bits.iter().enumerate().rev() {
+			let bit_commitment = point(&bit.commitment)?;
+			let challenge = scalar(&bit.challenge)?;
+			let nonce_zero = RistrettoPoint::vartime_double_scalar_mul_basepoint(
+				&-challenge,
+				&bit_commitment,
+				&scalar(&bit.response_zero)?,
+			);
+			let challenge_one =
+				ring_challenge(&self.commitment, index, &bit.commitment, &nonce_zero);
+			let nonce_one = RistrettoPoint::vartime_double_scalar_mul_basepoint(
+				&-challenge_one,
+				&(bit_commitment - value_generator),
+				&scalar(&bit.response_one)?,
+			);
+			ensure!(
+				ring_challenge(&self.commitment, index, &bit.commitment, &nonce_one) == challenge,
+				"Invalid range proof"
+			);
+			weighted_sum = weighted_sum + weighted_sum + bit_commitment;
+		}
+		ensure!(weighted_sum == commitment, "Range proof does not match the commitment");
+		Ok(commitment)
+	}
+}
+
+/// Sum of the amounts spent by a transaction minus the amounts it creates, as a commitment
+pub struct Balance(RistrettoPoint);
+
+impl Default for Balance {
+	fn default() -> Self {
+		Balance(RistrettoPoint::identity())
+	}
+}
+
+impl Balance {
+	/// Add the amount of a spent UTXO. Its range proof was checked when it was created.
+	pub fn add_input(&mut self, utxo: &TransactionOutput) -> Result<(), &'static str> {
+		self.0 += match &utxo.confidential {
+			Some(confidential) => point(&confidential.commitment)?,
+			None => commit(utxo.value, &Scalar::zero()),
+		};
+		Ok(())
+	}
+
+	/// Subtract the amount of a created output, checking its range proof
+	pub fn add_output(&mut self, output: &TransactionOutput) -> Result<(), &'static str> {
+		self.0 -= match &output.confidential {
+			Some(confidential) => {
+				ensure!(output.is_spendable(), "Data outputs cannot be confidential");
+				ensure!(output.value == 0, "Confidential outputs must have a zero value");
+				confidential.verify()?
+			},
+			None => commit(output.value, &Scalar::zero()),
+		};
+		Ok(())
+	}
+
+	/// Check that the spent amounts pay for the created ones and `fee`, exactly
+	pub fn check(self, fee: Value) -> Result<(), &'static str> {
+		ensure!(
+			self.0 == commit(fee, &Scalar::zero()),
+			"Confidential amounts do not balance with the fee"
+		);
+		Ok(())
+	}
+}
+
+/// Generator `H` the value is committed with, hashed to a point so that its discrete log
+/// with respect to the basepoint `G` is unknown
+pub fn value_generator() -> RistrettoPoint {
+	RistrettoPoint::from_uniform_bytes(&sp_io::hashing::blake2_512(GENERATOR_CONTEXT))
+}
+
+/// Pedersen commitment `value·H + blinding·G`
+pub fn commit(value: Value, blinding: &Scalar) -> RistrettoPoint {
+	Scalar::from(value) * value_generator() + blinding * &RISTRETTO_BASEPOINT_TABLE
+}
+
+/// Decompress a commitment
+pub fn point(commitment: &H256) -> Result<RistrettoPoint, &'static str> {
+	CompressedRistretto(commitment.to_fixed_bytes())
+		.decompress()
+		.ok_or("Invalid commitment")
+}
+
+/// Decode a scalar, rejecting non-canonical encodings so proofs cannot be malleated
+fn scalar(bytes: &H256) -> Result<Scalar, &'static str> {
+	Scalar::from_canonical_bytes(bytes.to_fixed_bytes()).ok_or("Invalid scalar in range proof")
+}
+
+/// Blinding factors of the bit commitments. They are chosen so that the bit commitments
+/// weighted by their powers of two add up to the commitment with `blinding`.
+fn bit_blindings(value: u64, blinding: &Scalar) -> Vec<Scalar> {
+	let mut blindings: Vec<_> = (0..RANGE_BITS - 1)
+		.map(|index| derive_secret(value, blinding, index, 0))
+		.collect();
+	let mut remainder = *blinding;
+	let mut weight = Scalar::one();
+	for bit_blinding in blindings.iter() {
+		remainder -= weight * bit_blinding;
+		weight += weight;
+	}
+	blindings.push(remainder * weight.invert());
+	blindings
+}
+
+/// Ring signature for bit `index` of the value committed to by `commitment`.
+/// The signature for the key the prover knows is closed with the nonce, the other one is
+/// simulated by picking its response first.
+fn prove_bit(
+	commitment: &CompressedRistretto,
+	index: usize,
+	bit: bool,
+	bit_blinding: &Scalar,
+	value: u64,
+	blinding: &Scalar,
+) -> BitProof {
+	let commitment = H256(commitment.to_bytes());
+	let value_generator = value_generator();
+	let blinded = bit_blinding * &RISTRETTO_BASEPOINT_TABLE;
+	let bit_commitment_point = if bit { value_generator + blinded } else { blinded };
+	let bit_commitment = H256(bit_commitment_point.compress().to_bytes());
+	let keys = [bit_commitment_point, bit_commitment_point - value_generator];
+	let (known, simulated) = if bit { (1, 0) } else { (0, 1) };
+
+	let nonce = derive_secret(value, blinding, index, 1);
+	let simulated_response = derive_secret(value, blinding, index, 2);
+	let simulated_challenge =
+		ring_challenge(&commitment, index, &bit_commitment, &(&nonce * &RISTRETTO_BASEPOINT_TABLE));
+	let simulated_nonce = RistrettoPoint::vartime_double_scalar_mul_basepoint(
+		&-simulated_challenge,
+		&keys[simulated],
+		&simulated_response,
+	);
+	let known_challenge = ring_challenge(&commitment, index, &bit_commitment, &simulated_nonce);
+
+	let mut responses = [Scalar::zero(); 2];
+	responses[known] = nonce + known_challenge * bit_blinding;
+	responses[simulated] = simulated_response;
+	let challenge = if bit { simulated_challenge } else { known_challenge };
+	BitProof {
+		commitment: bit_commitment,
+		challenge: H256(challenge.to_bytes()),
+		response_zero: H256(responses[0].to_bytes()),
+		response_one: H256(responses[1].to_bytes()),
+	}
+}
+
+/// Challenge of the next key of the ring of bit `index`, given the nonce of the previous one
+fn ring_challenge(
+	commitment: &H256,
+	index: usize,
+	bit_commitment: &H256,
+	nonce: &RistrettoPoint,
+) -> Scalar {
+	let preimage = [
+		CHALLENGE_CONTEXT,
+		commitment.as_bytes(),
+		&[index as u8][..],
+		bit_commitment.as_bytes(),
+		&nonce.compress().to_bytes()[..],
+	]
+	.concat();
+	Scalar::from_bytes_mod_order_wide(&sp_io::hashing::blake2_512(&preimage))
+}
+
+/// Secret number `kind` of bit `index`, bound to the value so that proofs of different values
+/// with the same blinding factor never share nonces
+fn derive_secret(value: u64, blinding: &Scalar, index: usize, kind: u8) -> Scalar {
+	let preimage =
+		[NONCE_CONTEXT, blinding.as_bytes(), &value.to_le_bytes()[..], &[index as u8, kind][..]]
+			.concat();
+	Scalar::from_bytes_mod_order_wide(&sp_io::hashing::blake2_512(&preimage))
+}
@@ -3,9 +3,19 @@
 #[cfg(feature = "std")]
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
+pub mod commitment;
+pub mod confidential;
+pub mod limits;
+pub mod metadata;
+pub mod musig;
+pub mod pow;
+pub mod psbt;
+pub mod script;
 pub mod utxo;
 use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
+use sp_finality_grandpa::AuthorityId as GrandpaId;
 
 use log::info;
 
@@ -113,6 +123,60 @@ pub fn native_version() -> NativeVersion {
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct GenesisConfig {
 	pub genesis_utxos: Vec<utxo::TransactionOutput>,
+	#[cfg_attr(feature = "std", serde(default))]
+	pub limits: limits::Limits,
+	/// Extra fee a transaction has to pay to replace a conflicting one in the pool
+	#[cfg_attr(feature = "std", serde(default = "default_fee_increment"))]
+	pub fee_increment: utxo::Value,
+	/// Minimum value of a spendable output
+	#[cfg_attr(feature = "std", serde(default = "default_dust_limit"))]
+	pub dust_limit: utxo::Value,
+	/// Difficulty of the first block mined with proof of work
+	#[cfg_attr(feature = "std", serde(default = "default_difficulty"))]
+	pub difficulty: sp_core::U256,
+	/// Whether blocks are mined with proof of work, only then do they carry a coinbase
+	#[cfg_attr(feature = "std", serde(default))]
+	pub proof_of_work: bool,
+	/// Aura slot duration in milliseconds
+	#[cfg_attr(feature = "std", serde(default = "default_slot_duration"))]
+	pub slot_duration: u64,
+	/// Aura and Grandpa keys of the validators, each Grandpa key with a voting weight of 1
+	#[cfg_attr(feature = "std", serde(default = "default_authorities"))]
+	pub authorities: Vec<(AuraId, GrandpaId)>,
+}
+
+#[cfg(feature = "std")]
+fn default_fee_increment() -> utxo::Value {
+	utxo::DEFAULT_FEE_INCREMENT
+}
+
+#[cfg(feature = "std")]
+fn default_dust_limit() -> utxo::Value {
+	utxo::DEFAULT_DUST_LIMIT
+}
+
+#[cfg(feature = "std")]
+fn default_difficulty() -> sp_core::U256 {
+	pow::DEFAULT_DIFFICULTY.into()
+}
+
+#[cfg(feature = "std")]
+fn default_slot_duration() -> u64 {
+	DEFAULT_SLOT_DURATION
+}
+
+/// Alice is the only authority. This makes things work nicely in `--dev` mode
+#[cfg(feature = "std")]
+fn default_authorities() -> Vec<(AuraId, GrandpaId)> {
+	use hex_literal::hex;
+	use sp_application_crypto::ByteArray;
+
+	vec![(
+		AuraId::from_slice(&hex!("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"))
+			.unwrap(),
+		GrandpaId::from_slice(&hex!("88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee"))
+			.unwrap(),
+	)]
 }
 
 #[cfg(feature = "std")]
@@ -123,10 +187,22 @@ impl Default for GenesisConfig {
 		const ALICE_PUB_KEY_BYTES: [u8; 32] =
 			hex!("d2bf4b844dfefd6772a8843e669f943408966a977e3ae2af1dd78e0f55f4df67");
 
-		GenesisConfig { genesis_utxos: vec![utxo::TransactionOutput {
+		GenesisConfig {
+			genesis_utxos: vec![utxo::TransactionOutput {
 				value: 100,
 				pubkey: H256::from(ALICE_PUB_KEY_BYTES),
-		}]}
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}],
+			limits: Default::default(),
+			fee_increment: utxo::DEFAULT_FEE_INCREMENT,
+			dust_limit: utxo::DEFAULT_DUST_LIMIT,
+			difficulty: default_difficulty(),
+			proof_of_work: false,
+			slot_duration: DEFAULT_SLOT_DURATION,
+			authorities: default_authorities(),
+		}
 	}
 }
 
@@ -135,14 +211,53 @@ impl BuildStorage for GenesisConfig {
 	fn assimilate_storage(&self, storage: &mut Storage) -> Result<(), String> {
 		storage.top.insert(well_known_keys::CODE.into(), WASM_BINARY.unwrap().to_vec());
 
-		for utxo in &self.genesis_utxos {
-			storage.top.insert(BlakeTwo256::hash_of(&utxo).encode(), utxo.encode());
+		if self.slot_duration == 0 {
+			return Err("Slot duration must not be zero".into())
+		}
+		if self.authorities.is_empty() {
+			return Err("At least one authority is required".into())
+		}
+
+		let mut total_issuance: utxo::Value = 0;
+		for (index, utxo) in self.genesis_utxos.iter().enumerate() {
+			if !utxo.is_spendable() {
+				return Err("Genesis UTXOs cannot carry data".into())
+			}
+			if utxo.confidential.is_some() {
+				return Err("Genesis UTXOs cannot be confidential".into())
+			}
+			if utxo.value == 0 {
+				return Err("Genesis UTXOs must have a non-zero value".into())
+			}
+			total_issuance = total_issuance
+				.checked_add(utxo.value)
+				.ok_or("Total value of the genesis UTXOs overflows")?;
+			let outpoint = genesis_outpoint(utxo, index);
+			storage.top.insert(outpoint.encode(), utxo.encode());
+			commitment::insert(&mut storage.top, &outpoint, utxo);
 		}
+		storage.top.insert(utxo::TOTAL_ISSUANCE_KEY.to_vec(), total_issuance.encode());
+		storage.top.insert(limits::LIMITS_KEY.to_vec(), self.limits.encode());
+		storage.top.insert(utxo::FEE_INCREMENT_KEY.to_vec(), self.fee_increment.encode());
+		storage.top.insert(utxo::DUST_LIMIT_KEY.to_vec(), self.dust_limit.encode());
+		storage.top.insert(pow::DIFFICULTY_KEY.to_vec(), self.difficulty.encode());
+		storage.top.insert(pow::PROOF_OF_WORK_KEY.to_vec(), self.proof_of_work.encode());
+		storage.top.insert(SLOT_DURATION_KEY.to_vec(), self.slot_duration.encode());
+		let (aura_authorities, grandpa_authorities): (Vec<_>, Vec<_>) =
+			self.authorities.iter().cloned().map(|(aura, grandpa)| (aura, (grandpa, 1))).unzip();
+		storage.top.insert(AURA_AUTHORITIES_KEY.to_vec(), aura_authorities.encode());
+		storage.top.insert(GRANDPA_AUTHORITIES_KEY.to_vec(), grandpa_authorities.encode());
 
 		Ok(())
 	}
 }
 
+/// Outpoint of the genesis UTXO at `index` of `GenesisConfig::genesis_utxos`.
+/// The index keeps identical allocations apart.
+pub fn genesis_outpoint(utxo: &utxo::TransactionOutput, index: usize) -> H256 {
+	BlakeTwo256::hash_of(&(utxo, index as u64))
+}
+
 /// Block header type as expected by this runtime.
 pub type Header = generic::Header<BlockNumber, BlakeTwo256>;
 /// Block type as expected by this runtime.
@@ -150,7 +265,7 @@ pub type Block = generic::Block<Header, BasicExtrinsic>;
 
 // this extrinsic type does nothing other than fulfill the compiler.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, TypeInfo)]
 pub struct BasicExtrinsic(utxo::Transaction);
 
 impl Extrinsic for BasicExtrinsic {
@@ -164,9 +279,82 @@ impl Extrinsic for BasicExtrinsic {
 
 pub const HEADER_KEY: [u8; 6] = *b"header";
 
+/// Storage key of the encoded extrinsics applied so far in the block being built.
+/// Cleared when the block is finished.
+pub const EXTRINSICS_KEY: [u8; 10] = *b"extrinsics";
+
+/// Storage key of the Aura slot duration in milliseconds
+pub const SLOT_DURATION_KEY: [u8; 13] = *b"slot_duration";
+
+/// Slot duration used when none is set in genesis, three-second blocks
+pub const DEFAULT_SLOT_DURATION: u64 = 3000;
+
+/// Aura slot duration in milliseconds, as set in genesis
+pub fn slot_duration() -> u64 {
+	sp_io::storage::get(&SLOT_DURATION_KEY)
+		.and_then(|raw_duration| u64::decode(&mut &raw_duration[..]).ok())
+		.unwrap_or(DEFAULT_SLOT_DURATION)
+}
+
+/// Storage key of the Aura authorities
+pub const AURA_AUTHORITIES_KEY: [u8; 16] = *b"aura_authorities";
+
+/// Storage key of the Grandpa authorities with their weights
+pub const GRANDPA_AUTHORITIES_KEY: [u8; 19] = *b"grandpa_authorities";
+
+/// Aura authorities, as set in genesis
+pub fn aura_authorities() -> Vec<AuraId> {
+	sp_io::storage::get(&AURA_AUTHORITIES_KEY)
+		.and_then(|raw_authorities| Vec::<AuraId>::decode(&mut &raw_authorities[..]).ok())
+		.unwrap_or_default()
+}
+
+/// Grandpa authorities with their weights, as set in genesis
+pub fn grandpa_authorities() -> sp_finality_grandpa::AuthorityList {
+	sp_io::storage::get(&GRANDPA_AUTHORITIES_KEY)
+		.and_then(|raw_authorities| {
+			sp_finality_grandpa::AuthorityList::decode(&mut &raw_authorities[..]).ok()
+		})
+		.unwrap_or_default()
+}
+
 /// The main struct in this module. In frame this comes from `construct_runtime!`
 pub struct Runtime;
 
+impl Runtime {
+	/// Bookkeeping shared by block authoring and block import, run before any extrinsic.
+	fn note_new_block(header: &<Block as BlockT>::Header) {
+		sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &header.number.encode());
+
+		// The genesis hash is not known while building genesis storage. It becomes
+		// available as the parent hash of block 1 and signatures commit to it.
+		if header.number == 1 {
+			sp_io::storage::set(&utxo::GENESIS_HASH_KEY, &header.parent_hash.encode());
+		}
+	}
+
+	/// Remember an applied extrinsic for the extrinsics root of the block
+	fn note_extrinsic(encoded_extrinsic: Vec<u8>) {
+		sp_io::storage::append(&EXTRINSICS_KEY, encoded_extrinsic.encode());
+	}
+
+	/// Extrinsics root of the applied extrinsics. They are forgotten, so nothing ends up in
+	/// the state root.
+	fn take_extrinsics_root() -> H256 {
+		let extrinsics = sp_io::storage::get(&EXTRINSICS_KEY)
+			.and_then(|raw_extrinsics| Vec::<Vec<u8>>::decode(&mut &raw_extrinsics[..]).ok())
+			.unwrap_or_default();
+		sp_io::storage::clear(&EXTRINSICS_KEY);
+		extrinsics_root(extrinsics)
+	}
+}
+
+/// Root of the trie holding the encoded extrinsics of a block under their SCALE compact encoded
+/// index, the same as FRAME based chains. Proofs against it show that an extrinsic is in a block.
+pub fn extrinsics_root(encoded_extrinsics: Vec<Vec<u8>>) -> H256 {
+	BlakeTwo256::ordered_trie_root(encoded_extrinsics, sp_storage::StateVersion::V0)
+}
+
 impl_runtime_apis! {
 	// https://substrate.dev/rustdocs/master/sp_api/trait.Core.html
 	impl sp_api::Core<Block> for Runtime {
@@ -176,13 +364,59 @@ impl_runtime_apis! {
 
 		fn execute_block(block: Block) {
 			info!(target: "frameless", "🖼️ Entering execute_block. block: {:?}", block);
+			Self::note_new_block(&block.header);
+
+			// Failing extrinsics are not counted by `apply_extrinsic`, so bound them up front
+			let length = block.extrinsics.iter().map(Encode::encoded_size).sum();
+			if limits::check_block(block.extrinsics.len(), length, &limits::get()).is_err() {
+				panic!("Block exceeds the transaction or block limits");
+			}
+
+			let encoded_extrinsics = block.extrinsics.iter().map(Encode::encode).collect();
+			assert_eq!(
+				block.header.extrinsics_root,
+				extrinsics_root(encoded_extrinsics),
+				"Extrinsics root does not match the extrinsics of the block"
+			);
+
+			// Signatures of all extrinsics in the block are collected and verified together
+			// by the host once every extrinsic has been applied.
+			sp_io::crypto::start_batch_verify();
 
 			for extrinsic in block.extrinsics {
 				match Self::apply_extrinsic(extrinsic) {
 					Ok(_) => {},
+					Err(TransactionValidityError::Invalid(InvalidTransaction::ExhaustsResources)) =>
+						panic!("Block exceeds the transaction or block limits"),
 					Err(e) => info!(target: "frameless", "🖼️ Error executing extrinsic {:?}", e)
 				}
 			}
+			limits::clear_block_usage();
+			sp_io::storage::clear(&EXTRINSICS_KEY);
+
+			// Spends with a bad signature were applied above, the block is rejected as a whole
+			if !sp_io::crypto::finish_batch_verify() {
+				panic!(
+					"Signature verification failed in block #{} {:?}",
+					block.header.number,
+					BlakeTwo256::hash_of(&block.header)
+				);
+			}
+
+			// Value is only created by genesis and mints, and only destroyed by burns.
+			// The value of confidential UTXOs is only known in total.
+			#[cfg(feature = "try-runtime")]
+			assert_eq!(
+				utxo::utxo_set_value().and_then(|value| value.checked_add(utxo::shielded_value())),
+				Some(utxo::total_issuance()),
+				"Value of the UTXO set does not match the total issuance"
+			);
+
+			assert_eq!(
+				commitment::find_root(&block.header.digest),
+				Some(commitment::root(&commitment::RuntimeStore)),
+				"UTXO set root in the header digest does not match the UTXO set"
+			);
 
 			// Verify Stateroot
 			let mut raw_state_root = &sp_io::storage::root(sp_storage::StateVersion::default())[..];
@@ -194,6 +428,7 @@ impl_runtime_apis! {
 		fn initialize_block(header: &<Block as BlockT>::Header) {
 			info!(target: "frameless", "🖼️ Entering initialize_block.");
 			sp_io::storage::set(&HEADER_KEY, &header.encode());
+			Self::note_new_block(header);
 		}
 	}
 
@@ -202,13 +437,27 @@ impl_runtime_apis! {
 		fn apply_extrinsic(extrinsic: <Block as BlockT>::Extrinsic) -> ApplyExtrinsicResult {
 			info!(target: "frameless", "🖼️ Entering apply_extrinsic: {:?}", extrinsic);
 
+			// Only account for the extrinsic once it has been applied successfully
+			let usage = limits::usage_with(&extrinsic.0, extrinsic.encoded_size())
+				.map_err(TransactionValidityError::Invalid)?;
+			utxo::check_outputs(&extrinsic.0).map_err(TransactionValidityError::Invalid)?;
+
+			let encoded_extrinsic = extrinsic.encode();
 			let transaction = extrinsic.0;
-			// Call spend
-			match utxo::spend(transaction) {
+			let result = if pow::is_coinbase(&transaction) {
+				pow::apply_coinbase(&transaction)
+			} else {
+				utxo::spend(transaction)
+			};
+			match result {
+				Err(sp_runtime::DispatchError::Other(utxo::INVALID_SIGNATURE)) =>
+					Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof)),
 				Err(_) => {
 					Err(TransactionValidityError::Invalid(InvalidTransaction::Custom(1)))
 				},
 				Ok(_) => {
+					limits::set_block_usage(usage);
+					Self::note_extrinsic(encoded_extrinsic);
 					Ok(Ok(()))
 				}
 			}
@@ -221,10 +470,14 @@ impl_runtime_apis! {
 			let raw_header = sp_io::storage::get(&HEADER_KEY)
 				.expect("We initialized with header, it never got mutated, qed");
 			sp_io::storage::clear(&HEADER_KEY);
+			limits::clear_block_usage();
 
 			// Create new header and add new state_root
 			let mut header = <Block as BlockT>::Header::decode(&mut &*raw_header)
 				.expect("we put a valid header in in the first place, qed");
+			header.extrinsics_root = Self::take_extrinsics_root();
+			// Commit to the UTXO set so light clients can check UTXOs against the header
+			header.digest.push(commitment::digest_item(commitment::root(&commitment::RuntimeStore)));
 			let raw_state_root = &sp_io::storage::root(sp_storage::StateVersion::default())[..];
 
 			header.state_root = sp_core::H256::decode(&mut &raw_state_root[..]).unwrap();
@@ -232,19 +485,18 @@ impl_runtime_apis! {
 			header
 		}
 
-		// This runtime does not expect any inherents so it does not insert any into blocks it builds.
-		fn inherent_extrinsics(_data: sp_inherents::InherentData) -> Vec<<Block as BlockT>::Extrinsic> {
+		// The only inherent is the coinbase of mined blocks, created when the node provides a miner key.
+		fn inherent_extrinsics(data: sp_inherents::InherentData) -> Vec<<Block as BlockT>::Extrinsic> {
 			info!(target: "frameless", "🖼️ Entering inherent_extrinsics.");
-			Vec::new()
+			pow::create_coinbase(&data).map(BasicExtrinsic).into_iter().collect()
 		}
 
-		// This runtime does not expect any inherents, so it does not do any inherent checking.
 		fn check_inherents(
 			block: Block,
-			_data: sp_inherents::InherentData
+			data: sp_inherents::InherentData
 		) -> sp_inherents::CheckInherentsResult {
 			info!(target: "frameless", "🖼️ Entering check_inherents. block: {:?}", block);
-			sp_inherents::CheckInherentsResult::default()
+			pow::check_coinbase(&block, &data)
 		}
 	}
 
@@ -256,12 +508,24 @@ impl_runtime_apis! {
 		) -> TransactionValidity {
 			info!(target: "frameless", "🖼️ Entering validate_transaction. source: {:?}, tx: {:?}, block hash: {:?}", source, tx, block_hash);
 
+			// Without a stored genesis hash the transaction is validated on top of the genesis
+			// block itself. This write only lives in the validation overlay.
+			if !sp_io::storage::exists(&utxo::GENESIS_HASH_KEY) {
+				sp_io::storage::set(&utxo::GENESIS_HASH_KEY, &block_hash.encode());
+			}
+			// Validate as if the transaction went into the next block.
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &(utxo::block_number() + 1).encode());
+
 			let call = tx.0;
+			limits::check_transaction(&call, &limits::get())
+				.map_err(TransactionValidityError::Invalid)?;
+			// Report transactions outside of their validity window as `Future` or `Stale`
+			utxo::check_validity_window(&call).map_err(TransactionValidityError::Invalid)?;
+			utxo::check_outputs(&call).map_err(TransactionValidityError::Invalid)?;
 			match utxo::validate_transaction(&call) {
-				Ok(mut valid) => {
-					valid.provides = vec![call.encode()];
-					Ok(valid)
-				},
+				Ok(valid) => Ok(valid),
+				Err(utxo::INVALID_SIGNATURE) =>
+					Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof)),
 				Err(_) => {
 					Err(TransactionValidityError::Invalid(InvalidTransaction::Custom(1)))
 				},
@@ -269,14 +533,32 @@ impl_runtime_apis! {
 		}
 	}
 
-	// Ignore everything after this.
-
 	impl sp_api::Metadata<Block> for Runtime {
 		fn metadata() -> OpaqueMetadata {
-			OpaqueMetadata::new(vec![0])
+			OpaqueMetadata::new(metadata::encoded_metadata())
+		}
+	}
+
+	impl commitment::UtxoCommitmentApi<Block> for Runtime {
+		fn utxo_root() -> H256 {
+			commitment::root(&commitment::RuntimeStore)
+		}
+
+		fn prove_outpoint(outpoint: H256) -> commitment::UtxoProof {
+			let utxo = sp_io::storage::get(&outpoint.encode())
+				.and_then(|raw_utxo| utxo::TransactionOutput::decode(&mut &raw_utxo[..]).ok());
+			commitment::prove(&commitment::RuntimeStore, &outpoint, utxo)
+		}
+	}
+
+	impl sp_consensus_pow::DifficultyApi<Block, sp_core::U256> for Runtime {
+		fn difficulty() -> sp_core::U256 {
+			pow::difficulty()
 		}
 	}
 
+	// Ignore everything after this.
+
 	impl sp_offchain::OffchainWorkerApi<Block> for Runtime {
 		fn offchain_worker(_header: &<Block as BlockT>::Header) {
 			// we do not do anything.
@@ -299,29 +581,17 @@ impl_runtime_apis! {
 	// Here is the Aura API for the sake of making this runtime work with the node template node
 	impl sp_consensus_aura::AuraApi<Block, AuraId> for Runtime {
 		fn slot_duration() -> sp_consensus_aura::SlotDuration {
-			// Three-second blocks
-			sp_consensus_aura::SlotDuration::from_millis(3000)
+			sp_consensus_aura::SlotDuration::from_millis(slot_duration())
 		}
 
 		fn authorities() -> Vec<AuraId> {
-			// The only authority is Alice. This makes things work nicely in `--dev` mode
-			use sp_application_crypto::ByteArray;
-
-			vec![
-				AuraId::from_slice(&hex_literal::hex!("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d").to_vec()).unwrap()
-			]
+			aura_authorities()
 		}
 	}
 
 	impl sp_finality_grandpa::GrandpaApi<Block> for Runtime {
 		fn grandpa_authorities() -> sp_finality_grandpa::AuthorityList {
-			use sp_application_crypto::ByteArray;
-			vec![
-				(
-					sp_finality_grandpa::AuthorityId::from_slice(&hex_literal::hex!("88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee").to_vec()).unwrap(),
-					1
-				)
-			]
+			grandpa_authorities()
 		}
 
 		fn current_set_id() -> sp_finality_grandpa::SetId {
@@ -352,7 +622,11 @@ impl_runtime_apis! {
 mod tests {
 	use super::*;
 
-	use sp_core::{H512, testing::SR25519};
+	use sp_core::{
+		H512,
+		testing::{SR25519, TaskExecutor},
+		traits::TaskExecutorExt,
+	};
 	use sp_keystore::testing::KeyStore;
 	use sp_keystore::{KeystoreExt, SyncCryptoStore};
 	use hex_literal::hex;
@@ -361,8 +635,46 @@ mod tests {
 
 	const ALICE_PHRASE: &str = "news slush supreme milk chapter athlete soap sausage put clutch what kitten";
 	// other random account generated with subkey
-	const GENESIS_UTXO: [u8; 32] = hex!("79eabcbd5ef6e958c6a7851b36da07691c19bda1835a08f875aa286911800999");
-	const GENESIS_UTXO_BIG: [u8; 32] = hex!("6540745c3083cdedfa78e14efb46b8fab9ab89c6772f49da16b636aaf628d65a");
+	const GENESIS_UTXO: [u8; 32] = hex!("7e2b6f3251c9a467e0c5ec23a167a439d52d7c5e534d0b9ff7d63f7d4f42e253");
+	const GENESIS_UTXO_BIG: [u8; 32] = hex!("e6356e3e15a7882448912ef8f388e5fc102511e54dd757b9b284a08c84fcfc79");
+	// Stand-in for the hash of the genesis block, which is only known once the chain is running
+	const TEST_GENESIS_HASH: [u8; 32] = [7u8; 32];
+
+	// Key of the third genesis UTXO, worth 50 and owned by Alice.
+	fn genesis_utxo_small(alice_pub_key: sp_core::sr25519::Public) -> H256 {
+		genesis_outpoint(
+			&utxo::TransactionOutput {
+				value: 50,
+				pubkey: H256::from(alice_pub_key),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			},
+			1,
+		)
+	}
+
+	// Sign every input of the transaction according to its own sighash.
+	fn sign_inputs(transaction: &mut utxo::Transaction, pubkey: &sp_core::sr25519::Public) {
+		for index in 0..transaction.inputs.len() {
+			transaction.inputs[index].sigscript = sign_input(transaction, index, pubkey);
+		}
+	}
+
+	fn sign_input(
+		transaction: &utxo::Transaction,
+		index: usize,
+		pubkey: &sp_core::sr25519::Public,
+	) -> H512 {
+		let payload = utxo::get_signing_payload(
+			transaction,
+			index,
+			&H256::from(TEST_GENESIS_HASH),
+			VERSION.transaction_version,
+		)
+		.unwrap();
+		H512::from(sp_io::crypto::sr25519_sign(SR25519, pubkey, &payload).unwrap())
+	}
 
 	// This function basically just builds a genesis storage key/value store according to our desired mockup.
 	// We start each test by giving Alice 100 utxo to start with.
@@ -380,21 +692,46 @@ mod tests {
 			&super::GenesisConfig {
 				genesis_utxos: vec![
 					utxo::TransactionOutput {
-						value: utxo::Value::max_value(),
+						value: 100,
 						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					},
 					utxo::TransactionOutput {
-						value: 100,
-						pubkey: H256::from(alice_pub_key)
+						value: 50,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					},
 				],
+				limits: Default::default(),
+				fee_increment: 10,
+				dust_limit: 2,
+				difficulty: 1000.into(),
+				proof_of_work: true,
+				slot_duration: 2000,
+				authorities: default_authorities(),
 			},
 			&mut t
 		)
 		.expect("UTXO Pallet storage can be assimilated");
+		// Worth more than any valid total issuance, only used to test overflow checks
+		let big_utxo = utxo::TransactionOutput {
+			value: utxo::Value::max_value(),
+			pubkey: H256::from(alice_pub_key),
+			lock: utxo::Lock::Pubkey,
+			data: None,
+			confidential: None,
+		};
+		t.top.insert(BlakeTwo256::hash_of(&big_utxo).encode(), big_utxo.encode());
+		t.top.insert(utxo::GENESIS_HASH_KEY.to_vec(), H256::from(TEST_GENESIS_HASH).encode());
 
 		let mut ext = sp_io::TestExternalities::from(t);
 		ext.register_extension(KeystoreExt(Arc::new(keystore)));
+		// Needed by the host to run batched signature verification
+		ext.register_extension(TaskExecutorExt::new(TaskExecutor::new()));
 		ext
 	}
 
@@ -407,6 +744,9 @@ mod tests {
 			let utxo_output = utxo::TransactionOutput {
 				value: 100,
 				pubkey: H256::from(alice_pub_key),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
 			};
 
 			let val_retrieved = sp_io::storage::get(&GENESIS_UTXO).unwrap();
@@ -429,21 +769,24 @@ mod tests {
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
 						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					},
 				],
 				outputs: vec![
 					utxo::TransactionOutput {
 						value: 25,
-						pubkey: H256::from(alice_pub_key)
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					}
 				],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-
-			transaction.inputs[0].sigscript = H512::from(signature);
+			sign_inputs(&mut transaction, &alice_pub_key);
 
 			let stripped_transaction_bytes = utxo::get_stripped_transaction(&mut transaction);
 			let new_utxo_hash_key =
@@ -470,17 +813,21 @@ mod tests {
 				inputs: vec![ utxo::TransactionInput {
 					outpoint: H256::zero(),
 					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
 				}],
 				outputs: vec![ utxo::TransactionOutput {
 					value: 100,
-					pubkey: H256::from(alice_pub_key)
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
 				}],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-			transaction.inputs[0].sigscript = H512::from(signature);
+			sign_inputs(&mut transaction, &alice_pub_key);
 			let spend_result = utxo::spend(transaction).err().unwrap();
 			assert_eq!(
 				spend_result,
@@ -501,26 +848,31 @@ mod tests {
 				inputs: vec![
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
-						sigscript: H512::zero()
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					},
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
-						sigscript: H512::zero()
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					}
 				],
 				outputs: vec![
 					utxo::TransactionOutput {
 						value: 25,
 						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 				}],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-			for input in transaction.inputs.iter_mut() {
-				input.sigscript = H512::from(signature.clone());
-			}
+			sign_inputs(&mut transaction, &alice_pub_key);
 			let spend_result = utxo::spend(transaction).err().unwrap();
 			assert_eq!(spend_result,
 				sp_runtime::DispatchError::Other("Inputs not unique")
@@ -540,18 +892,22 @@ mod tests {
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
 						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 				}],
 				outputs: vec![
 					utxo::TransactionOutput {
 						value: 0,
-						pubkey: H256::from(alice_pub_key)
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					}],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-			transaction.inputs[0].sigscript = H512::from(signature);
+			sign_inputs(&mut transaction, &alice_pub_key);
 			let spend_result = utxo::spend(transaction).err().unwrap();
 			assert_eq!(
 				spend_result,
@@ -572,22 +928,29 @@ mod tests {
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
 						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					}],
 				outputs: vec![
 					utxo::TransactionOutput {
 						value: 25,
 						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					},
 					utxo::TransactionOutput {
 						value: 76,
 						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					}],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-			transaction.inputs[0].sigscript = H512::from(signature);
+			sign_inputs(&mut transaction, &alice_pub_key);
 			let spend_result = utxo::spend(transaction).err().unwrap();
 			assert_eq!(
 				spend_result,
@@ -608,22 +971,29 @@ mod tests {
 						utxo::TransactionInput {
 							outpoint: H256::from(GENESIS_UTXO),
 							sigscript: H512::zero(),
+							sighash: utxo::SigHash::All,
+							pubkey: None,
+							redeemer: None,
 						}],
 					outputs: vec![
 						utxo::TransactionOutput {
 							value: 2 as utxo::Value,
 							pubkey: H256::from(alice_pub_key),
+							lock: utxo::Lock::Pubkey,
+							data: None,
+							confidential: None,
 						},
 						utxo::TransactionOutput {
 							value: utxo::Value::max_value(),
 							pubkey: H256::from(alice_pub_key),
+							lock: utxo::Lock::Pubkey,
+							data: None,
+							confidential: None,
 						}],
+					..Default::default()
 				};
 
-				let signature =
-					sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-					.unwrap();
-				transaction.inputs[0].sigscript = H512::from(signature);
+				sign_inputs(&mut transaction, &alice_pub_key);
 				let spend_result = utxo::spend(transaction).err().unwrap();
 				assert_eq!(
 					spend_result,
@@ -644,26 +1014,30 @@ mod tests {
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO_BIG),
 						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					},
 					utxo::TransactionInput {
 						outpoint: H256::from(GENESIS_UTXO),
 						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
 					}],
 				outputs: vec![
 					utxo::TransactionOutput {
 						value: 50,
 						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
 					}
 				],
+				..Default::default()
 			};
 
-			let signature =
-				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode())
-				.unwrap();
-
-			for input in transaction.inputs.iter_mut() {
-				input.sigscript = H512::from(signature.clone());
-			}
+			sign_inputs(&mut transaction, &alice_pub_key);
 
 			let spend_result = utxo::spend(transaction).err().unwrap();
 			assert_eq!(
@@ -730,6 +1104,7 @@ mod tests {
         let tx = utxo::Transaction {
             inputs,
             outputs,
+            ..Default::default()
         };
         let res = utxo::validate_transaction(&tx).err().unwrap();
         assert_eq!(res, "Outputs not unique");
@@ -753,9 +1128,2617 @@ mod tests {
         let tx = utxo::Transaction {
             inputs,
             outputs,
+            ..Default::default()
         };
         let res = utxo::validate_transaction(&tx).err().unwrap();
         assert_eq!(res, "Inputs not unique");
     }
 
+	#[test]
+	fn utxo_frameless_sighash_all_payload_commits_to_chain_and_stripped_transaction() {
+		let transaction = utxo::Transaction {
+			inputs: vec![utxo::TransactionInput {
+				outpoint: H256::from(GENESIS_UTXO),
+				sigscript: H512::repeat_byte(1),
+				sighash: utxo::SigHash::All,
+				pubkey: None,
+				redeemer: None,
+			}],
+			outputs: vec![utxo::TransactionOutput {
+				value: 25,
+				pubkey: H256::zero(),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}],
+			..Default::default()
+		};
+
+		let genesis_hash = H256::from(TEST_GENESIS_HASH);
+		let mut expected =
+			(utxo::SIGNING_CONTEXT, &genesis_hash, VERSION.transaction_version).encode();
+		expected.extend(utxo::get_stripped_transaction(&transaction));
+
+		assert_eq!(
+			utxo::get_signing_payload(&transaction, 0, &genesis_hash, VERSION.transaction_version),
+			Ok(expected)
+		);
+		assert_eq!(
+			utxo::get_signing_payload(&transaction, 1, &genesis_hash, VERSION.transaction_version),
+			Err("Input index out of range")
+		);
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_all_commits_to_outputs() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			transaction.outputs[0].value = 30;
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_none_allows_changing_outputs() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::None,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			transaction.outputs = vec![
+				utxo::TransactionOutput {
+					value: 40,
+					pubkey: H256::repeat_byte(2),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				},
+				utxo::TransactionOutput {
+					value: 60,
+					pubkey: H256::repeat_byte(3),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				},
+			];
+			assert_ok!(utxo::spend(transaction));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_single_commits_to_matching_output() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::Single,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			// Outputs other than the matching one are not covered
+			transaction.outputs.push(utxo::TransactionOutput {
+				value: 75,
+				pubkey: H256::repeat_byte(2),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			});
+			assert_ok!(utxo::validate_transaction(&transaction));
+
+			transaction.outputs[0].value = 24;
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_single_without_matching_output_fails() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![
+					utxo::TransactionInput {
+						outpoint: H256::from(GENESIS_UTXO),
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
+					},
+					utxo::TransactionInput {
+						outpoint: genesis_utxo_small(alice_pub_key),
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::Single,
+						pubkey: None,
+						redeemer: None,
+					},
+				],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			transaction.inputs[0].sigscript = sign_input(&transaction, 0, &alice_pub_key);
+
+			assert_eq!(
+				utxo::get_signing_payload(
+					&transaction,
+					1,
+					&H256::from(TEST_GENESIS_HASH),
+					VERSION.transaction_version
+				),
+				Err("SigHash Single without a matching output")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("SigHash Single without a matching output")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_all_anyone_can_pay_allows_adding_inputs() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::AllAnyoneCanPay,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 150,
+					pubkey: H256::repeat_byte(2),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Total outputs cannot exceed total inputs")
+			);
+
+			// A second contributor adds an input without invalidating the first signature
+			transaction.inputs.push(utxo::TransactionInput {
+				outpoint: genesis_utxo_small(alice_pub_key),
+				sigscript: H512::zero(),
+				sighash: utxo::SigHash::All,
+				pubkey: None,
+				redeemer: None,
+			});
+			transaction.inputs[1].sigscript = sign_input(&transaction, 1, &alice_pub_key);
+			assert_ok!(utxo::spend(transaction.clone()));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_all_adding_inputs_fails() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 150,
+					pubkey: H256::repeat_byte(2),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			transaction.inputs.push(utxo::TransactionInput {
+				outpoint: genesis_utxo_small(alice_pub_key),
+				sigscript: H512::zero(),
+				sighash: utxo::SigHash::All,
+				pubkey: None,
+				redeemer: None,
+			});
+			transaction.inputs[1].sigscript = sign_input(&transaction, 1, &alice_pub_key);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_none_anyone_can_pay_signs_only_own_input() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::NoneAnyoneCanPay,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 100,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			// Both inputs and outputs can be changed by whoever completes the transaction
+			transaction.inputs.insert(0, utxo::TransactionInput {
+				outpoint: genesis_utxo_small(alice_pub_key),
+				sigscript: H512::zero(),
+				sighash: utxo::SigHash::All,
+				pubkey: None,
+				redeemer: None,
+			});
+			transaction.outputs = vec![utxo::TransactionOutput {
+				value: 140,
+				pubkey: H256::repeat_byte(2),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}];
+			transaction.inputs[0].sigscript = sign_input(&transaction, 0, &alice_pub_key);
+			assert_ok!(utxo::spend(transaction));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_sighash_single_anyone_can_pay_commits_to_input_and_output() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::SingleAnyoneCanPay,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			transaction.inputs.push(utxo::TransactionInput {
+				outpoint: genesis_utxo_small(alice_pub_key),
+				sigscript: H512::zero(),
+				sighash: utxo::SigHash::SingleAnyoneCanPay,
+				pubkey: None,
+				redeemer: None,
+			});
+			transaction.outputs.push(utxo::TransactionOutput {
+				value: 50,
+				pubkey: H256::repeat_byte(2),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			});
+			transaction.inputs[1].sigscript = sign_input(&transaction, 1, &alice_pub_key);
+			assert_ok!(utxo::validate_transaction(&transaction));
+
+			// Moving the first input away from its output breaks its signature
+			transaction.inputs.swap(0, 1);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_psbt_merge_and_finalize() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			let small_utxo =
+				utxo::TransactionOutput {
+					value: 50,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				};
+
+			let transaction = utxo::Transaction {
+				inputs: vec![
+					utxo::TransactionInput {
+						outpoint: H256::from(GENESIS_UTXO),
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
+					},
+					utxo::TransactionInput {
+						outpoint: genesis_utxo_small(alice_pub_key),
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey: None,
+						redeemer: None,
+					},
+				],
+				outputs: vec![utxo::TransactionOutput {
+					value: 140,
+					pubkey: H256::repeat_byte(2),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			let mut first = psbt::PartiallySignedTransaction::new(
+				transaction,
+				vec![
+					utxo::TransactionOutput {
+						value: 100,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+					small_utxo,
+				],
+				H256::from(TEST_GENESIS_HASH),
+			)
+			.unwrap();
+			assert_eq!(first.fee(), Some(10));
+
+			// Each copy collects the signature of one input
+			let mut second = first.clone();
+			let payload = first.signing_payload(0).unwrap();
+			let signature =
+				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &payload).unwrap();
+			first.add_signature(0, H512::from(signature)).unwrap();
+			let payload = second.signing_payload(1).unwrap();
+			let signature =
+				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &payload).unwrap();
+			second.add_signature(1, H512::from(signature)).unwrap();
+			assert!(!first.is_complete());
+			assert_eq!(first.clone().finalize(), Err("Missing signature for an input"));
+
+			first.merge(second).unwrap();
+			assert!(first.is_complete());
+			assert_ok!(utxo::spend(first.finalize().unwrap()));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_psbt_merge_different_transactions_fails() {
+		let transaction = utxo::Transaction {
+			inputs: vec![utxo::TransactionInput {
+				outpoint: H256::from(GENESIS_UTXO),
+				..Default::default()
+			}],
+			outputs: vec![utxo::TransactionOutput {
+				value: 25,
+				pubkey: H256::zero(),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}],
+			..Default::default()
+		};
+		let prevouts = vec![utxo::TransactionOutput {
+			value: 100,
+			pubkey: H256::zero(),
+			lock: utxo::Lock::Pubkey,
+			data: None,
+			confidential: None,
+		}];
+		let genesis_hash = H256::from(TEST_GENESIS_HASH);
+		let mut first = psbt::PartiallySignedTransaction::new(
+			transaction.clone(),
+			prevouts.clone(),
+			genesis_hash,
+		)
+		.unwrap();
+
+		let mut other_transaction = transaction.clone();
+		other_transaction.outputs[0].value = 26;
+		let second =
+			psbt::PartiallySignedTransaction::new(other_transaction, prevouts.clone(), genesis_hash)
+				.unwrap();
+		assert_eq!(first.merge(second), Err("Cannot merge signatures of different transactions"));
+
+		let other_chain =
+			psbt::PartiallySignedTransaction::new(transaction.clone(), prevouts, H256::zero())
+				.unwrap();
+		assert_eq!(first.merge(other_chain), Err("Cannot merge signatures for different chains"));
+
+		assert_eq!(
+			psbt::PartiallySignedTransaction::new(transaction, vec![], genesis_hash),
+			Err("Exactly one previous output is required per input")
+		);
+
+		// A copy that lost an input, e.g. edited by hand, can neither be merged nor finalized
+		let mut truncated = first.clone();
+		truncated.inputs.pop();
+		assert_eq!(
+			first.clone().merge(truncated.clone()),
+			Err("Exactly one previous output is required per input")
+		);
+		assert_eq!(
+			truncated.clone().merge(first.clone()),
+			Err("Exactly one previous output is required per input")
+		);
+		assert_eq!(truncated.finalize(), Err("Exactly one previous output is required per input"));
+	}
+
+	#[test]
+	fn utxo_frameless_signature_for_other_chain_fails() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+
+			let payload = utxo::get_signing_payload(
+				&transaction,
+				0,
+				&H256::repeat_byte(9),
+				VERSION.transaction_version,
+			)
+			.unwrap();
+			let signature =
+				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &payload).unwrap();
+			transaction.inputs[0].sigscript = H512::from(signature);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+
+			// The bare stripped transaction is not a valid payload either
+			let signature = sp_io::crypto::sr25519_sign(
+				SR25519,
+				&alice_pub_key,
+				&utxo::get_stripped_transaction(&transaction),
+			)
+			.unwrap();
+			transaction.inputs[0].sigscript = H512::from(signature);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_signature_for_other_transaction_version_fails() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+
+			let payload = utxo::get_signing_payload(
+				&transaction,
+				0,
+				&H256::from(TEST_GENESIS_HASH),
+				VERSION.transaction_version + 1,
+			)
+			.unwrap();
+			let signature =
+				sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &payload).unwrap();
+			transaction.inputs[0].sigscript = H512::from(signature);
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_validate_without_genesis_hash_fails() {
+		sp_io::TestExternalities::default().execute_with(|| {
+			let transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::zero(),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			assert_eq!(utxo::validate_transaction(&transaction), Err("Genesis hash is not known"));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_batch_verification_defers_signature_checks() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			sp_io::crypto::start_batch_verify();
+			assert_ok!(utxo::validate_transaction(&transaction));
+			assert!(sp_io::crypto::finish_batch_verify());
+
+			// A bad signature passes validation inside the batch but fails the batch itself
+			transaction.outputs[0].value = 30;
+			sp_io::crypto::start_batch_verify();
+			assert_ok!(utxo::validate_transaction(&transaction));
+			assert!(!sp_io::crypto::finish_batch_verify());
+
+			// Outside of a batch it is reported right away
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	#[should_panic(expected = "Signature verification failed in block #2")]
+	fn utxo_frameless_imported_block_with_bad_signature_panics() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			transaction.outputs[0].value = 30;
+			let extrinsic = BasicExtrinsic(transaction);
+
+			// Block authoring checks the signature right away and leaves the spend out
+			assert_eq!(
+				Runtime::apply_extrinsic(extrinsic.clone()),
+				Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof))
+			);
+			assert!(sp_io::storage::exists(&H256::from(GENESIS_UTXO).encode()));
+
+			// Block import only finds out once the whole block is applied
+			Runtime::execute_block(Block {
+				header: Header {
+					parent_hash: H256::from(TEST_GENESIS_HASH),
+					number: 2,
+					state_root: H256::zero(),
+					extrinsics_root: extrinsics_root(vec![extrinsic.encode()]),
+					digest: Default::default(),
+				},
+				extrinsics: vec![extrinsic],
+			});
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_transaction_limits() {
+		let transaction = utxo::Transaction {
+			inputs: vec![
+				utxo::TransactionInput { outpoint: H256::repeat_byte(1), ..Default::default() },
+				utxo::TransactionInput { outpoint: H256::repeat_byte(2), ..Default::default() },
+			],
+			outputs: vec![utxo::TransactionOutput {
+				value: 25,
+				pubkey: H256::zero(),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}],
+			..Default::default()
+		};
+		let limits = limits::Limits::default();
+		assert_eq!(limits::check_transaction(&transaction, &limits), Ok(()));
+		// Two spent and one created UTXO update the commitment three times
+		assert_eq!(
+			limits::transaction_weight(&transaction),
+			limits::BASE_TRANSACTION_WEIGHT +
+				2 * limits::INPUT_WEIGHT +
+				limits::OUTPUT_WEIGHT +
+				3 * limits::COMMITMENT_UPDATE_WEIGHT
+		);
+
+		let few_inputs = limits::Limits { max_inputs: 1, ..Default::default() };
+		assert_eq!(
+			limits::check_transaction(&transaction, &few_inputs),
+			Err(InvalidTransaction::ExhaustsResources)
+		);
+		let no_outputs = limits::Limits { max_outputs: 0, ..Default::default() };
+		assert_eq!(
+			limits::check_transaction(&transaction, &no_outputs),
+			Err(InvalidTransaction::ExhaustsResources)
+		);
+		let small = limits::Limits {
+			max_transaction_size: transaction.encoded_size() as u32 - 1,
+			..Default::default()
+		};
+		assert_eq!(
+			limits::check_transaction(&transaction, &small),
+			Err(InvalidTransaction::ExhaustsResources)
+		);
+		let light = limits::Limits {
+			max_block_weight: limits::transaction_weight(&transaction) - 1,
+			..Default::default()
+		};
+		assert_eq!(
+			limits::check_transaction(&transaction, &light),
+			Err(InvalidTransaction::ExhaustsResources)
+		);
+	}
+
+	#[test]
+	fn utxo_frameless_apply_extrinsic_reports_full_block() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut first = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut first, &alice_pub_key);
+			let mut second = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: genesis_utxo_small(alice_pub_key),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut second, &alice_pub_key);
+
+			// Room for exactly one of the two transactions
+			let limits = limits::Limits {
+				max_block_weight: limits::transaction_weight(&first) + 1,
+				..Default::default()
+			};
+			sp_io::storage::set(&limits::LIMITS_KEY, &limits.encode());
+
+			assert_eq!(Runtime::apply_extrinsic(BasicExtrinsic(first)), Ok(Ok(())));
+			assert_eq!(
+				Runtime::apply_extrinsic(BasicExtrinsic(second.clone())),
+				Err(TransactionValidityError::Invalid(InvalidTransaction::ExhaustsResources))
+			);
+			// The rejected transaction was not applied
+			assert!(sp_io::storage::exists(&genesis_utxo_small(alice_pub_key).encode()));
+
+			// A new block has room again
+			limits::clear_block_usage();
+			assert_eq!(Runtime::apply_extrinsic(BasicExtrinsic(second)), Ok(Ok(())));
+		})
+	}
+
+	#[test]
+	#[should_panic(expected = "Block exceeds the transaction or block limits")]
+	fn utxo_frameless_execute_block_bounds_failing_extrinsics() {
+		new_test_ext().execute_with(|| {
+			// Spends of an unknown UTXO fail and are never counted by `apply_extrinsic`
+			let failing: Vec<_> = (0u8..3)
+				.map(|index| {
+					BasicExtrinsic(utxo::Transaction {
+						inputs: vec![utxo::TransactionInput {
+							outpoint: H256::repeat_byte(index),
+							..Default::default()
+						}],
+						outputs: vec![utxo::TransactionOutput {
+							value: 10,
+							pubkey: H256::repeat_byte(2),
+							lock: utxo::Lock::Pubkey,
+							data: None,
+							confidential: None,
+						}],
+						..Default::default()
+					})
+				})
+				.collect();
+			for extrinsic in failing.iter() {
+				assert!(Runtime::apply_extrinsic(extrinsic.clone()).is_err());
+			}
+			assert_eq!(limits::block_usage(), Default::default());
+
+			let limits = limits::Limits {
+				max_block_weight: 2 * limits::BASE_TRANSACTION_WEIGHT,
+				..Default::default()
+			};
+			sp_io::storage::set(&limits::LIMITS_KEY, &limits.encode());
+			assert_ok!(limits::check_block(2, 0, &limits));
+
+			Runtime::execute_block(Block {
+				header: Header {
+					parent_hash: H256::zero(),
+					number: 1,
+					state_root: H256::zero(),
+					extrinsics_root: extrinsics_root(failing.iter().map(Encode::encode).collect()),
+					digest: Default::default(),
+				},
+				extrinsics: failing,
+			});
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_metadata_describes_runtime() {
+		let encoded = metadata::encoded_metadata();
+		assert_eq!(&encoded[..4], &metadata::META_RESERVED.encode()[..]);
+		assert_eq!(encoded[4], metadata::FRAMELESS_METADATA_VERSION);
+
+		let runtime_metadata = metadata::runtime_metadata();
+		let type_name = |symbol: &metadata::TypeRef| {
+			runtime_metadata.types.resolve(symbol.id()).unwrap().path().ident().unwrap()
+		};
+		assert_eq!(type_name(&runtime_metadata.block.extrinsic), "BasicExtrinsic");
+		assert_eq!(type_name(&runtime_metadata.block.transaction), "Transaction");
+		assert_eq!(type_name(&runtime_metadata.block.header), "Header");
+
+		let utxos = &runtime_metadata.storage[0];
+		assert_eq!(utxos.name, "Utxos");
+		assert_eq!(type_name(&utxos.value), "TransactionOutput");
+		let shielded = runtime_metadata
+			.storage
+			.iter()
+			.find(|entry| entry.name == "ShieldedValue")
+			.unwrap();
+		assert_eq!(shielded.key, utxo::SHIELDED_VALUE_KEY.to_vec());
+
+		// Every implemented runtime API is named
+		assert_eq!(runtime_metadata.apis.len(), RUNTIME_API_VERSIONS.len());
+	}
+
+	#[test]
+	fn utxo_frameless_validity_window() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				valid_from: Some(5),
+				valid_until: Some(10),
+				fee: None,
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &4u32.encode());
+			assert_eq!(utxo::check_validity_window(&transaction), Err(InvalidTransaction::Future));
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Transaction is not valid yet")
+			);
+
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &5u32.encode());
+			assert_eq!(utxo::check_validity_window(&transaction), Ok(6));
+			assert_eq!(utxo::validate_transaction(&transaction).unwrap().longevity, 6);
+
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &10u32.encode());
+			assert_eq!(utxo::validate_transaction(&transaction).unwrap().longevity, 1);
+
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &11u32.encode());
+			assert_eq!(utxo::check_validity_window(&transaction), Err(InvalidTransaction::Stale));
+			assert_eq!(
+				utxo::spend(transaction).err().unwrap(),
+				sp_runtime::DispatchError::Other("Transaction has expired")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_window_is_signed() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 25,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				valid_from: None,
+				valid_until: Some(10),
+				fee: None,
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_ok!(utxo::validate_transaction(&transaction));
+
+			// Extending the window invalidates the signature
+			transaction.valid_until = None;
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_dust_outputs_fail() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![
+					utxo::TransactionOutput {
+						value: 98,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+					utxo::TransactionOutput {
+						value: 1,
+						pubkey: H256::repeat_byte(2),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+				],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			assert_eq!(utxo::dust_limit(), 2);
+			assert_eq!(
+				utxo::check_outputs(&transaction),
+				Err(InvalidTransaction::Custom(utxo::DUST_OUTPUT_ERROR))
+			);
+			assert_eq!(
+				utxo::spend(transaction).err().unwrap(),
+				sp_runtime::DispatchError::Other("Output value is below the dust limit")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_data_outputs_are_not_stored() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			// Data outputs are exempt from the dust limit, their value is burned
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![
+					utxo::TransactionOutput {
+						value: 90,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+					utxo::TransactionOutput {
+						value: 1,
+						pubkey: H256::zero(),
+						lock: utxo::Lock::Pubkey,
+						data: Some(b"hello".to_vec()),
+						confidential: None,
+					},
+				],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_eq!(utxo::check_outputs(&transaction), Ok(()));
+			// Only the 9 units not burned are paid as fee
+			assert_eq!(utxo::validate_transaction(&transaction).unwrap().priority, 9);
+
+			let mut stripped = transaction.clone();
+			stripped.inputs[0].sigscript = H512::zero();
+			let change_key = BlakeTwo256::hash_of(&(&stripped.encode(), 0u64));
+			let data_key = BlakeTwo256::hash_of(&(&stripped.encode(), 1u64));
+			assert_ok!(utxo::spend(transaction));
+			assert!(sp_io::storage::exists(&change_key.encode()));
+			assert!(!sp_io::storage::exists(&data_key.encode()));
+
+			let too_large = utxo::Transaction {
+				outputs: vec![utxo::TransactionOutput {
+					value: 0,
+					pubkey: H256::zero(),
+					lock: utxo::Lock::Pubkey,
+					data: Some(vec![0u8; utxo::MAX_OUTPUT_DATA + 1]),
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			assert_eq!(
+				utxo::check_outputs(&too_large),
+				Err(InvalidTransaction::Custom(utxo::OUTPUT_DATA_TOO_LARGE_ERROR))
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_rejects_existing_output() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let output = utxo::TransactionOutput {
+				value: 90,
+				pubkey: H256::from(alice_pub_key),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			};
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![output.clone()],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_ok!(utxo::validate_transaction(&transaction));
+
+			// The outpoint is derived without the signatures, whichever variant is submitted
+			let stripped = utxo::get_stripped_transaction(&transaction);
+			let outpoint = BlakeTwo256::hash_of(&(&stripped, 0u64));
+			sp_io::storage::set(&outpoint.encode(), &output.encode());
+			assert_eq!(
+				utxo::validate_transaction(&transaction).err(),
+				Some("output utxo already exists")
+			);
+			assert_eq!(
+				utxo::spend(transaction).err().unwrap(),
+				sp_runtime::DispatchError::Other("output utxo already exists")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_total_issuance_tracks_burns() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			// The oversized test UTXO is not part of the issuance
+			sp_io::storage::clear(&GENESIS_UTXO_BIG);
+			assert_eq!(utxo::total_issuance(), 150);
+			assert_eq!(utxo::utxo_set_value(), Some(150));
+			// Other entries under 32 byte keys are not counted, nor do they stop the walk
+			sp_io::storage::set(&[7; 32], b"no utxo");
+			assert_eq!(utxo::utxo_set_value(), Some(150));
+
+			// 100 spent, 60 kept, 30 burned in a data output and 10 burned as fee
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![
+					utxo::TransactionOutput {
+						value: 60,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+					utxo::TransactionOutput {
+						value: 30,
+						pubkey: H256::zero(),
+						lock: utxo::Lock::Pubkey,
+						data: Some(b"burn".to_vec()),
+						confidential: None,
+					},
+				],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_ok!(utxo::spend(transaction));
+
+			assert_eq!(utxo::total_issuance(), 110);
+			assert_eq!(utxo::utxo_set_value(), Some(110));
+
+			utxo::mint(5);
+			assert_eq!(utxo::total_issuance(), 115);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_commitment_proofs() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			let genesis_utxo = utxo::TransactionOutput {
+				value: 100,
+				pubkey: H256::from(alice_pub_key),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			};
+			let outpoint = H256::from(GENESIS_UTXO);
+
+			let root = commitment::root(&commitment::RuntimeStore);
+			assert!(!root.is_zero());
+			let proof =
+				commitment::prove(&commitment::RuntimeStore, &outpoint, Some(genesis_utxo.clone()));
+			assert!(commitment::verify_proof(&root, &outpoint, &proof));
+
+			// A different value or a claim of absence does not verify
+			let forged = commitment::UtxoProof {
+				utxo: Some(utxo::TransactionOutput { value: 1000, ..genesis_utxo.clone() }),
+				..proof.clone()
+			};
+			assert!(!commitment::verify_proof(&root, &outpoint, &forged));
+			let absent = commitment::UtxoProof { utxo: None, ..proof };
+			assert!(!commitment::verify_proof(&root, &outpoint, &absent));
+
+			let unknown = H256::repeat_byte(9);
+			let proof = commitment::prove(&commitment::RuntimeStore, &unknown, None);
+			assert!(commitment::verify_proof(&root, &unknown, &proof));
+
+			// Once spent, the outpoint is proven absent against the new root
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput { outpoint, ..Default::default() }],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			assert_ok!(utxo::spend(transaction));
+
+			let new_root = commitment::root(&commitment::RuntimeStore);
+			assert_ne!(new_root, root);
+			let proof = commitment::prove(&commitment::RuntimeStore, &outpoint, None);
+			assert!(commitment::verify_proof(&new_root, &outpoint, &proof));
+
+			let mut digest = sp_runtime::Digest::default();
+			assert_eq!(commitment::find_root(&digest), None);
+			digest.push(commitment::digest_item(new_root));
+			assert_eq!(commitment::find_root(&digest), Some(new_root));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_commitment_stores_compact_tree() {
+		let utxo = |value| utxo::TransactionOutput {
+			value,
+			pubkey: H256::repeat_byte(1),
+			lock: utxo::Lock::Pubkey,
+			data: None,
+			confidential: None,
+		};
+		let outpoints: Vec<H256> = (0..64u64).map(|index| BlakeTwo256::hash_of(&index)).collect();
+		let mut store = std::collections::BTreeMap::<Vec<u8>, Vec<u8>>::new();
+		let mut reversed = store.clone();
+		for (index, outpoint) in outpoints.iter().enumerate() {
+			commitment::insert(&mut store, outpoint, &utxo(index as u64 + 1));
+		}
+		for (index, outpoint) in outpoints.iter().enumerate().rev() {
+			commitment::insert(&mut reversed, outpoint, &utxo(index as u64 + 1));
+		}
+		// A leaf per UTXO and a branch wherever paths part, instead of a node per level
+		assert_eq!(store, reversed);
+		assert!(store.len() < 4 * outpoints.len());
+		let root = commitment::root(&store);
+
+		for (index, outpoint) in outpoints.iter().enumerate() {
+			let proof = commitment::prove(&store, outpoint, Some(utxo(index as u64 + 1)));
+			assert!(proof.siblings.len() < commitment::MAX_EXPECTED_DEPTH as usize);
+			assert!(commitment::verify_proof(&root, outpoint, &proof));
+			let forged = commitment::UtxoProof { utxo: Some(utxo(1000)), ..proof };
+			assert!(!commitment::verify_proof(&root, outpoint, &forged));
+		}
+
+		// Absent outpoints end either in an empty subtree or at the leaf of another UTXO
+		let mut absent_in_empty = false;
+		let mut absent_at_leaf = false;
+		for index in 64..128u64 {
+			let outpoint = BlakeTwo256::hash_of(&index);
+			let proof = commitment::prove(&store, &outpoint, None);
+			assert!(commitment::verify_proof(&root, &outpoint, &proof));
+			match proof.other_leaf {
+				Some((other, _)) => {
+					absent_at_leaf = true;
+					// The other leaf does not prove the outpoint present, nor itself absent
+					let present = commitment::UtxoProof { utxo: Some(utxo(1)), ..proof.clone() };
+					assert!(!commitment::verify_proof(&root, &outpoint, &present));
+					assert!(!commitment::verify_proof(&root, &other, &proof));
+				},
+				None => absent_in_empty = true,
+			}
+		}
+		assert!(absent_in_empty && absent_at_leaf);
+
+		// The same tree is left after removing UTXOs as if they had never been added,
+		// and nothing is left once all are spent
+		let mut half = std::collections::BTreeMap::<Vec<u8>, Vec<u8>>::new();
+		for (index, outpoint) in outpoints.iter().enumerate().skip(32) {
+			commitment::insert(&mut half, outpoint, &utxo(index as u64 + 1));
+		}
+		for outpoint in outpoints.iter().take(32) {
+			commitment::remove(&mut store, outpoint);
+		}
+		assert_eq!(store, half);
+		for outpoint in outpoints.iter().skip(32) {
+			commitment::remove(&mut store, outpoint);
+		}
+		assert!(store.is_empty());
+		assert!(commitment::root(&store).is_zero());
+	}
+
+	#[test]
+	fn utxo_frameless_finalize_block_commits_extrinsics() {
+		new_test_ext().execute_with(|| {
+			use sp_api::runtime_decl_for_Core::Core;
+
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			let extrinsic = BasicExtrinsic(transaction);
+
+			Runtime::initialize_block(&Header::new(
+				1,
+				Default::default(),
+				Default::default(),
+				H256::from(TEST_GENESIS_HASH),
+				Default::default(),
+			));
+			assert_eq!(Runtime::apply_extrinsic(extrinsic.clone()), Ok(Ok(())));
+			// Failing extrinsics are left out of the block
+			assert!(Runtime::apply_extrinsic(extrinsic.clone()).is_err());
+			let header = Runtime::finalize_block();
+
+			assert_eq!(header.extrinsics_root, extrinsics_root(vec![extrinsic.encode()]));
+			assert_eq!(
+				commitment::find_root(&header.digest),
+				Some(commitment::root(&commitment::RuntimeStore))
+			);
+			assert!(!sp_io::storage::exists(&EXTRINSICS_KEY));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_priority_and_outpoint_tags() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			// 100 + 50 spent, 115 created: a fee of 35
+			let mut transaction = utxo::Transaction {
+				inputs: vec![
+					utxo::TransactionInput {
+						outpoint: H256::from(GENESIS_UTXO),
+						..Default::default()
+					},
+					utxo::TransactionInput {
+						outpoint: genesis_utxo_small(alice_pub_key),
+						..Default::default()
+					},
+				],
+				outputs: vec![utxo::TransactionOutput {
+					value: 115,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+
+			// Prioritized by whole fee increments of 10
+			let valid = utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(valid.priority, 3);
+			assert_eq!(
+				valid.provides,
+				vec![H256::from(GENESIS_UTXO).encode(), genesis_utxo_small(alice_pub_key).encode()]
+			);
+			assert!(valid.requires.is_empty());
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_bump_fee_outbids_pending_spend() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			let transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![
+					utxo::TransactionOutput {
+						value: 25,
+						pubkey: H256::repeat_byte(2),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+					utxo::TransactionOutput {
+						value: 70,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					},
+				],
+				..Default::default()
+			};
+			let mut pending = psbt::PartiallySignedTransaction::new(
+				transaction,
+				vec![utxo::TransactionOutput {
+					value: 100,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				H256::from(TEST_GENESIS_HASH),
+			)
+			.unwrap();
+			let sign = |psbt: &mut psbt::PartiallySignedTransaction| {
+				let payload = psbt.signing_payload(0).unwrap();
+				let signature =
+					sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &payload).unwrap();
+				psbt.add_signature(0, H512::from(signature)).unwrap();
+			};
+			sign(&mut pending);
+
+			// A replacement has to pay at least one fee increment more
+			let increment = utxo::fee_increment();
+			assert_eq!(increment, 10);
+			assert_eq!(utxo::check_replacement(5, 15, increment), Ok(()));
+			assert_eq!(
+				utxo::check_replacement(5, 14, increment),
+				Err("Replacement has to pay at least one fee increment more")
+			);
+			assert_eq!(
+				pending.clone().bump_fee(1, increment - 1, increment),
+				Err("Replacement has to pay at least one fee increment more")
+			);
+
+			let mut replacement = pending.clone();
+			replacement.bump_fee(1, increment, increment).unwrap();
+			assert!(!replacement.is_complete());
+			assert_eq!(replacement.fee(), Some(15));
+			assert_eq!(
+				replacement.clone().bump_fee(1, 71, increment),
+				Err("Change output is too small")
+			);
+			assert_eq!(
+				replacement.clone().bump_fee(2, increment, increment),
+				Err("Output index out of range")
+			);
+			sign(&mut replacement);
+
+			// Wallets bumping by less than an increment are not outbidding in the pool either
+			let mut underpaid = pending.clone();
+			underpaid.bump_fee(1, increment - 1, 1).unwrap();
+			sign(&mut underpaid);
+
+			// All spend the same outpoint. The pool only replaces a pending transaction with a
+			// strictly higher priority, which takes reaching the next whole fee increment.
+			let replacement = replacement.finalize().unwrap();
+			let pending = utxo::validate_transaction(&pending.finalize().unwrap()).unwrap();
+			let underpaid = utxo::validate_transaction(&underpaid.finalize().unwrap()).unwrap();
+			let valid = utxo::validate_transaction(&replacement).unwrap();
+			assert_eq!(pending.provides, valid.provides);
+			assert_eq!(pending.provides, underpaid.provides);
+			assert_eq!(pending.priority, 0);
+			assert_eq!(underpaid.priority, pending.priority);
+			assert_eq!(valid.priority, 1);
+
+			// The increment is read from state on chain
+			sp_io::storage::set(&utxo::FEE_INCREMENT_KEY, &(5 as utxo::Value).encode());
+			assert_eq!(utxo::validate_transaction(&replacement).unwrap().priority, 3);
+		})
+	}
+
+	fn coinbase_inherent_data(miner: H256, timestamp: u64) -> sp_inherents::InherentData {
+		let mut data = sp_inherents::InherentData::new();
+		data.put_data(pow::COINBASE_INHERENT_IDENTIFIER, &miner).unwrap();
+		data.put_data(pow::TIMESTAMP_INHERENT_IDENTIFIER, &timestamp).unwrap();
+		data
+	}
+
+	/// Start block `number` the way `initialize_block` does, without needing a full header
+	fn start_block(number: BlockNumber) {
+		sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &number.encode());
+		sp_io::storage::clear(&EXTRINSICS_KEY);
+	}
+
+	#[test]
+	fn utxo_frameless_coinbase_pays_miner() {
+		new_test_ext().execute_with(|| {
+			let miner = H256::repeat_byte(7);
+			start_block(1);
+
+			// No coinbase unless the node provides a miner key
+			assert!(Runtime::inherent_extrinsics(sp_inherents::InherentData::new()).is_empty());
+
+			let inherents = Runtime::inherent_extrinsics(coinbase_inherent_data(miner, 1_000));
+			assert_eq!(inherents.len(), 1);
+			let coinbase = inherents[0].clone();
+			assert!(pow::is_coinbase(&coinbase.0));
+			assert_eq!(pow::coinbase_timestamp(&coinbase.0), Some(1_000));
+
+			// Coinbases are never accepted into the pool
+			assert!(utxo::validate_transaction(&coinbase.0).is_err());
+
+			let issuance = utxo::total_issuance();
+			assert_eq!(Runtime::apply_extrinsic(coinbase.clone()), Ok(Ok(())));
+			let outpoint = BlakeTwo256::hash_of(&(&coinbase.0.encode(), 0u64));
+			let reward = utxo::TransactionOutput {
+				value: pow::BLOCK_REWARD,
+				pubkey: miner,
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			};
+			assert_eq!(sp_io::storage::get(&outpoint.encode()), Some(reward.encode()));
+			assert_eq!(utxo::total_issuance(), issuance + pow::BLOCK_REWARD);
+			assert_eq!(pow::last_timestamp(), Some(1_000));
+
+			// Only one coinbase per block, and only as its first extrinsic
+			let second = pow::coinbase(H256::repeat_byte(8), 1_001);
+			assert!(Runtime::apply_extrinsic(BasicExtrinsic(second)).is_err());
+
+			// A coinbase is only valid in its own block and must pay exactly the block reward
+			start_block(2);
+			assert!(Runtime::apply_extrinsic(coinbase).is_err());
+			let mut greedy = pow::coinbase(miner, 2_000);
+			greedy.outputs[0].value += 1;
+			assert!(Runtime::apply_extrinsic(BasicExtrinsic(greedy)).is_err());
+			assert_eq!(utxo::total_issuance(), issuance + pow::BLOCK_REWARD);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_coinbase_needs_proof_of_work() {
+		new_test_ext().execute_with(|| {
+			sp_io::storage::set(&pow::PROOF_OF_WORK_KEY, &false.encode());
+			assert!(!pow::is_enabled());
+			start_block(1);
+			let issuance = utxo::total_issuance();
+
+			// Aura authors cannot mint rewards nor move the difficulty
+			assert!(Runtime::inherent_extrinsics(coinbase_inherent_data(H256::repeat_byte(7), 1_000))
+				.is_empty());
+			let coinbase = pow::coinbase(H256::repeat_byte(7), 1_000);
+			assert_eq!(
+				pow::apply_coinbase(&coinbase),
+				Err("Coinbases are only accepted on proof of work chains".into())
+			);
+			assert!(Runtime::apply_extrinsic(BasicExtrinsic(coinbase)).is_err());
+			assert_eq!(utxo::total_issuance(), issuance);
+			assert_eq!(pow::last_timestamp(), None);
+
+			// Chains without the flag in genesis run Aura
+			sp_io::storage::clear(&pow::PROOF_OF_WORK_KEY);
+			assert!(!pow::is_enabled());
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_coinbase_rejects_confidential_outputs() {
+		use curve25519_dalek::scalar::Scalar;
+
+		new_test_ext().execute_with(|| {
+			let miner = H256::repeat_byte(7);
+			let hidden = confidential::Confidential::new(1_000_000, &Scalar::from(5u64));
+			start_block(1);
+			let issuance = utxo::total_issuance();
+
+			// The reward would mint the hidden value instead of the block reward
+			let mut coinbase = pow::coinbase(miner, 1_000);
+			coinbase.outputs[0].value = 0;
+			coinbase.outputs[0].confidential = Some(hidden.clone());
+			assert_eq!(pow::apply_coinbase(&coinbase), Err("Invalid coinbase reward".into()));
+			coinbase.outputs[0].value = pow::BLOCK_REWARD;
+			assert_eq!(pow::apply_coinbase(&coinbase), Err("Invalid coinbase reward".into()));
+
+			let mut coinbase = pow::coinbase(miner, 1_000);
+			coinbase.outputs[0].lock = utxo::Lock::PubkeyHash(utxo::pubkey_hash(&miner));
+			assert_eq!(pow::apply_coinbase(&coinbase), Err("Invalid coinbase reward".into()));
+
+			let mut coinbase = pow::coinbase(miner, 1_000);
+			coinbase.outputs[1].confidential = Some(hidden);
+			assert_eq!(pow::apply_coinbase(&coinbase), Err("Invalid coinbase timestamp".into()));
+
+			assert_eq!(utxo::total_issuance(), issuance);
+			assert_eq!(pow::last_timestamp(), None);
+			assert_ok!(pow::apply_coinbase(&pow::coinbase(miner, 1_000)));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_difficulty_adjusts_to_block_time() {
+		new_test_ext().execute_with(|| {
+			let target = pow::TARGET_BLOCK_TIME;
+			let difficulty = sp_core::U256::from(1000);
+			assert_eq!(pow::next_difficulty(difficulty, target), difficulty);
+			assert!(pow::next_difficulty(difficulty, target / 2) > difficulty);
+			assert!(pow::next_difficulty(difficulty, target * 2) < difficulty);
+			// Outliers are clamped to four times the target either way
+			assert_eq!(
+				pow::next_difficulty(difficulty, 0),
+				pow::next_difficulty(difficulty, target / 4)
+			);
+			assert_eq!(pow::next_difficulty(1.into(), u64::max_value()), 1.into());
+
+			// The first coinbase only records its timestamp
+			assert_eq!(pow::difficulty(), difficulty);
+			start_block(1);
+			assert_ok!(pow::apply_coinbase(&pow::coinbase(H256::repeat_byte(7), 10_000)));
+			assert_eq!(pow::difficulty(), difficulty);
+
+			// A block found in half the target time raises the difficulty of the next one
+			start_block(2);
+			assert_ok!(pow::apply_coinbase(&pow::coinbase(H256::repeat_byte(7), 10_000 + target / 2)));
+			assert_eq!(pow::difficulty(), pow::next_difficulty(difficulty, target / 2));
+
+			// Timestamps must increase, the builder bumps a lagging clock
+			start_block(3);
+			assert!(pow::apply_coinbase(&pow::coinbase(H256::repeat_byte(7), 10_000)).is_err());
+			let coinbase = pow::create_coinbase(&coinbase_inherent_data(H256::repeat_byte(7), 5)).unwrap();
+			assert_eq!(pow::coinbase_timestamp(&coinbase), Some(10_000 + target / 2 + 1));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_check_inherents_rejects_future_coinbase() {
+		new_test_ext().execute_with(|| {
+			start_block(1);
+			let coinbase = pow::coinbase(H256::repeat_byte(7), 100_000);
+			let block = |extrinsics| Block {
+				header: Header {
+					parent_hash: H256::zero(),
+					number: 1,
+					state_root: H256::zero(),
+					extrinsics_root: H256::zero(),
+					digest: Default::default(),
+				},
+				extrinsics,
+			};
+
+			let now = 100_000 - pow::MAX_TIMESTAMP_DRIFT;
+			let data = coinbase_inherent_data(H256::zero(), now);
+			assert!(Runtime::check_inherents(block(vec![BasicExtrinsic(coinbase.clone())]), data).ok());
+
+			let data = coinbase_inherent_data(H256::zero(), now - 1);
+			let result = Runtime::check_inherents(block(vec![BasicExtrinsic(coinbase)]), data);
+			assert!(!result.ok());
+			assert!(result.fatal_error());
+
+			// Blocks without a coinbase have nothing to check
+			let data = coinbase_inherent_data(H256::zero(), 0);
+			assert!(Runtime::check_inherents(block(Vec::new()), data).ok());
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_slot_duration_from_genesis() {
+		new_test_ext().execute_with(|| {
+			assert_eq!(slot_duration(), 2000);
+			sp_io::storage::clear(&SLOT_DURATION_KEY);
+			assert_eq!(slot_duration(), DEFAULT_SLOT_DURATION);
+		});
+
+		let config = GenesisConfig { slot_duration: 0, ..Default::default() };
+		assert!(matches!(config.build_storage(), Err(e) if e == "Slot duration must not be zero"));
+	}
+
+	#[test]
+	fn utxo_frameless_identical_genesis_allocations_are_kept() {
+		let allocation =
+			utxo::TransactionOutput {
+				value: 30,
+				pubkey: H256::repeat_byte(1),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			};
+		let config = GenesisConfig {
+			genesis_utxos: vec![allocation.clone(), allocation.clone()],
+			..Default::default()
+		};
+		let storage = config.build_storage().unwrap();
+
+		let first = genesis_outpoint(&allocation, 0);
+		let second = genesis_outpoint(&allocation, 1);
+		assert_ne!(first, second);
+		assert_eq!(storage.top.get(&first.encode()), Some(&allocation.encode()));
+		assert_eq!(storage.top.get(&second.encode()), Some(&allocation.encode()));
+		assert_eq!(
+			storage.top.get(&utxo::TOTAL_ISSUANCE_KEY.to_vec()),
+			Some(&(60 as utxo::Value).encode())
+		);
+
+		let zero = utxo::TransactionOutput { value: 0, ..allocation };
+		let config = GenesisConfig { genesis_utxos: vec![zero], ..Default::default() };
+		assert!(matches!(
+			config.build_storage(),
+			Err(e) if e == "Genesis UTXOs must have a non-zero value"
+		));
+	}
+
+	#[test]
+	fn utxo_frameless_authorities_from_genesis() {
+		use sp_application_crypto::ByteArray;
+
+		new_test_ext().execute_with(|| {
+			let (aura, grandpa) = default_authorities().remove(0);
+			assert_eq!(aura_authorities(), vec![aura]);
+			assert_eq!(grandpa_authorities(), vec![(grandpa, 1)]);
+		});
+
+		let authorities: Vec<(AuraId, GrandpaId)> = (1u8..=3)
+			.map(|i| {
+				(AuraId::from_slice(&[i; 32]).unwrap(), GrandpaId::from_slice(&[i; 32]).unwrap())
+			})
+			.collect();
+		let config = GenesisConfig { authorities: authorities.clone(), ..Default::default() };
+		sp_io::TestExternalities::from(config.build_storage().unwrap()).execute_with(|| {
+			assert_eq!(aura_authorities().len(), 3);
+			assert_eq!(aura_authorities()[2], authorities[2].0);
+			assert_eq!(grandpa_authorities()[1], (authorities[1].1.clone(), 1));
+		});
+
+		let config = GenesisConfig { authorities: Vec::new(), ..Default::default() };
+		assert!(matches!(
+			config.build_storage(),
+			Err(e) if e == "At least one authority is required"
+		));
+	}
+
+	#[test]
+	fn utxo_frameless_pubkey_hash_output_is_spent_by_revealing_the_key() {
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			let bob_pub_key = keystore.sr25519_generate_new(SR25519, Some("//Bob")).unwrap();
+			let alice_hash = utxo::pubkey_hash(&H256::from(alice_pub_key));
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					sigscript: H512::zero(),
+					sighash: utxo::SigHash::All,
+					pubkey: Some(H256::from(alice_pub_key)),
+					redeemer: None,
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::zero(),
+					lock: utxo::Lock::PubkeyHash(alice_hash),
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			// Outputs locked to a key take no revealed key
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Only pubkey hash outputs take a revealed key")
+			);
+
+			transaction.inputs[0].pubkey = None;
+			let outpoint =
+				BlakeTwo256::hash_of(&(&utxo::get_stripped_transaction(&transaction), 0u64));
+			assert_ok!(utxo::spend(transaction));
+			let stored = sp_io::storage::get(&outpoint.encode()).unwrap();
+			assert_eq!(
+				utxo::TransactionOutput::decode(&mut &stored[..]).unwrap().lock,
+				utxo::Lock::PubkeyHash(alice_hash)
+			);
+
+			let spend = |pubkey: Option<H256>, signer: &sp_core::sr25519::Public| {
+				let mut transaction = utxo::Transaction {
+					inputs: vec![utxo::TransactionInput {
+						outpoint,
+						sigscript: H512::zero(),
+						sighash: utxo::SigHash::All,
+						pubkey,
+						redeemer: None,
+					}],
+					outputs: vec![utxo::TransactionOutput {
+						value: 80,
+						pubkey: H256::from(bob_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					}],
+					..Default::default()
+				};
+				sign_inputs(&mut transaction, signer);
+				transaction
+			};
+
+			assert_eq!(
+				utxo::validate_transaction(&spend(None, &alice_pub_key)),
+				Err("Spending a pubkey hash output requires its key")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&spend(Some(H256::from(bob_pub_key)), &bob_pub_key)),
+				Err("Revealed key does not match the pubkey hash")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&spend(Some(H256::from(alice_pub_key)), &bob_pub_key)),
+				Err("Invalid Signature to spend this Input")
+			);
+			assert_ok!(utxo::spend(spend(Some(H256::from(alice_pub_key)), &alice_pub_key)));
+			assert!(!sp_io::storage::exists(&outpoint.encode()));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_confidential_known_vectors() {
+		use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_COMPRESSED, scalar::Scalar};
+
+		let compressed = |point: curve25519_dalek::ristretto::RistrettoPoint| point.compress().to_bytes();
+		assert_eq!(
+			compressed(confidential::value_generator()),
+			hex!("06a63f454558d8f0b603cc97470a8985f8136c449e6ca0bbebdc70fa5a240f17")
+		);
+		// Committing to zero with a blinding factor of one gives the ristretto basepoint
+		assert_eq!(
+			compressed(confidential::commit(0, &Scalar::one())),
+			hex!("e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d76")
+		);
+		assert_eq!(
+			compressed(confidential::commit(0, &Scalar::one())),
+			RISTRETTO_BASEPOINT_COMPRESSED.to_bytes()
+		);
+		assert_eq!(confidential::commit(1, &Scalar::zero()), confidential::value_generator());
+		assert_eq!(
+			compressed(confidential::commit(1000, &Scalar::from(7u64))),
+			hex!("8840b2a322539667bb9b50e5b77227595bb59487ca9206b40a1ce1d335a10c56")
+		);
+
+		// Proofs are deterministic
+		let amount = confidential::Confidential::new(1000, &Scalar::from(7u64));
+		assert_eq!(amount, confidential::Confidential::new(1000, &Scalar::from(7u64)));
+		assert_eq!(
+			amount.commitment,
+			H256::from(hex!("8840b2a322539667bb9b50e5b77227595bb59487ca9206b40a1ce1d335a10c56"))
+		);
+		assert_eq!(amount.range_proof.bits.len(), confidential::RANGE_BITS);
+		assert_eq!(
+			amount.range_proof.bits[0].commitment,
+			H256::from(hex!("06e5e429cf5dd7210bdadb19a9c00bc519e03927440146a0bef0b5488883b05e"))
+		);
+		assert_eq!(
+			amount.range_proof.bits[0].challenge,
+			H256::from(hex!("a375e9a61efe26239446a162df6d9997ad5ae8193a64c79a2798b43ebb3ea506"))
+		);
+		assert_eq!(
+			sp_io::hashing::blake2_256(&amount.range_proof.encode()),
+			hex!("1dd7f8b73eb89f932079265cbe1d5328baaf286ea9ed1a3d2bc2cc6d222cf921")
+		);
+		assert_eq!(amount.verify().map(compressed), Ok(amount.commitment.to_fixed_bytes()));
+	}
+
+	#[test]
+	fn utxo_frameless_confidential_range_proof_rejects_tampering() {
+		use curve25519_dalek::scalar::Scalar;
+
+		for value in [0, 1, u64::MAX] {
+			assert!(confidential::Confidential::new(value, &Scalar::from(3u64)).verify().is_ok());
+		}
+
+		let amount = confidential::Confidential::new(1000, &Scalar::from(7u64));
+
+		let mut tampered = amount.clone();
+		tampered.range_proof.bits[5].response_one.0[0] ^= 1;
+		assert_eq!(tampered.verify(), Err("Invalid range proof"));
+
+		let mut tampered = amount.clone();
+		tampered.range_proof.bits.swap(0, 3);
+		assert_eq!(tampered.verify(), Err("Invalid range proof"));
+
+		let mut tampered = amount.clone();
+		tampered.range_proof.bits.pop();
+		assert_eq!(tampered.verify(), Err("Range proof has the wrong number of bits"));
+
+		// The proof is bound to its commitment
+		let mut tampered = amount;
+		tampered.commitment = confidential::Confidential::new(1001, &Scalar::from(7u64)).commitment;
+		assert_eq!(tampered.verify(), Err("Invalid range proof"));
+	}
+
+	#[test]
+	fn utxo_frameless_confidential_outputs_balance_with_declared_fee() {
+		use curve25519_dalek::scalar::Scalar;
+
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			sp_io::storage::clear(&GENESIS_UTXO_BIG);
+
+			let confidential_output = |value: u64, blinding: &Scalar| utxo::TransactionOutput {
+				value: 0,
+				pubkey: H256::from(alice_pub_key),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: Some(confidential::Confidential::new(value, blinding)),
+			};
+			// 100 plain in, 60 and 35 confidential out, the blinding factors cancel out
+			let blinding = Scalar::from(99u64);
+			let shield = |fee: Option<utxo::Value>| {
+				let mut transaction = utxo::Transaction {
+					inputs: vec![utxo::TransactionInput {
+						outpoint: H256::from(GENESIS_UTXO),
+						..Default::default()
+					}],
+					outputs: vec![
+						confidential_output(60, &blinding),
+						confidential_output(35, &-blinding),
+					],
+					fee,
+					..Default::default()
+				};
+				sign_inputs(&mut transaction, &alice_pub_key);
+				transaction
+			};
+
+			assert_eq!(
+				utxo::validate_transaction(&shield(None)),
+				Err("Creating a confidential output requires a declared fee")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&shield(Some(6))),
+				Err("Confidential amounts do not balance with the fee")
+			);
+			let transaction = shield(Some(5));
+			assert_ok!(utxo::validate_transaction(&transaction));
+			let stripped = utxo::get_stripped_transaction(&transaction);
+			assert_ok!(utxo::spend(transaction));
+
+			assert_eq!(utxo::total_issuance(), 145);
+			assert_eq!(utxo::shielded_value(), 95);
+			assert_eq!(utxo::utxo_set_value(), Some(50));
+
+			// Both confidential outputs back into a plain one
+			let outpoints: Vec<_> =
+				(0..2).map(|index| BlakeTwo256::hash_of(&(&stripped, index as u64))).collect();
+			let unshield = |fee: Option<utxo::Value>, value: utxo::Value| {
+				let mut transaction = utxo::Transaction {
+					inputs: outpoints
+						.iter()
+						.map(|outpoint| utxo::TransactionInput {
+							outpoint: *outpoint,
+							..Default::default()
+						})
+						.collect(),
+					outputs: vec![utxo::TransactionOutput {
+						value,
+						pubkey: H256::from(alice_pub_key),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					}],
+					fee,
+					..Default::default()
+				};
+				sign_inputs(&mut transaction, &alice_pub_key);
+				transaction
+			};
+
+			assert_eq!(
+				utxo::validate_transaction(&unshield(None, 90)),
+				Err("Spending a confidential output requires a declared fee")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&unshield(Some(5), 91)),
+				Err("Confidential amounts do not balance with the fee")
+			);
+			assert_ok!(utxo::spend(unshield(Some(5), 90)));
+
+			assert_eq!(utxo::total_issuance(), 140);
+			assert_eq!(utxo::shielded_value(), 0);
+			assert_eq!(utxo::utxo_set_value(), Some(140));
+		})
+	}
+
+	// Participants of the aggregate signature tests, with their secret scalars
+	fn musig_participants() -> Vec<(H256, curve25519_dalek::scalar::Scalar)> {
+		use sp_core::Pair as _;
+
+		[1u8, 2, 3]
+			.iter()
+			.map(|seed| {
+				let pair = sp_core::sr25519::Pair::from_seed(&[*seed; 32]);
+				let mut secret = [0u8; 32];
+				secret.copy_from_slice(&pair.to_raw_vec()[..32]);
+				(
+					H256::from(pair.public().0),
+					curve25519_dalek::scalar::Scalar::from_bytes_mod_order(secret),
+				)
+			})
+			.collect()
+	}
+
+	// Run the three signing rounds between all participants, returning their partial signatures.
+	fn musig_partial_signatures(message: &[u8]) -> Vec<musig::PartialSignature> {
+		let participants = musig_participants();
+		let keys: Vec<_> = participants.iter().map(|(key, _)| *key).collect();
+		let mut sessions: Vec<_> = participants
+			.iter()
+			.enumerate()
+			.map(|(index, (key, _))| {
+				musig::Session::new(keys.clone(), *key, message.to_vec(), &[index as u8 + 1; 64])
+					.unwrap()
+			})
+			.collect();
+
+		let commitments: Vec<_> = sessions.iter().map(|s| s.commitment().unwrap()).collect();
+		assert_eq!(sessions[0].reveal(), Err("Nonce commitments have not been collected yet"));
+		for session in sessions.iter_mut() {
+			assert_ok!(session.add_commitments(&commitments));
+		}
+		let nonces: Vec<_> = sessions.iter().map(|s| s.reveal().unwrap()).collect();
+		for session in sessions.iter_mut() {
+			assert_ok!(session.add_nonces(&nonces));
+		}
+		sessions
+			.iter()
+			.zip(participants.iter())
+			.map(|(session, (_, secret))| session.sign(secret).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn utxo_frameless_musig_aggregate_key_known_vector() {
+		let keys: Vec<_> = musig_participants().into_iter().map(|(key, _)| key).collect();
+		assert_eq!(
+			keys,
+			[
+				hex!("189dac29296d31814dc8c56cf3d36a0543372bba7538fa322a4aebfebc39e056"),
+				hex!("1a4fee48c1ba1a48e8cd43782a8485d635aa91cfb82cbb477f0c1c576bc4031c"),
+				hex!("8ee504148e75c34e8f051899b3c6e4241ff18dc1c9211260b6a6a434bedb485f"),
+			]
+			.map(H256::from)
+		);
+		let aggregate =
+			H256::from(hex!("02f6db0571e6aee67cd9b66f2be121b92006dc3fc789ad1ba3ff92cc71c17a76"));
+		assert_eq!(musig::aggregate_key(&keys), Ok(aggregate));
+		// The order of the keys does not matter, but every key does
+		assert_eq!(musig::aggregate_key(&[keys[2], keys[0], keys[1]]), Ok(aggregate));
+		assert_ne!(musig::aggregate_key(&keys[..2]), Ok(aggregate));
+		assert_ne!(musig::aggregate_key(&keys[..1]), Ok(keys[0]));
+		assert_eq!(musig::aggregate_key(&[keys[0], keys[0]]), Err("Keys must be distinct"));
+		assert_eq!(musig::aggregate_key(&[]), Err("No keys to aggregate"));
+	}
+
+	#[test]
+	fn utxo_frameless_musig_signing_rounds_are_checked() {
+		let participants = musig_participants();
+		let keys: Vec<_> = participants.iter().map(|(key, _)| *key).collect();
+		let message = b"message".to_vec();
+		assert_eq!(
+			musig::Session::new(keys[1..].to_vec(), keys[0], message.clone(), &[0; 64]),
+			Err("The signer is not one of the keys")
+		);
+
+		let new_session = |index: usize| {
+			musig::Session::new(keys.clone(), keys[index], message.clone(), &[index as u8 + 1; 64])
+				.unwrap()
+		};
+		let mut sessions: Vec<_> = (0..3).map(new_session).collect();
+		let commitments: Vec<_> = sessions.iter().map(|s| s.commitment().unwrap()).collect();
+		assert_eq!(
+			sessions[0].clone().add_commitments(&commitments[1..]),
+			Err("Exactly one message per participant is required")
+		);
+		assert_eq!(
+			sessions[0].clone().add_commitments(&[
+				commitments[0].clone(),
+				commitments[1].clone(),
+				commitments[1].clone()
+			]),
+			Err("Missing the message of a participant")
+		);
+		assert_eq!(sessions[0].sign(&participants[0].1), Err("Nonces have not been collected yet"));
+		for session in sessions.iter_mut() {
+			assert_ok!(session.add_commitments(&commitments));
+		}
+
+		// A nonce chosen after seeing the others does not match its commitment
+		let mut nonces: Vec<_> = sessions.iter().map(|s| s.reveal().unwrap()).collect();
+		let mut late = nonces.clone();
+		late[2].nonce = nonces[0].nonce;
+		assert_eq!(
+			sessions[0].clone().add_nonces(&late),
+			Err("Nonce does not match its commitment")
+		);
+		nonces.reverse();
+		for session in sessions.iter_mut() {
+			assert_ok!(session.add_nonces(&nonces));
+		}
+		assert_eq!(
+			sessions[0].sign(&participants[1].1),
+			Err("Secret key does not belong to the signer")
+		);
+	}
+
+	#[test]
+	fn utxo_frameless_musig_aggregate_signature_spends_output() {
+		use sp_core::Pair as _;
+
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			let keys: Vec<_> = musig_participants().into_iter().map(|(key, _)| key).collect();
+			let aggregate = musig::aggregate_key(&keys).unwrap();
+
+			// An output locked to the aggregate key is an ordinary output locked to a key
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 100,
+					pubkey: aggregate,
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			let outpoint =
+				BlakeTwo256::hash_of(&(&utxo::get_stripped_transaction(&transaction), 0u64));
+			assert_ok!(utxo::spend(transaction));
+
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput { outpoint, ..Default::default() }],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			let message = utxo::get_signing_payload(
+				&transaction,
+				0,
+				&H256::from(TEST_GENESIS_HASH),
+				VERSION.transaction_version,
+			)
+			.unwrap();
+			let partials = musig_partial_signatures(&message);
+
+			// Every participant has to sign
+			assert_eq!(
+				musig::aggregate_signature(&message, &partials[..2]),
+				Err("Partial signatures were made with different nonces")
+			);
+			let mut forged = partials.clone();
+			forged[1].signature = forged[2].signature;
+			assert_eq!(
+				musig::aggregate_signature(&message, &forged),
+				Err("Invalid partial signature")
+			);
+			assert_eq!(
+				musig::aggregate_signature(b"other message", &partials),
+				Err("Invalid partial signature")
+			);
+
+			// Nor can a participant spend alone
+			let participant = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
+			transaction.inputs[0].sigscript = H512::from(participant.sign(&message));
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Invalid Signature to spend this Input")
+			);
+			transaction.inputs[0].sigscript =
+				musig::aggregate_signature(&message, &partials).unwrap();
+			assert_ok!(utxo::spend(transaction));
+			assert!(!sp_io::storage::exists(&outpoint.encode()));
+		})
+	}
+
+	// Run `validator` with `arguments` as the only input of a transaction spending a script
+	// output with the datum `b"datum"`
+	fn run_script(validator: &[u8], arguments: Vec<Vec<u8>>) -> Result<(), &'static str> {
+		let transaction = utxo::Transaction {
+			inputs: vec![utxo::TransactionInput::default()],
+			outputs: vec![utxo::TransactionOutput {
+				value: 10,
+				pubkey: H256::repeat_byte(2),
+				lock: utxo::Lock::Pubkey,
+				data: None,
+				confidential: None,
+			}],
+			..Default::default()
+		};
+		let spent = [utxo::TransactionOutput {
+			value: 20,
+			pubkey: H256::zero(),
+			lock: utxo::Lock::Script {
+				validator: script::validator_hash(validator),
+				datum: b"datum".to_vec(),
+			},
+			data: None,
+			confidential: None,
+		}];
+		let context = script::Context {
+			transaction: &transaction,
+			index: 0,
+			spent: &spent,
+			signing_payload: &[],
+		};
+		script::validate(&script::Redeemer { validator: validator.to_vec(), arguments }, &context)
+	}
+
+	// Signature of input `index` by the key with the secret URI `suri`
+	fn script_signature(transaction: &utxo::Transaction, index: usize, suri: &str) -> Vec<u8> {
+		use sp_core::Pair as _;
+
+		let payload = utxo::get_signing_payload(
+			transaction,
+			index,
+			&H256::from(TEST_GENESIS_HASH),
+			VERSION.transaction_version,
+		)
+		.unwrap();
+		let pair = sp_core::sr25519::Pair::from_string(suri, None).unwrap();
+		pair.sign(&payload).0.to_vec()
+	}
+
+	#[test]
+	fn utxo_frameless_script_machine() {
+		use script::*;
+
+		let number = |value: utxo::Value| push_data(&encode_number(value));
+		assert_eq!(encode_number(0), Vec::<u8>::new());
+		assert_eq!(encode_number(256), vec![0, 1]);
+		assert_eq!(push_data(&[7; 80])[..2], [OP_PUSHDATA1, 80]);
+		assert_eq!(push_data(&[7; 300])[..3], [OP_PUSHDATA2, 44, 1]);
+
+		new_test_ext().execute_with(|| {
+			let sum = [number(2), number(3), vec![OP_ADD], number(5), vec![OP_EQUAL]].concat();
+			assert_ok!(run_script(&sum, vec![]));
+			let large = [push_data(&[7; 300]), push_data(&[7; 300]), vec![OP_EQUAL]].concat();
+			assert_ok!(run_script(&large, vec![]));
+			assert_ok!(run_script(&[OP_DATUM, 5, b'd', b'a', b't', b'u', b'm', OP_EQUAL], vec![]));
+			assert_ok!(run_script(&[OP_SWAP, OP_DROP], vec![vec![], vec![1]]));
+			assert_eq!(
+				run_script(&[OP_SUB], vec![vec![2], vec![3]]),
+				Err("Script arithmetic overflow")
+			);
+
+			// Arguments are pushed in order, the last one is checked by `OP_IF`
+			let branch = [OP_IF, OP_FALSE, OP_ELSE, OP_TRUE, OP_ENDIF];
+			assert_ok!(run_script(&branch, vec![vec![]]));
+			assert_eq!(
+				run_script(&branch, vec![vec![], vec![1]]),
+				Err("Script did not accept the spend")
+			);
+			// Instructions of branches not taken are not run
+			assert_ok!(run_script(&[OP_FALSE, OP_IF, 0xff, OP_ENDIF, OP_TRUE], vec![]));
+			assert_eq!(run_script(&[0xff], vec![]), Err("Invalid script instruction"));
+			assert_eq!(
+				run_script(&[OP_TRUE, OP_IF], vec![]),
+				Err("Unbalanced conditional in script")
+			);
+			assert_eq!(run_script(&[OP_ENDIF], vec![]), Err("Unbalanced conditional in script"));
+
+			assert_eq!(run_script(&[], vec![]), Err("Script did not accept the spend"));
+			assert_eq!(run_script(&[OP_ADD], vec![vec![1]]), Err("Script stack underflow"));
+			assert_eq!(run_script(&[5, 1, 2], vec![]), Err("Script is truncated"));
+			assert_eq!(run_script(&[OP_VALIDUNTIL], vec![]), Err("Transaction has no expiry"));
+			assert_eq!(
+				run_script(&[OP_TRUE, OP_OUTPUTVALUE], vec![]),
+				Err("Script index out of range")
+			);
+			assert_eq!(run_script(&[OP_TRUE; 300], vec![]), Err("Script stack is too deep"));
+			assert_eq!(
+				run_script(&[[OP_TRUE].as_ref(), &[OP_HASH; 1000]].concat(), vec![]),
+				Err("Script exceeds its cost limit")
+			);
+			assert_eq!(
+				run_script(&[0u8; MAX_VALIDATOR_SIZE + 1], vec![]),
+				Err("Validator is too large")
+			);
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_script_output_is_spent_by_its_validator() {
+		use script::*;
+		use sp_core::Pair as _;
+
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+			let bob = sp_core::sr25519::Pair::from_string("//Bob", None).unwrap().public();
+
+			// Bob may claim the output any time, Alice takes it back from block 10 on
+			let validator = [
+				&[OP_IF][..],
+				&push_data(bob.as_ref()),
+				&[OP_CHECKSIG, OP_ELSE, OP_VALIDFROM],
+				&push_data(&encode_number(10)),
+				&[OP_LESSTHAN, OP_NOT, OP_VERIFY],
+				&push_data(alice_pub_key.as_ref()),
+				&[OP_CHECKSIG, OP_ENDIF],
+			]
+			.concat();
+			let mut transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::from(GENESIS_UTXO),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 90,
+					pubkey: H256::zero(),
+					lock: utxo::Lock::Script {
+						validator: validator_hash(&validator),
+						datum: Vec::new(),
+					},
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			sign_inputs(&mut transaction, &alice_pub_key);
+			// Outputs locked to a key take no redeemer
+			transaction.inputs[0].redeemer =
+				Some(Redeemer { validator: vec![OP_TRUE], arguments: Vec::new() });
+			assert_eq!(
+				utxo::validate_transaction(&transaction),
+				Err("Only script outputs take a redeemer")
+			);
+			transaction.inputs[0].redeemer = None;
+			let outpoint =
+				BlakeTwo256::hash_of(&(&utxo::get_stripped_transaction(&transaction), 0u64));
+			assert_ok!(utxo::spend(transaction));
+
+			let spend = |valid_from: Option<u32>, suri: &str, mut arguments: Vec<Vec<u8>>| {
+				let mut transaction = utxo::Transaction {
+					inputs: vec![utxo::TransactionInput { outpoint, ..Default::default() }],
+					outputs: vec![utxo::TransactionOutput {
+						value: 80,
+						pubkey: H256::repeat_byte(2),
+						lock: utxo::Lock::Pubkey,
+						data: None,
+						confidential: None,
+					}],
+					valid_from,
+					..Default::default()
+				};
+				arguments.insert(0, script_signature(&transaction, 0, suri));
+				transaction.inputs[0].redeemer =
+					Some(Redeemer { validator: validator.clone(), arguments });
+				transaction
+			};
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &5u32.encode());
+
+			let mut claim = spend(None, "//Bob", vec![vec![1]]);
+			let redeemer = claim.inputs[0].redeemer.take();
+			assert_eq!(
+				utxo::validate_transaction(&claim),
+				Err("Spending a script output requires a redeemer")
+			);
+			claim.inputs[0].redeemer =
+				Some(Redeemer { validator: vec![OP_TRUE], arguments: Vec::new() });
+			assert_eq!(
+				utxo::validate_transaction(&claim),
+				Err("Validator does not match the script hash")
+			);
+			claim.inputs[0].redeemer = redeemer;
+			claim.inputs[0].pubkey = Some(H256::from(bob));
+			assert_eq!(
+				utxo::validate_transaction(&claim),
+				Err("Only pubkey hash outputs take a revealed key")
+			);
+			claim.inputs[0].pubkey = None;
+			assert_ok!(utxo::validate_transaction(&claim));
+
+			// Only Bob's signature is accepted for the claim, and Alice has to wait
+			assert_eq!(
+				utxo::validate_transaction(&spend(None, "//Charlie", vec![vec![1]])),
+				Err("Script did not accept the spend")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&spend(None, ALICE_PHRASE, vec![vec![]])),
+				Err("Transaction has no start")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&spend(Some(5), ALICE_PHRASE, vec![vec![]])),
+				Err("Script did not accept the spend")
+			);
+			sp_io::storage::set(&utxo::BLOCK_NUMBER_KEY, &10u32.encode());
+			assert_eq!(
+				utxo::validate_transaction(&spend(Some(10), "//Bob", vec![vec![]])),
+				Err("Script did not accept the spend")
+			);
+			assert_ok!(utxo::spend(spend(Some(10), ALICE_PHRASE, vec![vec![]])));
+			assert!(!sp_io::storage::exists(&outpoint.encode()));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_script_datum_carries_state() {
+		use script::*;
+
+		new_test_ext().execute_with(|| {
+			let keystore = KeyStore::new();
+			let alice_pub_key =
+				keystore.sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
+
+			// A counter: the first output has to continue the contract with the next count
+			let validator = [
+				OP_FALSE,
+				OP_OUTPUTVALIDATOR,
+				OP_VALIDATOR,
+				OP_EQUALVERIFY,
+				OP_FALSE,
+				OP_OUTPUTDATUM,
+				OP_DATUM,
+				OP_TRUE,
+				OP_ADD,
+				OP_EQUAL,
+			];
+			let counter = |value: utxo::Value, count: utxo::Value| utxo::TransactionOutput {
+				value,
+				pubkey: H256::zero(),
+				lock: utxo::Lock::Script {
+					validator: validator_hash(&validator),
+					datum: encode_number(count),
+				},
+				data: None,
+				confidential: None,
+			};
+			let create = |output: utxo::TransactionOutput| {
+				let mut transaction = utxo::Transaction {
+					inputs: vec![utxo::TransactionInput {
+						outpoint: H256::from(GENESIS_UTXO),
+						..Default::default()
+					}],
+					outputs: vec![output],
+					..Default::default()
+				};
+				sign_inputs(&mut transaction, &alice_pub_key);
+				transaction
+			};
+			let mut too_large = counter(90, 0);
+			too_large.lock = utxo::Lock::Script {
+				validator: validator_hash(&validator),
+				datum: vec![1; MAX_ITEM_SIZE + 1],
+			};
+			assert_eq!(utxo::validate_transaction(&create(too_large)), Err("Datum is too large"));
+
+			let transaction = create(counter(90, 0));
+			let outpoint =
+				BlakeTwo256::hash_of(&(&utxo::get_stripped_transaction(&transaction), 0u64));
+			assert_ok!(utxo::spend(transaction));
+
+			let redeemer = Redeemer { validator: validator.to_vec(), arguments: Vec::new() };
+			let increment = |output: utxo::TransactionOutput| utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint,
+					redeemer: Some(redeemer.clone()),
+					..Default::default()
+				}],
+				outputs: vec![output],
+				..Default::default()
+			};
+			assert_eq!(
+				utxo::validate_transaction(&increment(counter(80, 2))),
+				Err("Script did not accept the spend")
+			);
+			assert_eq!(
+				utxo::validate_transaction(&increment(utxo::TransactionOutput {
+					value: 80,
+					pubkey: H256::from(alice_pub_key),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				})),
+				Err("Script did not accept the spend")
+			);
+			let transaction = increment(counter(80, 1));
+			let next = BlakeTwo256::hash_of(&(&utxo::get_stripped_transaction(&transaction), 0u64));
+			assert_ok!(utxo::spend(transaction));
+			let stored = sp_io::storage::get(&next.encode()).unwrap();
+			assert_eq!(utxo::TransactionOutput::decode(&mut &stored[..]).unwrap(), counter(80, 1));
+		})
+	}
+
+	#[test]
+	fn utxo_frameless_psbt_redeemer_completes_script_input() {
+		use script::*;
+
+		new_test_ext().execute_with(|| {
+			let validator = vec![OP_DATUM];
+			let prevout = utxo::TransactionOutput {
+				value: 50,
+				pubkey: H256::zero(),
+				lock: utxo::Lock::Script { validator: validator_hash(&validator), datum: vec![1] },
+				data: None,
+				confidential: None,
+			};
+			let transaction = utxo::Transaction {
+				inputs: vec![utxo::TransactionInput {
+					outpoint: H256::repeat_byte(1),
+					..Default::default()
+				}],
+				outputs: vec![utxo::TransactionOutput {
+					value: 40,
+					pubkey: H256::repeat_byte(2),
+					lock: utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
+				}],
+				..Default::default()
+			};
+			let mut psbt = psbt::PartiallySignedTransaction::new(
+				transaction,
+				vec![prevout],
+				H256::from(TEST_GENESIS_HASH),
+			)
+			.unwrap();
+			assert_eq!(psbt.clone().finalize(), Err("Missing signature for an input"));
+			assert_eq!(
+				psbt.add_redeemer(0, Redeemer { validator: vec![OP_TRUE], arguments: Vec::new() }),
+				Err("Validator does not match the script hash")
+			);
+
+			let redeemer = Redeemer { validator, arguments: Vec::new() };
+			let mut other = psbt.clone();
+			other.add_redeemer(0, redeemer.clone()).unwrap();
+			assert!(other.is_complete());
+			psbt.merge(other).unwrap();
+			assert!(psbt.is_complete());
+			assert_eq!(psbt.finalize().unwrap().inputs[0].redeemer, Some(redeemer));
+		})
+	}
+
 }
@@ -0,0 +1,169 @@
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_runtime::transaction_validity::InvalidTransaction;
+
+use crate::{commitment, utxo::Transaction};
+
+/// Storage key of the active `Limits`
+pub const LIMITS_KEY: [u8; 6] = *b"limits";
+
+/// Storage key of the `BlockUsage` of the block being built or executed.
+/// Cleared when the block is finished.
+pub const BLOCK_USAGE_KEY: [u8; 11] = *b"block_usage";
+
+/// Abstract measure of the time it takes to execute a transaction
+pub type Weight = u64;
+
+/// Weight of every transaction, regardless of its content
+pub const BASE_TRANSACTION_WEIGHT: Weight = 10_000;
+
+/// Weight of reading the UTXO spent by an input and verifying its signature
+pub const INPUT_WEIGHT: Weight = 100_000;
+
+/// Weight of writing the UTXO created by an output
+pub const OUTPUT_WEIGHT: Weight = 20_000;
+
+/// Weight of one level of the UTXO commitment: reading the node and its sibling, hashing and
+/// writing the node
+pub const COMMITMENT_NODE_WEIGHT: Weight = 25_000;
+
+/// Weight of updating the UTXO commitment for a UTXO created or spent, on top of `INPUT_WEIGHT`
+/// and `OUTPUT_WEIGHT`. Only the branches above the leaf are rewritten, see
+/// `commitment::insert`.
+pub const COMMITMENT_UPDATE_WEIGHT: Weight =
+	COMMITMENT_NODE_WEIGHT * commitment::MAX_EXPECTED_DEPTH as Weight;
+
+/// Weight of verifying the range proof of a confidential output, on top of `OUTPUT_WEIGHT`
+pub const RANGE_PROOF_WEIGHT: Weight = 10_000_000;
+
+/// Weight of running the validator of a script input up to `script::MAX_COST`, on top of
+/// `INPUT_WEIGHT`
+pub const SCRIPT_WEIGHT: Weight = 5_000_000;
+
+/// Resource limits for transactions and blocks, set in genesis and held in state
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct Limits {
+	/// Maximum length of an encoded transaction in bytes
+	pub max_transaction_size: u32,
+
+	/// Maximum number of inputs of a transaction
+	pub max_inputs: u32,
+
+	/// Maximum number of outputs of a transaction
+	pub max_outputs: u32,
+
+	/// Maximum total length of the extrinsics of a block in bytes
+	pub max_block_length: u32,
+
+	/// Maximum total weight of the extrinsics of a block
+	pub max_block_weight: Weight,
+}
+
+impl Default for Limits {
+	fn default() -> Self {
+		Limits {
+			max_transaction_size: 64 * 1024,
+			max_inputs: 256,
+			max_outputs: 256,
+			max_block_length: 4 * 1024 * 1024,
+			max_block_weight: 1_000_000_000,
+		}
+	}
+}
+
+/// Resources used so far by the block being built or executed
+#[derive(PartialEq, Eq, Clone, Copy, Encode, Decode, Debug, Default)]
+pub struct BlockUsage {
+	pub length: u32,
+	pub weight: Weight,
+}
+
+/// Limits currently held in state, or the defaults if none were set
+pub fn get() -> Limits {
+	sp_io::storage::get(&LIMITS_KEY)
+		.and_then(|raw_limits| Limits::decode(&mut &raw_limits[..]).ok())
+		.unwrap_or_default()
+}
+
+/// Weight of a transaction based on the work done for its inputs and outputs
+pub fn transaction_weight(transaction: &Transaction) -> Weight {
+	// Data outputs are never added to the UTXO set, nor to its commitment
+	let updates = transaction.inputs.len() +
+		transaction.outputs.iter().filter(|output| output.is_spendable()).count();
+	let range_proofs =
+		transaction.outputs.iter().filter(|output| output.confidential.is_some()).count();
+	let scripts = transaction.inputs.iter().filter(|input| input.redeemer.is_some()).count();
+	BASE_TRANSACTION_WEIGHT
+		.saturating_add(INPUT_WEIGHT.saturating_mul(transaction.inputs.len() as Weight))
+		.saturating_add(OUTPUT_WEIGHT.saturating_mul(transaction.outputs.len() as Weight))
+		.saturating_add(RANGE_PROOF_WEIGHT.saturating_mul(range_proofs as Weight))
+		.saturating_add(SCRIPT_WEIGHT.saturating_mul(scripts as Weight))
+		.saturating_add(COMMITMENT_UPDATE_WEIGHT.saturating_mul(updates as Weight))
+}
+
+/// Check a transaction on its own against the limits. A transaction that
+/// does not fit into an empty block is rejected as well.
+pub fn check_transaction(transaction: &Transaction, limits: &Limits) -> Result<(), InvalidTransaction> {
+	let size = transaction.encoded_size();
+	if size > limits.max_transaction_size as usize || size > limits.max_block_length as usize {
+		return Err(InvalidTransaction::ExhaustsResources)
+	}
+	if transaction.inputs.len() > limits.max_inputs as usize ||
+		transaction.outputs.len() > limits.max_outputs as usize
+	{
+		return Err(InvalidTransaction::ExhaustsResources)
+	}
+	if transaction_weight(transaction) > limits.max_block_weight {
+		return Err(InvalidTransaction::ExhaustsResources)
+	}
+	Ok(())
+}
+
+/// Check the extrinsics of an imported block as a whole. Failing extrinsics are left out of
+/// the block usage, but they are still decoded, hashed and partly verified, so all `count`
+/// extrinsics with a total encoded length of `length` have to fit into the length limit, each
+/// weighing at least `BASE_TRANSACTION_WEIGHT`.
+pub fn check_block(count: usize, length: usize, limits: &Limits) -> Result<(), InvalidTransaction> {
+	let weight = BASE_TRANSACTION_WEIGHT.saturating_mul(count as Weight);
+	if length > limits.max_block_length as usize || weight > limits.max_block_weight {
+		return Err(InvalidTransaction::ExhaustsResources)
+	}
+	Ok(())
+}
+
+/// Resources used by the current block so far
+pub fn block_usage() -> BlockUsage {
+	sp_io::storage::get(&BLOCK_USAGE_KEY)
+		.and_then(|raw_usage| BlockUsage::decode(&mut &raw_usage[..]).ok())
+		.unwrap_or_default()
+}
+
+/// Block usage after including `transaction`, with an encoded length of `length`.
+/// Fails with `ExhaustsResources` once the block is full so the block author stops adding
+/// transactions. Nothing is written, see `set_block_usage`.
+pub fn usage_with(transaction: &Transaction, length: usize) -> Result<BlockUsage, InvalidTransaction> {
+	let limits = get();
+	check_transaction(transaction, &limits)?;
+
+	let usage = block_usage();
+	let new_usage = BlockUsage {
+		length: usage.length.saturating_add(length as u32),
+		weight: usage.weight.saturating_add(transaction_weight(transaction)),
+	};
+	if new_usage.length > limits.max_block_length || new_usage.weight > limits.max_block_weight {
+		return Err(InvalidTransaction::ExhaustsResources)
+	}
+	Ok(new_usage)
+}
+
+pub fn set_block_usage(usage: BlockUsage) {
+	sp_io::storage::set(&BLOCK_USAGE_KEY, &usage.encode());
+}
+
+/// Forget the usage of a finished block, it must not end up in the state root
+pub fn clear_block_usage() {
+	sp_io::storage::clear(&BLOCK_USAGE_KEY);
+}
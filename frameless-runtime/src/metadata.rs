@@ -0,0 +1,254 @@
+//! Metadata of the frameless runtime, returned by `Metadata_metadata`.
+//!
+//! This runtime has no pallets, so FRAME's metadata format does not fit. Instead the
+//! metadata is its own format built from `scale-info`, encoded as:
+//!
+//! 1. `META_RESERVED`, the `meta` magic number also used by FRAME metadata, as `u32`
+//! 2. `FRAMELESS_METADATA_VERSION` as `u8`. It is outside of the range of FRAME metadata versions
+//!    so tools expecting FRAME metadata reject it instead of misreading it.
+//! 3. `RuntimeMetadata`
+//!
+//! Every type in `RuntimeMetadata` is referenced by its id in `RuntimeMetadata::types`.
+
+use parity_scale_codec::Encode;
+use scale_info::{interner::UntrackedSymbol, meta_type, PortableRegistry, Registry};
+use sp_core::H256;
+use core::any::TypeId;
+use sp_std::prelude::*;
+
+use crate::{
+	commitment, limits, pow, utxo, BasicExtrinsic, BlockNumber, Header, AURA_AUTHORITIES_KEY,
+	GRANDPA_AUTHORITIES_KEY, RUNTIME_API_VERSIONS, SLOT_DURATION_KEY,
+};
+
+/// Magic number at the start of the metadata, `meta` in little endian
+pub const META_RESERVED: u32 = 0x6174656d;
+
+/// Version of the frameless metadata format
+pub const FRAMELESS_METADATA_VERSION: u8 = 100;
+
+/// Reference to a type in `RuntimeMetadata::types`
+pub type TypeRef = UntrackedSymbol<TypeId>;
+
+/// Description of the runtime, enough for a client to decode blocks and storage
+#[derive(Encode)]
+pub struct RuntimeMetadata {
+	/// Registry of every type referenced in the metadata
+	pub types: PortableRegistry,
+
+	/// How blocks and their extrinsics are encoded
+	pub block: BlockMetadata,
+
+	/// Storage items of the runtime
+	pub storage: Vec<StorageEntryMetadata>,
+
+	/// Runtime APIs implemented by the runtime
+	pub apis: Vec<RuntimeApiMetadata>,
+}
+
+/// Types making up a block. A block is encoded as its header followed by
+/// the SCALE encoded `Vec` of its extrinsics.
+#[derive(Encode)]
+pub struct BlockMetadata {
+	/// Block header
+	pub header: TypeRef,
+
+	/// Extrinsic, `BasicExtrinsic`
+	pub extrinsic: TypeRef,
+
+	/// Transaction wrapped by each extrinsic, `utxo::Transaction`
+	pub transaction: TypeRef,
+}
+
+/// A value, or a map of values, in storage
+#[derive(Encode)]
+pub struct StorageEntryMetadata {
+	/// Name of the item
+	pub name: &'static str,
+
+	/// Storage key of a plain value, or prefix of the keys of a map
+	pub key: Vec<u8>,
+
+	/// For maps, the type whose SCALE encoding is appended to `key` to build the key of an entry.
+	/// No hashing is applied.
+	pub map_key: Option<TypeRef>,
+
+	/// Type of the stored value(s)
+	pub value: TypeRef,
+
+	/// Documentation of the item
+	pub docs: Vec<&'static str>,
+}
+
+/// A runtime API as listed in the runtime version
+#[derive(Encode)]
+pub struct RuntimeApiMetadata {
+	/// Name of the API trait
+	pub name: &'static str,
+
+	/// Identifier of the API, the blake2_64 hash of its name
+	pub id: [u8; 8],
+
+	/// Version of the API implemented by this runtime
+	pub version: u32,
+}
+
+/// Names of the runtime APIs implemented in `impl_runtime_apis!`
+const RUNTIME_API_NAMES: [&str; 10] = [
+	"Core",
+	"BlockBuilder",
+	"TaggedTransactionQueue",
+	"Metadata",
+	"UtxoCommitmentApi",
+	"DifficultyApi",
+	"OffchainWorkerApi",
+	"SessionKeys",
+	"AuraApi",
+	"GrandpaApi",
+];
+
+/// Build the metadata of this runtime
+pub fn runtime_metadata() -> RuntimeMetadata {
+	let mut registry = Registry::new();
+
+	let block = BlockMetadata {
+		header: registry.register_type(&meta_type::<Header>()),
+		extrinsic: registry.register_type(&meta_type::<BasicExtrinsic>()),
+		transaction: registry.register_type(&meta_type::<utxo::Transaction>()),
+	};
+
+	let storage = vec![
+		StorageEntryMetadata {
+			name: "Utxos",
+			key: Vec::new(),
+			map_key: Some(registry.register_type(&meta_type::<H256>())),
+			value: registry.register_type(&meta_type::<utxo::TransactionOutput>()),
+			docs: vec![
+				"Unspent transaction outputs, keyed by their outpoint.",
+				"The outpoint of an output is the hash of the stripped transaction",
+				"that created it together with the output's index.",
+			],
+		},
+		StorageEntryMetadata {
+			name: "UtxoCommitment",
+			key: commitment::NODE_PREFIX.to_vec(),
+			map_key: Some(registry.register_type(&meta_type::<(u16, H256)>())),
+			value: registry.register_type(&meta_type::<commitment::Node>()),
+			docs: vec![
+				"Stored nodes of the sparse Merkle tree committing to the UTXO set, keyed by",
+				"their height and the path to them with the bits below the height cleared.",
+				"A subtree holding a single UTXO is a leaf, one holding more is a branch.",
+				"The root is the node at height 256.",
+			],
+		},
+		StorageEntryMetadata {
+			name: "GenesisHash",
+			key: utxo::GENESIS_HASH_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<H256>()),
+			docs: vec!["Hash of the genesis block, written when block 1 is initialized."],
+		},
+		StorageEntryMetadata {
+			name: "BlockNumber",
+			key: utxo::BLOCK_NUMBER_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<BlockNumber>()),
+			docs: vec!["Number of the latest initialized block."],
+		},
+		StorageEntryMetadata {
+			name: "TotalIssuance",
+			key: utxo::TOTAL_ISSUANCE_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<utxo::Value>()),
+			docs: vec!["Sum of the values of all UTXOs."],
+		},
+		StorageEntryMetadata {
+			name: "ShieldedValue",
+			key: utxo::SHIELDED_VALUE_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<utxo::Value>()),
+			docs: vec!["Total value hidden in confidential UTXOs, part of the total issuance."],
+		},
+		StorageEntryMetadata {
+			name: "FeeIncrement",
+			key: utxo::FEE_INCREMENT_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<utxo::Value>()),
+			docs: vec!["Fee step a replacement transaction has to pay on top of the one it replaces."],
+		},
+		StorageEntryMetadata {
+			name: "DustLimit",
+			key: utxo::DUST_LIMIT_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<utxo::Value>()),
+			docs: vec!["Minimum value of a spendable output."],
+		},
+		StorageEntryMetadata {
+			name: "ProofOfWork",
+			key: pow::PROOF_OF_WORK_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<bool>()),
+			docs: vec!["Whether blocks are mined with proof of work and carry a coinbase."],
+		},
+		StorageEntryMetadata {
+			name: "Difficulty",
+			key: pow::DIFFICULTY_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<sp_core::U256>()),
+			docs: vec!["Difficulty the next block mined with proof of work has to meet."],
+		},
+		StorageEntryMetadata {
+			name: "LastTimestamp",
+			key: pow::LAST_TIMESTAMP_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<u64>()),
+			docs: vec!["Timestamp of the latest coinbase in milliseconds."],
+		},
+		StorageEntryMetadata {
+			name: "SlotDuration",
+			key: SLOT_DURATION_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<u64>()),
+			docs: vec!["Aura slot duration in milliseconds."],
+		},
+		StorageEntryMetadata {
+			name: "AuraAuthorities",
+			key: AURA_AUTHORITIES_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<Vec<sp_consensus_aura::sr25519::AuthorityId>>()),
+			docs: vec!["Aura authorities."],
+		},
+		StorageEntryMetadata {
+			name: "GrandpaAuthorities",
+			key: GRANDPA_AUTHORITIES_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<sp_finality_grandpa::AuthorityList>()),
+			docs: vec!["Grandpa authorities with their voting weights."],
+		},
+		StorageEntryMetadata {
+			name: "Limits",
+			key: limits::LIMITS_KEY.to_vec(),
+			map_key: None,
+			value: registry.register_type(&meta_type::<limits::Limits>()),
+			docs: vec!["Size limits of transactions and blocks."],
+		},
+	];
+
+	let apis = RUNTIME_API_NAMES
+		.iter()
+		.filter_map(|&name| {
+			let id = sp_io::hashing::blake2_64(name.as_bytes());
+			RUNTIME_API_VERSIONS
+				.iter()
+				.find(|(api_id, _)| *api_id == id)
+				.map(|(_, version)| RuntimeApiMetadata { name, id, version: *version })
+		})
+		.collect();
+
+	RuntimeMetadata { types: registry.into(), block, storage, apis }
+}
+
+/// Metadata in the encoding returned by `Metadata_metadata`
+pub fn encoded_metadata() -> Vec<u8> {
+	(META_RESERVED, FRAMELESS_METADATA_VERSION, runtime_metadata()).encode()
+}
@@ -0,0 +1,315 @@
+//! Aggregated Schnorr signatures of several parties (MuSig).
+//!
+//! The keys `X_1..X_n` of the participants aggregate into the key `X = Σ a_i·X_i`, where
+//! `a_i = H(L, X_i)` and `L` commits to the sorted list of keys, so nobody can pick their key
+//! to cancel out the others. An aggregate signature is an ordinary sr25519 signature of `X`:
+//! an output locked to `X` is a plain `Lock::Pubkey` output and the chain can tell neither
+//! that it belongs to several parties nor who they are. Spending it takes a signature of every
+//! participant, combined into one.
+//!
+//! Signing takes three rounds between the participants, each run by a `Session`:
+//! 1. every participant picks a secret nonce `r_i` and sends a commitment to `R_i = r_i·G`,
+//! 2. once all commitments are in, every participant reveals `R_i`,
+//! 3. once all nonces are in and match their commitments, every participant sends the partial
+//!    signature `s_i = r_i + k·a_i·x_i`, where `k` is the sr25519 challenge of `X`, the message
+//!    and `R = Σ R_i`.
+//! Anyone can then check the partial signatures and combine them into the signature `(R, Σ s_i)`.
+//! Committing to the nonces first keeps the last participant to reveal from choosing theirs
+//! based on the others.
+
+use curve25519_dalek::{
+	constants::RISTRETTO_BASEPOINT_TABLE,
+	ristretto::{CompressedRistretto, RistrettoPoint},
+	scalar::Scalar,
+	traits::Identity,
+};
+use merlin::Transcript;
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::{H256, H512};
+use sp_std::prelude::*;
+
+use crate::{ensure, fail};
+
+/// Signing context of sr25519 signatures in Substrate
+const SIGNING_CONTEXT: &[u8] = b"substrate";
+
+/// Domain separator of the hash the key coefficients are derived from
+const KEY_COEFFICIENT_CONTEXT: &[u8] = b"frameless-utxo-musig-key";
+
+/// Domain separator of the nonce commitments
+const NONCE_COMMITMENT_CONTEXT: &[u8] = b"frameless-utxo-musig-nonce";
+
+/// First round message: commitment of a participant to its nonce
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct NonceCommitment {
+	pub signer: H256,
+	pub commitment: H256,
+}
+
+/// Second round message: the nonce of a participant
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct NonceReveal {
+	pub signer: H256,
+	pub nonce: H256,
+}
+
+/// Third round message: the share of a participant in the aggregate signature
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct PartialSignature {
+	pub signer: H256,
+
+	/// Nonce of the participant, `R_i`
+	pub nonce: H256,
+
+	/// Sum of the nonces of all participants, `R`
+	pub aggregate_nonce: H256,
+
+	/// Share `s_i` of the signature
+	pub signature: H256,
+}
+
+/// Signing state of one participant. It holds the secret nonce, so it must stay with the
+/// participant and must never be used to sign twice: two partial signatures with the same
+/// nonce reveal the secret key.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct Session {
+	/// Keys of all participants, sorted
+	pub keys: Vec<H256>,
+
+	/// Key of this participant
+	pub signer: H256,
+
+	/// Message being signed
+	pub message: Vec<u8>,
+
+	/// Secret nonce `r_i` of this participant
+	pub secret_nonce: H256,
+
+	/// Nonce commitments of all participants in the order of `keys`, once collected
+	pub commitments: Option<Vec<H256>>,
+
+	/// Nonces of all participants in the order of `keys`, once collected
+	pub nonces: Option<Vec<H256>>,
+}
+
+impl Session {
+	/// Start signing `message` as `signer`, one of `keys`. `nonce_seed` must be fresh
+	/// randomness, it is turned into the secret nonce.
+	pub fn new(
+		keys: Vec<H256>,
+		signer: H256,
+		message: Vec<u8>,
+		nonce_seed: &[u8; 64],
+	) -> Result<Self, &'static str> {
+		let keys = sorted_keys(keys)?;
+		ensure!(keys.contains(&signer), "The signer is not one of the keys");
+		let secret_nonce = Scalar::from_bytes_mod_order_wide(nonce_seed);
+		Ok(Self {
+			keys,
+			signer,
+			message,
+			secret_nonce: H256(secret_nonce.to_bytes()),
+			commitments: None,
+			nonces: None,
+		})
+	}
+
+	/// Key the aggregate signature is valid for
+	pub fn aggregate_key(&self) -> Result<H256, &'static str> {
+		aggregate_key(&self.keys)
+	}
+
+	/// First round: commitment to the nonce of this participant
+	pub fn commitment(&self) -> Result<NonceCommitment, &'static str> {
+		let nonce = self.nonce()?;
+		Ok(NonceCommitment { signer: self.signer, commitment: nonce_commitment(&nonce) })
+	}
+
+	/// Record the nonce commitments of all participants, including this one
+	pub fn add_commitments(&mut self, commitments: &[NonceCommitment]) -> Result<(), &'static str> {
+		ensure!(self.commitments.is_none(), "Nonce commitments were already collected");
+		let commitments = in_key_order(&self.keys, commitments, |c| (c.signer, c.commitment))?;
+		let own = self.commitment()?;
+		ensure!(
+			commitments[self.position()] == own.commitment,
+			"Nonce commitment of this signer does not match its nonce"
+		);
+		self.commitments = Some(commitments);
+		Ok(())
+	}
+
+	/// Second round: the nonce of this participant, only revealed once all commitments are in
+	pub fn reveal(&self) -> Result<NonceReveal, &'static str> {
+		ensure!(self.commitments.is_some(), "Nonce commitments have not been collected yet");
+		Ok(NonceReveal { signer: self.signer, nonce: self.nonce()? })
+	}
+
+	/// Record the nonces of all participants, checking them against their commitments
+	pub fn add_nonces(&mut self, nonces: &[NonceReveal]) -> Result<(), &'static str> {
+		let commitments = self
+			.commitments
+			.as_ref()
+			.ok_or("Nonce commitments have not been collected yet")?;
+		ensure!(self.nonces.is_none(), "Nonces were already collected");
+		let nonces = in_key_order(&self.keys, nonces, |n| (n.signer, n.nonce))?;
+		for (nonce, commitment) in nonces.iter().zip(commitments.iter()) {
+			ensure!(nonce_commitment(nonce) == *commitment, "Nonce does not match its commitment");
+		}
+		self.nonces = Some(nonces);
+		Ok(())
+	}
+
+	/// Third round: the partial signature of this participant, whose secret key is `secret`
+	pub fn sign(&self, secret: &Scalar) -> Result<PartialSignature, &'static str> {
+		let nonces = self.nonces.as_ref().ok_or("Nonces have not been collected yet")?;
+		ensure!(public_key(secret) == self.signer, "Secret key does not belong to the signer");
+
+		let aggregate_nonce = sum_points(nonces)?;
+		let challenge = challenge(&self.message, &self.aggregate_key()?, &aggregate_nonce);
+		let coefficient = key_coefficient(&self.keys, &self.signer);
+		let signature = scalar(&self.secret_nonce)? + challenge * coefficient * secret;
+		Ok(PartialSignature {
+			signer: self.signer,
+			nonce: nonces[self.position()],
+			aggregate_nonce,
+			signature: H256(signature.to_bytes()),
+		})
+	}
+
+	fn nonce(&self) -> Result<H256, &'static str> {
+		Ok(public_key(&scalar(&self.secret_nonce)?))
+	}
+
+	fn position(&self) -> usize {
+		self.keys
+			.iter()
+			.position(|key| *key == self.signer)
+			.expect("The signer is checked to be one of the keys; qed")
+	}
+}
+
+/// Aggregate key of the participants with `keys`, in any order
+pub fn aggregate_key(keys: &[H256]) -> Result<H256, &'static str> {
+	let keys = sorted_keys(keys.to_vec())?;
+	let mut aggregate = RistrettoPoint::identity();
+	for key in keys.iter() {
+		aggregate += key_coefficient(&keys, key) * point(key)?;
+	}
+	Ok(H256(aggregate.compress().to_bytes()))
+}
+
+/// Check the partial signatures of all participants on `message` and combine them into
+/// the aggregate signature. The participants are the signers of `partials`.
+pub fn aggregate_signature(
+	message: &[u8],
+	partials: &[PartialSignature],
+) -> Result<H512, &'static str> {
+	let keys = sorted_keys(partials.iter().map(|partial| partial.signer).collect())?;
+	let aggregate_key = aggregate_key(&keys)?;
+	let aggregate_nonce = partials.first().ok_or("No partial signatures")?.aggregate_nonce;
+	let nonces: Vec<_> = partials.iter().map(|partial| partial.nonce).collect();
+	ensure!(
+		partials.iter().all(|partial| partial.aggregate_nonce == aggregate_nonce) &&
+			sum_points(&nonces)? == aggregate_nonce,
+		"Partial signatures were made with different nonces"
+	);
+
+	let challenge = challenge(message, &aggregate_key, &aggregate_nonce);
+	let mut signature = Scalar::zero();
+	for partial in partials {
+		let share = scalar(&partial.signature)?;
+		let expected = point(&partial.nonce)? +
+			challenge * key_coefficient(&keys, &partial.signer) * point(&partial.signer)?;
+		ensure!(&share * &RISTRETTO_BASEPOINT_TABLE == expected, "Invalid partial signature");
+		signature += share;
+	}
+
+	let mut bytes = [0u8; 64];
+	bytes[..32].copy_from_slice(aggregate_nonce.as_bytes());
+	bytes[32..].copy_from_slice(signature.as_bytes());
+	// Marks the signature as schnorrkel's, not a legacy one
+	bytes[63] |= 128;
+	Ok(H512(bytes))
+}
+
+/// Sort the keys, rejecting duplicates
+fn sorted_keys(mut keys: Vec<H256>) -> Result<Vec<H256>, &'static str> {
+	ensure!(!keys.is_empty(), "No keys to aggregate");
+	keys.sort();
+	ensure!(keys.windows(2).all(|pair| pair[0] != pair[1]), "Keys must be distinct");
+	Ok(keys)
+}
+
+/// Coefficient `a_i` of `key` in the aggregate of the sorted `keys`
+fn key_coefficient(keys: &[H256], key: &H256) -> Scalar {
+	let keys: Vec<u8> = keys.iter().flat_map(|key| key.to_fixed_bytes()).collect();
+	let key_list = sp_io::hashing::blake2_256(&keys);
+	let preimage = [KEY_COEFFICIENT_CONTEXT, &key_list[..], key.as_bytes()].concat();
+	Scalar::from_bytes_mod_order_wide(&sp_io::hashing::blake2_512(&preimage))
+}
+
+fn nonce_commitment(nonce: &H256) -> H256 {
+	H256(sp_io::hashing::blake2_256(&[NONCE_COMMITMENT_CONTEXT, nonce.as_bytes()].concat()))
+}
+
+/// Challenge of an sr25519 signature of `message` by `key` with nonce `nonce`, as computed by
+/// schnorrkel when verifying it
+fn challenge(message: &[u8], key: &H256, nonce: &H256) -> Scalar {
+	let mut transcript = Transcript::new(b"SigningContext");
+	transcript.append_message(b"", SIGNING_CONTEXT);
+	transcript.append_message(b"sign-bytes", message);
+	transcript.append_message(b"proto-name", b"Schnorr-sig");
+	transcript.append_message(b"sign:pk", key.as_bytes());
+	transcript.append_message(b"sign:R", nonce.as_bytes());
+	let mut challenge = [0u8; 64];
+	transcript.challenge_bytes(b"sign:c", &mut challenge);
+	Scalar::from_bytes_mod_order_wide(&challenge)
+}
+
+/// Values of the messages of all participants in the order of `keys`
+fn in_key_order<T>(
+	keys: &[H256],
+	messages: &[T],
+	signer_and_value: impl Fn(&T) -> (H256, H256),
+) -> Result<Vec<H256>, &'static str> {
+	ensure!(messages.len() == keys.len(), "Exactly one message per participant is required");
+	keys.iter()
+		.map(|key| {
+			messages
+				.iter()
+				.map(&signer_and_value)
+				.find(|(signer, _)| signer == key)
+				.map(|(_, value)| value)
+				.ok_or("Missing the message of a participant")
+		})
+		.collect()
+}
+
+fn sum_points(points: &[H256]) -> Result<H256, &'static str> {
+	let mut sum = RistrettoPoint::identity();
+	for p in points {
+		sum += point(p)?;
+	}
+	Ok(H256(sum.compress().to_bytes()))
+}
+
+fn point(key: &H256) -> Result<RistrettoPoint, &'static str> {
+	CompressedRistretto(key.to_fixed_bytes())
+		.decompress()
+		.ok_or("Invalid public key")
+}
+
+fn scalar(bytes: &H256) -> Result<Scalar, &'static str> {
+	Scalar::from_canonical_bytes(bytes.to_fixed_bytes()).ok_or("Invalid scalar")
+}
+
+fn public_key(secret: &Scalar) -> H256 {
+	H256((secret * &RISTRETTO_BASEPOINT_TABLE).compress().to_bytes())
+}
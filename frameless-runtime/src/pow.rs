@@ -0,0 +1,216 @@
+//! Proof of work support: difficulty adjustment and coinbase rewards.
+//!
+//! Blocks mined by a proof of work node start with a coinbase, an inherent transaction without
+//! inputs. Its first output pays `BLOCK_REWARD` to the miner and its second output is a data
+//! output holding the timestamp of the block in milliseconds. The time between the coinbases of
+//! consecutive blocks drives the difficulty, which the node reads through
+//! `sp_consensus_pow::DifficultyApi` to check seals. Coinbases are only accepted on chains
+//! whose genesis enables proof of work, so Aura authors can neither mint rewards nor move the
+//! difficulty.
+
+use parity_scale_codec::{Decode, Encode};
+use sp_core::{H256, U256};
+use sp_inherents::{CheckInherentsResult, InherentData, InherentIdentifier, IsFatalError};
+use sp_runtime::traits::{BlakeTwo256, Hash};
+use sp_std::prelude::*;
+
+use crate::{
+	commitment, ensure, fail,
+	utxo::{self, Lock, Transaction, TransactionOutput, Value},
+	Block,
+};
+
+/// Storage key of the flag enabling proof of work, set in genesis
+pub const PROOF_OF_WORK_KEY: [u8; 13] = *b"proof_of_work";
+
+/// Storage key of the difficulty a block built on the current state has to meet
+pub const DIFFICULTY_KEY: [u8; 10] = *b"difficulty";
+
+/// Storage key of the timestamp of the latest coinbase, in milliseconds
+pub const LAST_TIMESTAMP_KEY: [u8; 14] = *b"last_timestamp";
+
+/// Difficulty used when none is set in genesis
+pub const DEFAULT_DIFFICULTY: u64 = 1_000_000;
+
+/// The difficulty never drops below this
+pub const MIN_DIFFICULTY: u64 = 1;
+
+/// Value minted by the coinbase of every block
+pub const BLOCK_REWARD: Value = 50;
+
+/// Block time the difficulty adjustment aims for, in milliseconds
+pub const TARGET_BLOCK_TIME: u64 = 6000;
+
+/// How far in the future of the importing node's clock a coinbase timestamp may be,
+/// in milliseconds
+pub const MAX_TIMESTAMP_DRIFT: u64 = 30_000;
+
+/// Inherent data holding the public key the coinbase reward is paid to
+pub const COINBASE_INHERENT_IDENTIFIER: InherentIdentifier = *b"coinbase";
+
+/// Inherent data holding the current time, the same identifier as `sp_timestamp`
+pub const TIMESTAMP_INHERENT_IDENTIFIER: InherentIdentifier = *b"timstap0";
+
+/// Why `check_inherents` rejected the coinbase of a block
+#[derive(Encode, Decode, Debug)]
+pub enum CoinbaseError {
+	/// The timestamp of the coinbase is more than `MAX_TIMESTAMP_DRIFT` ahead of the local clock
+	TimestampTooFarInFuture,
+}
+
+impl IsFatalError for CoinbaseError {
+	fn is_fatal_error(&self) -> bool {
+		true
+	}
+}
+
+/// Whether the chain is mined with proof of work, `false` unless genesis enables it
+pub fn is_enabled() -> bool {
+	sp_io::storage::get(&PROOF_OF_WORK_KEY)
+		.and_then(|raw_flag| bool::decode(&mut &raw_flag[..]).ok())
+		.unwrap_or_default()
+}
+
+/// Difficulty a block built on the current state has to meet
+pub fn difficulty() -> U256 {
+	sp_io::storage::get(&DIFFICULTY_KEY)
+		.and_then(|raw_difficulty| U256::decode(&mut &raw_difficulty[..]).ok())
+		.unwrap_or_else(|| U256::from(DEFAULT_DIFFICULTY))
+}
+
+/// Timestamp of the latest coinbase, `None` before the first mined block
+pub fn last_timestamp() -> Option<u64> {
+	sp_io::storage::get(&LAST_TIMESTAMP_KEY)
+		.and_then(|raw_timestamp| u64::decode(&mut &raw_timestamp[..]).ok())
+}
+
+/// Difficulty after a block found `elapsed` milliseconds after its parent.
+/// It moves a quarter of the way towards the difficulty that would have hit
+/// `TARGET_BLOCK_TIME`, so a single lucky or unlucky block does not swing it.
+pub fn next_difficulty(difficulty: U256, elapsed: u64) -> U256 {
+	let elapsed = elapsed.clamp(TARGET_BLOCK_TIME / 4, TARGET_BLOCK_TIME * 4);
+	let target = difficulty.saturating_mul(U256::from(TARGET_BLOCK_TIME)) / U256::from(elapsed);
+	let next = difficulty.saturating_mul(U256::from(3)).saturating_add(target) / U256::from(4);
+	next.max(U256::from(MIN_DIFFICULTY))
+}
+
+/// Whether a transaction is a coinbase. Other transactions always have inputs.
+pub fn is_coinbase(transaction: &Transaction) -> bool {
+	transaction.inputs.is_empty()
+}
+
+/// Coinbase of the current block paying the reward to `miner`. It is only valid in this
+/// block, which also makes its outpoint unique.
+pub fn coinbase(miner: H256, timestamp: u64) -> Transaction {
+	let block_number = utxo::block_number();
+	Transaction {
+		inputs: Vec::new(),
+		outputs: vec![
+			TransactionOutput {
+				value: BLOCK_REWARD,
+				pubkey: miner,
+				lock: Lock::Pubkey,
+				data: None,
+				confidential: None,
+			},
+			TransactionOutput {
+				value: 0,
+				pubkey: H256::zero(),
+				lock: Lock::Pubkey,
+				data: Some(timestamp.encode()),
+				confidential: None,
+			},
+		],
+		valid_from: Some(block_number),
+		valid_until: Some(block_number),
+		fee: None,
+	}
+}
+
+/// Timestamp carried by a coinbase, `None` if it has no valid timestamp output
+pub fn coinbase_timestamp(transaction: &Transaction) -> Option<u64> {
+	match transaction.outputs.get(1) {
+		Some(TransactionOutput { value: 0, data: Some(data), .. }) =>
+			u64::decode(&mut &data[..]).ok(),
+		_ => None,
+	}
+}
+
+/// Coinbase for the block being built, if the node asked for one by providing a miner key on
+/// a proof of work chain. The timestamp is raised above the previous one if the local clock
+/// lags behind.
+pub fn create_coinbase(data: &InherentData) -> Option<Transaction> {
+	if !is_enabled() {
+		return None
+	}
+	let miner = data.get_data::<H256>(&COINBASE_INHERENT_IDENTIFIER).ok()??;
+	let now = data.get_data::<u64>(&TIMESTAMP_INHERENT_IDENTIFIER).ok()??;
+	let timestamp = match last_timestamp() {
+		Some(last) => now.max(last.saturating_add(1)),
+		None => now,
+	};
+	Some(coinbase(miner, timestamp))
+}
+
+/// Pay the block reward and adjust the difficulty to the time since the previous coinbase.
+/// The coinbase must be the first extrinsic of the block and exactly follow `coinbase`.
+pub fn apply_coinbase(transaction: &Transaction) -> utxo::DispatchResult {
+	ensure!(is_enabled(), "Coinbases are only accepted on proof of work chains");
+	ensure!(
+		!sp_io::storage::exists(&crate::EXTRINSICS_KEY),
+		"Coinbase must be the first extrinsic of the block"
+	);
+	let block_number = utxo::block_number();
+	ensure!(
+		transaction.valid_from == Some(block_number) && transaction.valid_until == Some(block_number),
+		"Coinbase must only be valid in the current block"
+	);
+	ensure!(transaction.outputs.len() == 2, "Coinbase must have a reward and a timestamp output");
+	let reward = &transaction.outputs[0];
+	// A confidential reward would mint whatever its commitment hides instead of `BLOCK_REWARD`
+	ensure!(
+		reward.is_spendable() &&
+			reward.value == BLOCK_REWARD &&
+			reward.lock == Lock::Pubkey &&
+			reward.confidential.is_none(),
+		"Invalid coinbase reward"
+	);
+	ensure!(transaction.outputs[1].confidential.is_none(), "Invalid coinbase timestamp");
+	let timestamp = coinbase_timestamp(transaction).ok_or("Invalid coinbase timestamp")?;
+
+	if let Some(last) = last_timestamp() {
+		ensure!(timestamp > last, "Coinbase timestamp must be after the previous one");
+		let difficulty = next_difficulty(difficulty(), timestamp - last);
+		sp_io::storage::set(&DIFFICULTY_KEY, &difficulty.encode());
+	}
+	sp_io::storage::set(&LAST_TIMESTAMP_KEY, &timestamp.encode());
+
+	// Same outpoint as any other output, a coinbase has no signatures to strip
+	let outpoint = BlakeTwo256::hash_of(&(&transaction.encode(), 0u64));
+	sp_io::storage::set(&outpoint.encode(), &reward.encode());
+	commitment::insert(&mut commitment::RuntimeStore, &outpoint, reward);
+	utxo::mint(BLOCK_REWARD);
+	Ok(())
+}
+
+/// Reject blocks whose coinbase timestamp is too far ahead of the importing node's clock
+pub fn check_coinbase(block: &Block, data: &InherentData) -> CheckInherentsResult {
+	let mut result = CheckInherentsResult::new();
+	let now = match data.get_data::<u64>(&TIMESTAMP_INHERENT_IDENTIFIER) {
+		Ok(Some(now)) => now,
+		_ => return result,
+	};
+	let timestamp = block
+		.extrinsics
+		.first()
+		.filter(|extrinsic| is_coinbase(&extrinsic.0))
+		.and_then(|extrinsic| coinbase_timestamp(&extrinsic.0));
+	if let Some(timestamp) = timestamp {
+		if timestamp > now.saturating_add(MAX_TIMESTAMP_DRIFT) {
+			result
+				.put_error(COINBASE_INHERENT_IDENTIFIER, &CoinbaseError::TimestampTooFarInFuture)
+				.expect("This is the only error put into the result; qed");
+		}
+	}
+	result
+}
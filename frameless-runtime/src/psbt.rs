@@ -0,0 +1,249 @@
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::{H256, H512};
+use sp_std::prelude::*;
+
+use crate::{
+	script::{self, Redeemer},
+	utxo::{self, Lock, Transaction, TransactionOutput, Value},
+};
+
+/// A transaction that is being signed by several parties.
+/// Each signer adds signatures for the inputs they own, the partial results are
+/// merged and the transaction is finalized once every input is signed, or redeemed if it
+/// spends a script output.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct PartiallySignedTransaction {
+	/// Transaction to be signed. All of its sigscripts are zero.
+	pub transaction: Transaction,
+
+	/// Genesis hash of the chain the transaction is meant for
+	pub genesis_hash: H256,
+
+	/// Transaction version of the runtime the signatures are made for
+	pub transaction_version: u32,
+
+	/// Signing state of each input, in the same order as `transaction.inputs`
+	pub inputs: Vec<PsbtInput>,
+
+	/// Free form data passed along between signers, e.g. a payment description
+	pub metadata: Option<Vec<u8>>,
+}
+
+/// Signing state of a single input
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug, TypeInfo)]
+pub struct PsbtInput {
+	/// The UTXO being spent by this input
+	pub prevout: TransactionOutput,
+
+	/// Signature collected for this input so far
+	pub signature: Option<H512>,
+
+	/// Key revealed by the signer when `prevout` is locked to a pubkey hash
+	#[cfg_attr(feature = "std", serde(default))]
+	pub pubkey: Option<H256>,
+
+	/// Validator and arguments collected when `prevout` is locked to a script
+	#[cfg_attr(feature = "std", serde(default))]
+	pub redeemer: Option<Redeemer>,
+}
+
+impl PartiallySignedTransaction {
+	/// Start signing `transaction` for the chain with the given genesis hash, `prevouts` are
+	/// the UTXOs spent by its inputs in order. Any sigscripts, revealed keys and redeemers
+	/// already present in the transaction are dropped.
+	pub fn new(
+		mut transaction: Transaction,
+		prevouts: Vec<TransactionOutput>,
+		genesis_hash: H256,
+	) -> Result<Self, &'static str> {
+		if transaction.inputs.len() != prevouts.len() {
+			return Err("Exactly one previous output is required per input")
+		}
+		for input in transaction.inputs.iter_mut() {
+			input.sigscript = H512::zero();
+			input.pubkey = None;
+			input.redeemer = None;
+		}
+		let inputs = prevouts
+			.into_iter()
+			.map(|prevout| PsbtInput { prevout, signature: None, pubkey: None, redeemer: None })
+			.collect();
+
+		Ok(Self {
+			transaction,
+			genesis_hash,
+			transaction_version: crate::VERSION.transaction_version,
+			inputs,
+			metadata: None,
+		})
+	}
+
+	/// Message that has to be signed by the owner of input `index`
+	pub fn signing_payload(&self, index: usize) -> Result<Vec<u8>, &'static str> {
+		utxo::get_signing_payload(
+			&self.transaction,
+			index,
+			&self.genesis_hash,
+			self.transaction_version,
+		)
+	}
+
+	/// Record the signature for input `index`, replacing any previous one
+	pub fn add_signature(&mut self, index: usize, signature: H512) -> Result<(), &'static str> {
+		let input = self.inputs.get_mut(index).ok_or("Input index out of range")?;
+		input.signature = Some(signature);
+		Ok(())
+	}
+
+	/// Record the key revealed to spend input `index`, whose previous output is locked to
+	/// the hash of that key
+	pub fn reveal_pubkey(&mut self, index: usize, pubkey: H256) -> Result<(), &'static str> {
+		let input = self.inputs.get_mut(index).ok_or("Input index out of range")?;
+		input.prevout.signer(Some(pubkey))?;
+		input.pubkey = Some(pubkey);
+		Ok(())
+	}
+
+	/// Record the redeemer spending input `index`, whose previous output is locked to the
+	/// script of its validator, replacing any previous one. The validator is only run once
+	/// the transaction is submitted.
+	pub fn add_redeemer(&mut self, index: usize, redeemer: Redeemer) -> Result<(), &'static str> {
+		let input = self.inputs.get_mut(index).ok_or("Input index out of range")?;
+		let validator = match &input.prevout.lock {
+			Lock::Script { validator, .. } => *validator,
+			_ => return Err("Only script outputs take a redeemer"),
+		};
+		if validator != script::validator_hash(&redeemer.validator) {
+			return Err("Validator does not match the script hash")
+		}
+		input.redeemer = Some(redeemer);
+		Ok(())
+	}
+
+	/// Key whose signature is expected for input `index`
+	pub fn signer(&self, index: usize) -> Result<H256, &'static str> {
+		let input = self.inputs.get(index).ok_or("Input index out of range")?;
+		input.prevout.signer(input.pubkey)
+	}
+
+	/// Whether every input has a signature or a redeemer
+	pub fn is_complete(&self) -> bool {
+		self.inputs
+			.iter()
+			.all(|input| input.signature.is_some() || input.redeemer.is_some())
+	}
+
+	/// Difference between the spent and the created value, `None` if outputs exceed inputs.
+	/// A transaction with confidential amounts pays the fee it declares.
+	pub fn fee(&self) -> Option<Value> {
+		if self.transaction.fee.is_some() {
+			return self.transaction.fee
+		}
+		let mut total_input: Value = 0;
+		for input in self.inputs.iter() {
+			total_input = total_input.checked_add(input.prevout.value)?;
+		}
+		let mut total_output: Value = 0;
+		for output in self.transaction.outputs.iter() {
+			total_output = total_output.checked_add(output.value)?;
+		}
+		total_input.checked_sub(total_output)
+	}
+
+	/// Pay `amount` more fee by taking it from output `change_output`, so the transaction can
+	/// replace its pending version in the pool. `amount` has to be at least the `fee_increment`
+	/// of the chain. Every signature commits to the outputs, so all collected signatures and
+	/// redeemers, which may hold signatures, are dropped and the inputs have to be signed again.
+	/// The change output has to be plain, a declared fee is raised by `amount` as well.
+	pub fn bump_fee(
+		&mut self,
+		change_output: usize,
+		amount: Value,
+		fee_increment: Value,
+	) -> Result<(), &'static str> {
+		let fee = self.fee().ok_or("Total outputs cannot exceed total inputs")?;
+		utxo::check_replacement(fee, fee.saturating_add(amount), fee_increment)?;
+		let output = self
+			.transaction
+			.outputs
+			.get_mut(change_output)
+			.ok_or("Output index out of range")?;
+		if output.confidential.is_some() {
+			return Err("Cannot take a fee bump from a confidential output")
+		}
+		output.value = output.value.checked_sub(amount).ok_or("Change output is too small")?;
+		if let Some(fee) = self.transaction.fee.as_mut() {
+			*fee = fee.checked_add(amount).ok_or("Fee overflow")?;
+		}
+		for input in self.inputs.iter_mut() {
+			input.signature = None;
+			input.redeemer = None;
+		}
+		Ok(())
+	}
+
+	/// Merge the signatures collected by another signer into this one.
+	/// Both sides must be signing the same transaction. Signatures already
+	/// present here are kept.
+	pub fn merge(&mut self, other: Self) -> Result<(), &'static str> {
+		if self.genesis_hash != other.genesis_hash ||
+			self.transaction_version != other.transaction_version
+		{
+			return Err("Cannot merge signatures for different chains")
+		}
+		if self.transaction != other.transaction {
+			return Err("Cannot merge signatures of different transactions")
+		}
+		self.check_inputs()?;
+		other.check_inputs()?;
+		for (input, other_input) in self.inputs.iter_mut().zip(other.inputs.into_iter()) {
+			if input.prevout != other_input.prevout {
+				return Err("Previous outputs do not match")
+			}
+			if input.signature.is_none() {
+				input.signature = other_input.signature;
+			}
+			if input.pubkey.is_none() {
+				input.pubkey = other_input.pubkey;
+			}
+			if input.redeemer.is_none() {
+				input.redeemer = other_input.redeemer;
+			}
+		}
+		if self.metadata.is_none() {
+			self.metadata = other.metadata;
+		}
+		Ok(())
+	}
+
+	/// Put the collected signatures in place and return the signed transaction
+	pub fn finalize(self) -> Result<Transaction, &'static str> {
+		self.check_inputs()?;
+		let mut transaction = self.transaction;
+		for (input, psbt_input) in transaction.inputs.iter_mut().zip(self.inputs.into_iter()) {
+			match psbt_input.redeemer {
+				Some(redeemer) => input.redeemer = Some(redeemer),
+				None => {
+					input.sigscript =
+						psbt_input.signature.ok_or("Missing signature for an input")?;
+					input.pubkey = psbt_input.pubkey;
+				},
+			}
+		}
+		Ok(transaction)
+	}
+
+	/// Check there is one entry in `inputs` for every input of the transaction, which a
+	/// partially signed transaction read from a file does not guarantee
+	fn check_inputs(&self) -> Result<(), &'static str> {
+		if self.inputs.len() != self.transaction.inputs.len() {
+			return Err("Exactly one previous output is required per input")
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,471 @@
+//! Validator scripts of extended UTXOs.
+//!
+//! An output locked with `Lock::Script` carries a datum and the hash of a validator script,
+//! see `validator_hash`. The input spending it reveals the script in its `Redeemer`, together
+//! with the arguments the script runs on. The output may be spent if the script accepts: it
+//! runs on a stack machine, starting with the arguments on the stack, and has to finish with
+//! a true value on top of the stack.
+//!
+//! Besides its stack a script can read the datum of the output it guards and the whole
+//! spending transaction: the UTXOs spent by every input, every output including the datums
+//! of script outputs, and the validity window. It can verify signatures of the input's
+//! signing payload, so it decides itself which keys have to sign. Since the datum of an output
+//! created by the transaction can be checked, a contract can carry its state from one output
+//! to the next.
+//!
+//! Runs are deterministic and bounded: the result only depends on the transaction and the
+//! UTXOs it spends, never on the block it is included in. Time is read from the validity
+//! window, which the runtime checks against the current block. Scripts cannot jump backwards,
+//! their size is limited and every instruction is charged against `MAX_COST`.
+//!
+//! Stack items are byte strings of at most `MAX_ITEM_SIZE` bytes. Numbers are read as
+//! little-endian `Value`s of at most 16 bytes, the empty string being zero, and are pushed in
+//! their shortest encoding. Any item with a non-zero byte is true.
+
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::{
+	sr25519::{Public, Signature},
+	H256, H512,
+};
+use sp_std::prelude::*;
+
+use crate::{
+	ensure, fail,
+	utxo::{Lock, Transaction, TransactionOutput, Value},
+};
+
+/// Maximum length of a validator script in bytes
+pub const MAX_VALIDATOR_SIZE: usize = 4096;
+
+/// Maximum length of a stack item in bytes. Datums and arguments are limited to it as well.
+pub const MAX_ITEM_SIZE: usize = 1024;
+
+/// Maximum number of items on the stack
+pub const MAX_STACK_DEPTH: usize = 256;
+
+/// Cost a script may use up, each instruction costs one unless noted otherwise
+pub const MAX_COST: u32 = 50_000;
+
+/// Cost of `OP_HASH`
+pub const HASH_COST: u32 = 50;
+
+/// Cost of `OP_CHECKSIG` and `OP_CHECKSIGVERIFY`
+pub const CHECKSIG_COST: u32 = 1_000;
+
+/// Push an empty item, which is false and zero
+pub const OP_FALSE: u8 = 0x00;
+/// Opcodes `0x01` to `0x4b` push the next that many bytes of the script
+pub const OP_PUSH_MAX: u8 = 0x4b;
+/// Push the bytes whose length is given by the next byte
+pub const OP_PUSHDATA1: u8 = 0x4c;
+/// Push the bytes whose length is given by the next two bytes, little-endian
+pub const OP_PUSHDATA2: u8 = 0x4d;
+/// Push `[1]`, which is true and one
+pub const OP_TRUE: u8 = 0x51;
+
+/// Duplicate the top item
+pub const OP_DUP: u8 = 0x60;
+/// Remove the top item
+pub const OP_DROP: u8 = 0x61;
+/// Swap the two top items
+pub const OP_SWAP: u8 = 0x62;
+/// Copy the second item to the top
+pub const OP_OVER: u8 = 0x63;
+/// Pop `n` and copy the item `n` below the top to the top, `0 OP_PICK` is `OP_DUP`
+pub const OP_PICK: u8 = 0x64;
+
+/// Pop an item and run the following instructions only if it is true
+pub const OP_IF: u8 = 0x68;
+/// Run the following instructions only if those after the matching `OP_IF` were not run
+pub const OP_ELSE: u8 = 0x69;
+/// End the instructions of the matching `OP_IF`
+pub const OP_ENDIF: u8 = 0x6a;
+/// Pop an item and reject the spend unless it is true
+pub const OP_VERIFY: u8 = 0x6b;
+/// Reject the spend
+pub const OP_RETURN: u8 = 0x6c;
+
+/// Pop two items and push whether they are equal
+pub const OP_EQUAL: u8 = 0x70;
+/// `OP_EQUAL` followed by `OP_VERIFY`
+pub const OP_EQUALVERIFY: u8 = 0x71;
+/// Pop an item and push whether it is false
+pub const OP_NOT: u8 = 0x72;
+/// Pop two items and push whether both are true
+pub const OP_BOOLAND: u8 = 0x73;
+/// Pop two items and push whether either is true
+pub const OP_BOOLOR: u8 = 0x74;
+
+/// Pop `b` and `a` and push `a + b`, rejecting the spend on overflow
+pub const OP_ADD: u8 = 0x78;
+/// Pop `b` and `a` and push `a - b`, rejecting the spend if `b` is larger
+pub const OP_SUB: u8 = 0x79;
+/// Pop `b` and `a` and push whether `a < b`
+pub const OP_LESSTHAN: u8 = 0x7a;
+
+/// Pop an item and push its Blake2-256 hash. Costs `HASH_COST`.
+pub const OP_HASH: u8 = 0x80;
+/// Pop a public key and a signature and push whether it is a valid sr25519 signature of the
+/// signing payload of the input, see `utxo::get_signing_payload`. Costs `CHECKSIG_COST`.
+pub const OP_CHECKSIG: u8 = 0x81;
+/// `OP_CHECKSIG` followed by `OP_VERIFY`
+pub const OP_CHECKSIGVERIFY: u8 = 0x82;
+
+/// Push the datum of the output being spent
+pub const OP_DATUM: u8 = 0x90;
+/// Push the index of the input being validated
+pub const OP_SELF: u8 = 0x91;
+/// Push the hash of the running validator, to find the outputs continuing a contract
+pub const OP_VALIDATOR: u8 = 0x92;
+/// Push the number of inputs
+pub const OP_INPUTCOUNT: u8 = 0x93;
+/// Push the number of outputs
+pub const OP_OUTPUTCOUNT: u8 = 0x94;
+/// Pop an index and push the value of the UTXO spent by that input
+pub const OP_INPUTVALUE: u8 = 0x95;
+/// Pop an index and push the value of that output. Confidential outputs have a value of zero.
+pub const OP_OUTPUTVALUE: u8 = 0x96;
+/// Pop an index and push the key of that output if it is locked to a key, else an empty item
+pub const OP_OUTPUTKEY: u8 = 0x97;
+/// Pop an index and push the validator hash of that output if it is a script output, else an
+/// empty item
+pub const OP_OUTPUTVALIDATOR: u8 = 0x98;
+/// Pop an index and push the datum of that output if it is a script output, else an empty item
+pub const OP_OUTPUTDATUM: u8 = 0x99;
+/// Push the first block the transaction may be included in, rejecting the spend if unset
+pub const OP_VALIDFROM: u8 = 0x9a;
+/// Push the last block the transaction may be included in, rejecting the spend if unset
+pub const OP_VALIDUNTIL: u8 = 0x9b;
+
+/// Data revealed by an input spending a script output
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
+pub struct Redeemer {
+	/// Validator script, its `validator_hash` is the one the spent output is locked to
+	pub validator: Vec<u8>,
+
+	/// Items pushed onto the stack in order before the validator runs, so the last one is on
+	/// top. Like signatures, they are not covered by signatures.
+	pub arguments: Vec<Vec<u8>>,
+}
+
+/// Everything a validator can read besides its stack
+pub struct Context<'a> {
+	/// Spending transaction
+	pub transaction: &'a Transaction,
+
+	/// Index of the input being validated
+	pub index: usize,
+
+	/// UTXOs spent by the inputs of the transaction, in order
+	pub spent: &'a [TransactionOutput],
+
+	/// Signing payload of the input being validated
+	pub signing_payload: &'a [u8],
+}
+
+/// Hash a script output is locked to: the Blake2-256 hash of its validator
+pub fn validator_hash(validator: &[u8]) -> H256 {
+	H256(sp_io::hashing::blake2_256(validator))
+}
+
+/// Run the validator revealed by `redeemer` to decide whether input `context.index` may spend
+/// the script output it refers to
+pub fn validate(redeemer: &Redeemer, context: &Context) -> Result<(), &'static str> {
+	let spent = context.spent.get(context.index).ok_or("Input index out of range")?;
+	let (validator, datum) = match &spent.lock {
+		Lock::Script { validator, datum } => (validator, datum),
+		_ => fail!("Only script outputs take a redeemer"),
+	};
+	ensure!(
+		validator_hash(&redeemer.validator) == *validator,
+		"Validator does not match the script hash"
+	);
+	ensure!(redeemer.validator.len() <= MAX_VALIDATOR_SIZE, "Validator is too large");
+	ensure!(redeemer.arguments.len() <= MAX_STACK_DEPTH, "Script stack is too deep");
+	ensure!(
+		redeemer.arguments.iter().all(|argument| argument.len() <= MAX_ITEM_SIZE),
+		"Script stack item is too large"
+	);
+
+	let mut machine = Machine {
+		context,
+		validator: *validator,
+		datum,
+		stack: redeemer.arguments.clone(),
+		cost: 0,
+	};
+	machine.run(&redeemer.validator)?;
+	match machine.stack.last() {
+		Some(top) if is_true(top) => Ok(()),
+		_ => Err("Script did not accept the spend"),
+	}
+}
+
+struct Machine<'a> {
+	context: &'a Context<'a>,
+	validator: H256,
+	datum: &'a [u8],
+	stack: Vec<Vec<u8>>,
+	cost: u32,
+}
+
+impl Machine<'_> {
+	fn run(&mut self, script: &[u8]) -> Result<(), &'static str> {
+		// Whether the instructions of each enclosing `OP_IF` are being run
+		let mut branches: Vec<bool> = Vec::new();
+		let mut position = 0;
+		while let Some(&opcode) = script.get(position) {
+			position += 1;
+			self.charge(1)?;
+			let running = branches.iter().all(|running| *running);
+
+			if opcode <= OP_PUSHDATA2 {
+				let length = match opcode {
+					OP_PUSHDATA1 => usize::from(read(script, &mut position, 1)?[0]),
+					OP_PUSHDATA2 => {
+						let bytes = read(script, &mut position, 2)?;
+						usize::from(u16::from_le_bytes([bytes[0], bytes[1]]))
+					},
+					length => usize::from(length),
+				};
+				let data = read(script, &mut position, length)?;
+				if running {
+					self.push(data.to_vec())?;
+				}
+				continue
+			}
+			match opcode {
+				OP_IF => {
+					let condition = running && is_true(&self.pop()?);
+					branches.push(condition);
+					continue
+				},
+				OP_ELSE => {
+					let branch = branches.last_mut().ok_or("Unbalanced conditional in script")?;
+					*branch = !*branch;
+					continue
+				},
+				OP_ENDIF => {
+					branches.pop().ok_or("Unbalanced conditional in script")?;
+					continue
+				},
+				_ if !running => continue,
+				_ => self.execute(opcode)?,
+			}
+		}
+		ensure!(branches.is_empty(), "Unbalanced conditional in script");
+		Ok(())
+	}
+
+	fn execute(&mut self, opcode: u8) -> Result<(), &'static str> {
+		let context = self.context;
+		match opcode {
+			OP_TRUE => self.push(vec![1])?,
+
+			OP_DUP => self.pick(0)?,
+			OP_DROP => {
+				self.pop()?;
+			},
+			OP_SWAP => {
+				let top = self.pop()?;
+				let below = self.pop()?;
+				self.push(top)?;
+				self.push(below)?;
+			},
+			OP_OVER => self.pick(1)?,
+			OP_PICK => {
+				let depth = self.pop_number()?;
+				self.pick(usize::try_from(depth).map_err(|_| "Script stack underflow")?)?;
+			},
+
+			OP_VERIFY => ensure!(is_true(&self.pop()?), "Script did not accept the spend"),
+			OP_RETURN => fail!("Script did not accept the spend"),
+
+			OP_EQUAL | OP_EQUALVERIFY => {
+				let equal = self.pop()? == self.pop()?;
+				self.push_bool(equal)?;
+				if opcode == OP_EQUALVERIFY {
+					self.execute(OP_VERIFY)?;
+				}
+			},
+			OP_NOT => {
+				let item = self.pop()?;
+				self.push_bool(!is_true(&item))?;
+			},
+			OP_BOOLAND | OP_BOOLOR => {
+				let b = is_true(&self.pop()?);
+				let a = is_true(&self.pop()?);
+				self.push_bool(if opcode == OP_BOOLAND { a && b } else { a || b })?;
+			},
+
+			OP_ADD => {
+				let b = self.pop_number()?;
+				let a = self.pop_number()?;
+				self.push_number(a.checked_add(b).ok_or("Script arithmetic overflow")?)?;
+			},
+			OP_SUB => {
+				let b = self.pop_number()?;
+				let a = self.pop_number()?;
+				self.push_number(a.checked_sub(b).ok_or("Script arithmetic overflow")?)?;
+			},
+			OP_LESSTHAN => {
+				let b = self.pop_number()?;
+				let a = self.pop_number()?;
+				self.push_bool(a < b)?;
+			},
+
+			OP_HASH => {
+				self.charge(HASH_COST)?;
+				let item = self.pop()?;
+				self.push(sp_io::hashing::blake2_256(&item).to_vec())?;
+			},
+			OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+				self.charge(CHECKSIG_COST)?;
+				let key = self.pop()?;
+				let signature = self.pop()?;
+				// Batch verification defers failures to the end of the block, so the result
+				// could not be used by the script. Verify right away instead.
+				let valid = key.len() == 32 &&
+					signature.len() == 64 &&
+					sp_io::crypto::sr25519_verify(
+						&Signature::from_raw(H512::from_slice(&signature).to_fixed_bytes()),
+						context.signing_payload,
+						&Public::from_h256(H256::from_slice(&key)),
+					);
+				self.push_bool(valid)?;
+				if opcode == OP_CHECKSIGVERIFY {
+					self.execute(OP_VERIFY)?;
+				}
+			},
+
+			OP_DATUM => self.push(self.datum.to_vec())?,
+			OP_SELF => self.push_number(context.index as Value)?,
+			OP_VALIDATOR => self.push(self.validator.as_bytes().to_vec())?,
+			OP_INPUTCOUNT => self.push_number(context.transaction.inputs.len() as Value)?,
+			OP_OUTPUTCOUNT => self.push_number(context.transaction.outputs.len() as Value)?,
+			OP_INPUTVALUE => {
+				let index = self.pop_number()?;
+				let spent = usize::try_from(index)
+					.ok()
+					.and_then(|index| context.spent.get(index))
+					.ok_or("Script index out of range")?;
+				self.push_number(spent.value)?;
+			},
+			OP_OUTPUTVALUE | OP_OUTPUTKEY | OP_OUTPUTVALIDATOR | OP_OUTPUTDATUM => {
+				let index = self.pop_number()?;
+				let output = usize::try_from(index)
+					.ok()
+					.and_then(|index| context.transaction.outputs.get(index))
+					.ok_or("Script index out of range")?;
+				// Data outputs are never added to the UTXO set, so they are owned by nobody
+				match (opcode, &output.lock) {
+					(OP_OUTPUTVALUE, _) => self.push_number(output.value)?,
+					_ if !output.is_spendable() => self.push(Vec::new())?,
+					(OP_OUTPUTKEY, Lock::Pubkey) => self.push(output.pubkey.as_bytes().to_vec())?,
+					(OP_OUTPUTVALIDATOR, Lock::Script { validator, .. }) =>
+						self.push(validator.as_bytes().to_vec())?,
+					(OP_OUTPUTDATUM, Lock::Script { datum, .. }) => self.push(datum.clone())?,
+					_ => self.push(Vec::new())?,
+				}
+			},
+			OP_VALIDFROM => {
+				let valid_from =
+					context.transaction.valid_from.ok_or("Transaction has no start")?;
+				self.push_number(valid_from.into())?;
+			},
+			OP_VALIDUNTIL => {
+				let valid_until =
+					context.transaction.valid_until.ok_or("Transaction has no expiry")?;
+				self.push_number(valid_until.into())?;
+			},
+
+			_ => fail!("Invalid script instruction"),
+		}
+		Ok(())
+	}
+
+	fn charge(&mut self, cost: u32) -> Result<(), &'static str> {
+		self.cost = self.cost.saturating_add(cost);
+		ensure!(self.cost <= MAX_COST, "Script exceeds its cost limit");
+		Ok(())
+	}
+
+	fn push(&mut self, item: Vec<u8>) -> Result<(), &'static str> {
+		ensure!(item.len() <= MAX_ITEM_SIZE, "Script stack item is too large");
+		ensure!(self.stack.len() < MAX_STACK_DEPTH, "Script stack is too deep");
+		self.stack.push(item);
+		Ok(())
+	}
+
+	fn pop(&mut self) -> Result<Vec<u8>, &'static str> {
+		self.stack.pop().ok_or("Script stack underflow")
+	}
+
+	fn pick(&mut self, depth: usize) -> Result<(), &'static str> {
+		let position = self
+			.stack
+			.len()
+			.checked_sub(depth)
+			.and_then(|above| above.checked_sub(1))
+			.ok_or("Script stack underflow")?;
+		self.push(self.stack[position].clone())
+	}
+
+	fn push_bool(&mut self, value: bool) -> Result<(), &'static str> {
+		self.push(if value { vec![1] } else { Vec::new() })
+	}
+
+	fn pop_number(&mut self) -> Result<Value, &'static str> {
+		let item = self.pop()?;
+		ensure!(item.len() <= 16, "Script number out of range");
+		let mut bytes = [0u8; 16];
+		bytes[..item.len()].copy_from_slice(&item);
+		Ok(Value::from_le_bytes(bytes))
+	}
+
+	fn push_number(&mut self, value: Value) -> Result<(), &'static str> {
+		self.push(encode_number(value))
+	}
+}
+
+/// Shortest encoding of a number as a stack item
+pub fn encode_number(value: Value) -> Vec<u8> {
+	let bytes = value.to_le_bytes();
+	let length = bytes.iter().rposition(|byte| *byte != 0).map_or(0, |last| last + 1);
+	bytes[..length].to_vec()
+}
+
+/// Script instructions pushing `data`
+pub fn push_data(data: &[u8]) -> Vec<u8> {
+	let mut script = match data.len() {
+		0 => return vec![OP_FALSE],
+		length if length <= usize::from(OP_PUSH_MAX) => vec![length as u8],
+		length if length <= usize::from(u8::MAX) => vec![OP_PUSHDATA1, length as u8],
+		length => {
+			let mut script = vec![OP_PUSHDATA2];
+			script.extend_from_slice(&(length as u16).to_le_bytes());
+			script
+		},
+	};
+	script.extend_from_slice(data);
+	script
+}
+
+fn read<'a>(
+	script: &'a [u8],
+	position: &mut usize,
+	length: usize,
+) -> Result<&'a [u8], &'static str> {
+	let end = position.checked_add(length).ok_or("Script is truncated")?;
+	let bytes = script.get(*position..end).ok_or("Script is truncated")?;
+	*position = end;
+	Ok(bytes)
+}
+
+fn is_true(item: &[u8]) -> bool {
+	item.iter().any(|byte| *byte != 0)
+}
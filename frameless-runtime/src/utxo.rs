@@ -3,6 +3,7 @@ use scale_info::TypeInfo;
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 use sp_core::{
+	H160,
 	H256,
 	H512,
 	sr25519::{Public, Signature},
@@ -11,15 +12,89 @@ use sp_std::collections::btree_set::BTreeSet;
 use sp_std::prelude::*;
 use sp_runtime::{
 	traits::{BlakeTwo256, Hash},
-	transaction_validity::{TransactionLongevity, ValidTransaction},
+	transaction_validity::{
+		InvalidTransaction, TransactionLongevity, TransactionPriority, ValidTransaction,
+	},
 };
 
 use log::info;
 
+use crate::{
+	commitment,
+	confidential::{self, Confidential},
+	script::{self, Redeemer},
+	BlockNumber,
+};
+
+/// Shows the owner of an output: the SS58 address of its key in the node's default format,
+/// or the hash a pubkey hash or script output is locked to. Without `std` there is no SS58
+/// encoder and the hex key is shown instead.
+pub struct Address<'a>(pub &'a TransactionOutput);
+
+impl sp_std::fmt::Display for Address<'_> {
+	fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		match &self.0.lock {
+			Lock::Pubkey => (),
+			Lock::PubkeyHash(hash) => return write!(f, "pubkey hash {:?}", hash),
+			Lock::Script { validator, .. } => return write!(f, "script {:?}", validator),
+		}
+		#[cfg(feature = "std")]
+		{
+			use sp_core::crypto::Ss58Codec;
+			write!(f, "{}", Public::from_h256(self.0.pubkey).to_ss58check())
+		}
+		#[cfg(not(feature = "std"))]
+		{
+			write!(f, "{:?}", self.0.pubkey)
+		}
+	}
+}
+
 // Value to represent a fungible value of a UTXO
 pub type Value = u128;
 pub type DispatchResult = Result<(), sp_runtime::DispatchError>;
 
+/// Error of an input whose signature does not verify, reported as `InvalidTransaction::BadProof`
+pub const INVALID_SIGNATURE: &str = "Invalid Signature to spend this Input";
+
+/// Storage key of the genesis block hash. It is written when block 1 is initialized.
+pub const GENESIS_HASH_KEY: [u8; 12] = *b"genesis_hash";
+
+/// Storage key of the number of the latest initialized block
+pub const BLOCK_NUMBER_KEY: [u8; 12] = *b"block_number";
+
+/// Storage key of the fee step a replacement pays on top. Transactions are prioritized by the
+/// number of whole increments of their fee, so the pool only lets a replacement through once it
+/// reaches the next one.
+pub const FEE_INCREMENT_KEY: [u8; 13] = *b"fee_increment";
+
+/// Fee step used when none is set in genesis
+pub const DEFAULT_FEE_INCREMENT: Value = 1;
+
+/// Storage key of the sum of the values of all UTXOs
+pub const TOTAL_ISSUANCE_KEY: [u8; 14] = *b"total_issuance";
+
+/// Storage key of the total value hidden in confidential UTXOs, see `shield`
+pub const SHIELDED_VALUE_KEY: [u8; 14] = *b"shielded_value";
+
+/// Storage key of the minimum value of a spendable output
+pub const DUST_LIMIT_KEY: [u8; 10] = *b"dust_limit";
+
+/// Minimum value of a spendable output used when none is set in genesis
+pub const DEFAULT_DUST_LIMIT: Value = 10;
+
+/// Maximum number of bytes carried by a data output
+pub const MAX_OUTPUT_DATA: usize = 80;
+
+/// `InvalidTransaction::Custom` code of a spendable output below the dust limit
+pub const DUST_OUTPUT_ERROR: u8 = 2;
+
+/// `InvalidTransaction::Custom` code of a data output larger than `MAX_OUTPUT_DATA`
+pub const OUTPUT_DATA_TOO_LARGE_ERROR: u8 = 3;
+
+/// Domain separator prepended to every signature payload
+pub const SIGNING_CONTEXT: &[u8] = b"frameless-utxo-spend";
+
 /// Return Err of the expression: `return Err($expression);`.
 ///
 /// Used as `fail!(expression)`.
@@ -62,23 +137,85 @@ pub struct Transaction {
 
 	/// UTXOs to be created as a result of current transaction dispatch
 	pub outputs: Vec<TransactionOutput>,
+
+	/// First block the transaction may be included in, if any
+	pub valid_from: Option<BlockNumber>,
+
+	/// Last block the transaction may be included in, if any.
+	/// Transactions without one never expire.
+	pub valid_until: Option<BlockNumber>,
+
+	/// Fee paid by the transaction. Confidential amounts hide the difference between inputs
+	/// and outputs, so a transaction spending or creating them has to declare its fee and is
+	/// checked to balance exactly. `None` for a plain transaction, whose fee is the difference.
+	#[cfg_attr(feature = "std", serde(default))]
+	pub fee: Option<Value>,
 }
 
 /// Single transaction input that refers to one UTXO
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
-#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug, MaxEncodedLen, TypeInfo)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
 pub struct TransactionInput {
 	/// Reference to an UTXO to be spent
 	pub outpoint: H256,
 
 	/// Proof that transaction owner is authorized to spend referred UTXO &
-	/// that the entire transaction is untampered
+	/// that the parts of the transaction selected by `sighash` are untampered
 	pub sigscript: H512,
+
+	/// Which parts of the transaction `sigscript` commits to
+	pub sighash: SigHash,
+
+	/// Public key revealed to spend an output locked with `Lock::PubkeyHash`, `None` for
+	/// any other output. Like `sigscript` it is not covered by signatures.
+	#[cfg_attr(feature = "std", serde(default))]
+	pub pubkey: Option<H256>,
+
+	/// Validator and its arguments revealed to spend an output locked with `Lock::Script`,
+	/// `None` for any other output. Such an input has no signature in `sigscript`, its
+	/// validator checks any signatures it needs. Not covered by signatures either.
+	#[cfg_attr(feature = "std", serde(default))]
+	pub redeemer: Option<Redeemer>,
+}
+
+/// Signature hash mode of an input. Selects which inputs and outputs of the
+/// transaction are covered by the input's signature.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Encode, Decode, Hash, Debug, MaxEncodedLen, TypeInfo)]
+pub enum SigHash {
+	/// Sign all inputs and all outputs
+	All,
+	/// Sign all inputs and none of the outputs
+	None,
+	/// Sign all inputs and only the output with the same index as this input
+	Single,
+	/// Sign only this input and all outputs
+	AllAnyoneCanPay,
+	/// Sign only this input and none of the outputs
+	NoneAnyoneCanPay,
+	/// Sign only this input and the output with the same index as this input
+	SingleAnyoneCanPay,
+}
+
+impl SigHash {
+	/// Whether other inputs may be added or removed after signing
+	pub fn anyone_can_pay(&self) -> bool {
+		matches!(
+			self,
+			SigHash::AllAnyoneCanPay | SigHash::NoneAnyoneCanPay | SigHash::SingleAnyoneCanPay
+		)
+	}
+}
+
+impl Default for SigHash {
+	fn default() -> Self {
+		SigHash::All
+	}
 }
 
 /// Single transaction output to create upon transaction dispatch
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
-#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug, MaxEncodedLen, TypeInfo)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
 pub struct TransactionOutput {
 	/// Value associated with this output
 	pub value: Value,
@@ -87,6 +224,70 @@ pub struct TransactionOutput {
 	/// owner must provide a proof by hashing the whole `Transaction` and
 	/// signing it with a corresponding private key.
 	pub pubkey: H256,
+
+	/// How the output is locked to its owner
+	#[cfg_attr(feature = "std", serde(default))]
+	pub lock: Lock,
+
+	/// Data carried by the output, at most `MAX_OUTPUT_DATA` bytes. An output carrying data
+	/// can never be spent: it is not added to the UTXO set, its `pubkey` is ignored and its
+	/// value is burned. It is exempt from the dust limit.
+	pub data: Option<Vec<u8>>,
+
+	/// Commitment to the amount of a confidential output, which replaces `value`. `value`
+	/// must then be zero. Only spendable outputs can be confidential, they are exempt from the
+	/// dust limit since their value is unknown.
+	#[cfg_attr(feature = "std", serde(default))]
+	pub confidential: Option<Confidential>,
+}
+
+impl TransactionOutput {
+	/// Whether the output is added to the UTXO set and can be spent later
+	pub fn is_spendable(&self) -> bool {
+		self.data.is_none()
+	}
+
+	/// Key whose signature spends this output, given the key revealed by the spending input
+	pub fn signer(&self, revealed: Option<H256>) -> Result<H256, &'static str> {
+		match (&self.lock, revealed) {
+			(Lock::Pubkey, None) => Ok(self.pubkey),
+			(Lock::Pubkey, Some(_)) => Err("Only pubkey hash outputs take a revealed key"),
+			(Lock::PubkeyHash(hash), Some(key)) => {
+				ensure!(pubkey_hash(&key) == *hash, "Revealed key does not match the pubkey hash");
+				Ok(key)
+			},
+			(Lock::PubkeyHash(_), None) => Err("Spending a pubkey hash output requires its key"),
+			(Lock::Script { .. }, _) => Err("Spending a script output requires a redeemer"),
+		}
+	}
+}
+
+/// Lock of a spendable output
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug, TypeInfo)]
+pub enum Lock {
+	/// Spent with a signature of `pubkey`
+	Pubkey,
+	/// Spent by revealing a public key whose `pubkey_hash` is this, together with a signature
+	/// of that key. `pubkey` is ignored, the key stays private until the output is spent.
+	PubkeyHash(H160),
+	/// Spent by revealing the validator script whose `script::validator_hash` is `validator`
+	/// in the `redeemer` of the spending input, if the script accepts the spend. The datum
+	/// holds the state of the contract the output belongs to, at most `script::MAX_ITEM_SIZE`
+	/// bytes, and can be read by the validator. `pubkey` is ignored.
+	Script { validator: H256, datum: Vec<u8> },
+}
+
+impl Default for Lock {
+	fn default() -> Self {
+		Lock::Pubkey
+	}
+}
+
+/// Hash a pay-to-public-key-hash output is locked to: the first 20 bytes of the
+/// Blake2-256 hash of the key
+pub fn pubkey_hash(pubkey: &H256) -> H160 {
+	H160::from_slice(&sp_io::hashing::blake2_256(pubkey.as_bytes())[..20])
 }
 
 /// Execute transaction
@@ -100,22 +301,29 @@ pub fn spend(mut transaction: Transaction) -> DispatchResult {
 }
 
 /// Called by Txpool and Runtime
+/// Current block is within the transaction's validity window
 /// Verify inputs and outputs are non-empty
 /// All inputs map to existing unspent && unlocked outputs
+/// Inputs spending script outputs are accepted by their validator instead of a signature
 /// Each input is unique.
 /// Each output is unique && is non-zero
-/// Total output value does not exceed total input value
+/// Spendable outputs reach the dust limit, data outputs stay within `MAX_OUTPUT_DATA`
+/// Total output value does not exceed total input value, or with a declared fee
+/// the commitments to the amounts balance exactly and confidential outputs prove their range
 /// New outputs are unique
 /// Sum of total input and output does not overflow
 /// verify signatures
 /// outputs cannot be exploited
+/// Provides a tag per spent outpoint and is prioritized by its fee, so
+/// conflicting spends in the pool replace each other by fee
 pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransaction, &'static str> {
     ensure!(!transaction.inputs.is_empty(), "No inputs");
     ensure!(!transaction.outputs.is_empty(), "No outputs");
 
     {
         // Check for uniqueness once. Afterwards dont need input_set.
-        let input_set: BTreeSet<_> = transaction.inputs.iter().collect();
+        // Compare outpoints only, the same UTXO must not be spent twice with different signatures.
+        let input_set: BTreeSet<_> = transaction.inputs.iter().map(|input| input.outpoint).collect();
         ensure!(input_set.len() == transaction.inputs.len(), "Inputs not unique");
     }
     {
@@ -124,29 +332,26 @@ pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransactio
         ensure!(output_set.len() == transaction.outputs.len(), "Outputs not unique");
     }
 
+    let longevity = check_validity_window(transaction).map_err(|e| match e {
+        InvalidTransaction::Future => "Transaction is not valid yet",
+        _ => "Transaction has expired",
+    })?;
+
     let mut total_input: Value = 0;
     let mut total_output: Value = 0;
-    let stripped_transaction = get_stripped_transaction(&transaction);
+    // Only transactions declaring their fee may touch confidential amounts
+    let mut balance = transaction.fee.map(|_| confidential::Balance::default());
+    let genesis_hash = genesis_hash()?;
+    let transaction_version = crate::VERSION.transaction_version;
 
+    // Validators can read every UTXO spent by the transaction, so all are looked up first
+    let mut spent = Vec::with_capacity(transaction.inputs.len());
     for input in transaction.inputs.iter() {
         match sp_io::storage::get(&input.outpoint.encode()) {
-            Some(utxo_bytes) => {
-                let utxo =
-                    TransactionOutput::decode(&mut &utxo_bytes[..])
-                    .expect("Should never happen; QED");
-                // Check Signature
-                let sig_verify_result =
-                    sp_io::crypto::sr25519_verify(
-                        &Signature::from_raw(*input.sigscript.as_fixed_bytes()),
-                        &stripped_transaction,
-                        &Public::from_h256(utxo.pubkey),
-                    );
-                ensure!(sig_verify_result, "Invalid Signature to spend this Input");
-                total_input =
-                    total_input
-                    .checked_add(utxo.value)
-                    .ok_or("input value overflow")?;
-            },
+            Some(utxo_bytes) => spent.push(
+                TransactionOutput::decode(&mut &utxo_bytes[..])
+                    .expect("Should never happen; QED"),
+            ),
             None => {
                 // To keep it simple we want to fail here. No handling for races.
                 return Err("No existing UTXO for this specified outpoint, Invalid Input");
@@ -154,30 +359,115 @@ pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransactio
         }
     }
 
+    for (index, (input, utxo)) in transaction.inputs.iter().zip(spent.iter()).enumerate() {
+        let signing_payload =
+            get_signing_payload(transaction, index, &genesis_hash, transaction_version)?;
+        match &input.redeemer {
+            Some(redeemer) => {
+                ensure!(input.pubkey.is_none(), "Only pubkey hash outputs take a revealed key");
+                let context = script::Context {
+                    transaction,
+                    index,
+                    spent: &spent,
+                    signing_payload: &signing_payload,
+                };
+                script::validate(redeemer, &context)?;
+            },
+            None => {
+                // Check Signature against the parts of the tx selected by the input's sighash
+                // While a batch is open (block import) the check is deferred to
+                // `finish_batch_verify` and this always succeeds. An imported block spending
+                // with a bad signature is therefore applied to state, and only rejected as a
+                // whole once all its extrinsics ran, while block authoring and the pool
+                // reject the transaction right here.
+                // Pubkey hash outputs are checked against the revealed key first
+                let signer = utxo.signer(input.pubkey)?;
+                let sig_verify_result =
+                    sp_io::crypto::sr25519_batch_verify(
+                        &Signature::from_raw(*input.sigscript.as_fixed_bytes()),
+                        &signing_payload,
+                        &Public::from_h256(signer),
+                    );
+                ensure!(sig_verify_result, INVALID_SIGNATURE);
+            },
+        }
+        match balance.as_mut() {
+            Some(balance) => balance.add_input(utxo)?,
+            None => ensure!(
+                utxo.confidential.is_none(),
+                "Spending a confidential output requires a declared fee"
+            ),
+        }
+        total_input =
+            total_input
+            .checked_add(utxo.value)
+            .ok_or("input value overflow")?;
+    }
+
     // Need to keep track of the output_index in order to avoid hashing
-    // collisions in storage.
+    // collisions in storage. Outpoints are derived from the stripped transaction,
+    // the same way `update_storage` does, so every signature variant maps to the same keys.
+    let stripped_transaction = get_stripped_transaction(transaction);
     let mut output_index: u64 = 0;
     // Verify outputs
     for output in transaction.outputs.iter() {
-        ensure!(output.value > 0, "Output values must be greater than zero");
         // ensure no duplicate utxo keys in the database.
-        let new_utxo_hash_key = BlakeTwo256::hash_of(&(&transaction.encode(), output_index));
+        let new_utxo_hash_key = BlakeTwo256::hash_of(&(&stripped_transaction, output_index));
         output_index = output_index.checked_add(1).ok_or("output index overflow")?;
-        ensure!(
-            !sp_io::storage::exists(&new_utxo_hash_key.encode()),
-            "output utxo already exists"
-        );
+        match balance.as_mut() {
+            Some(balance) => balance.add_output(output)?,
+            None => ensure!(
+                output.confidential.is_none(),
+                "Creating a confidential output requires a declared fee"
+            ),
+        }
+        if output.is_spendable() {
+            ensure!(
+                output.value > 0 || output.confidential.is_some(),
+                "Output values must be greater than zero"
+            );
+            ensure!(
+                !sp_io::storage::exists(&new_utxo_hash_key.encode()),
+                "output utxo already exists"
+            );
+            if let Lock::Script { datum, .. } = &output.lock {
+                ensure!(datum.len() <= script::MAX_ITEM_SIZE, "Datum is too large");
+            }
+        }
+        // The value of data outputs is burned, it counts as spent but is not paid as fee
         total_output = total_output
             .checked_add(output.value)
             .ok_or("output value overflow")?;
     }
 
-    if total_output > total_input {
-        return Err("Total outputs cannot exceed total inputs");
-    }
+    check_outputs(transaction).map_err(|e| match e {
+        InvalidTransaction::Custom(DUST_OUTPUT_ERROR) => "Output value is below the dust limit",
+        _ => "Output data is too large",
+    })?;
+
+    let fee = match (transaction.fee, balance) {
+        (Some(fee), Some(balance)) => {
+            balance.check(fee)?;
+            fee
+        },
+        _ => {
+            if total_output > total_input {
+                return Err("Total outputs cannot exceed total inputs");
+            }
+            total_input - total_output
+        },
+    };
+
+    // The pool only lets a transaction replace the ones spending the same outpoints when
+    // its priority is strictly higher. Counting whole fee increments makes a replacement cross
+    // into the next increment, wallets bump by a full one, see `check_replacement`.
+    let priority = TransactionPriority::try_from(fee / fee_increment())
+        .unwrap_or(TransactionPriority::max_value());
 
     Ok(ValidTransaction {
-        longevity: TransactionLongevity::max_value(),
+        priority,
+        provides: transaction.inputs.iter().map(|input| input.outpoint.encode()).collect(),
+        longevity,
         propagate: true,
         ..Default::default()
     })
@@ -194,25 +484,233 @@ pub fn get_stripped_transaction(transaction: &Transaction) -> Vec<u8> {
     tx.encode()
 }
 
+/// Number of the block being built or executed.
+/// During pool validation this is the block the transaction would be included in.
+pub fn block_number() -> BlockNumber {
+    sp_io::storage::get(&BLOCK_NUMBER_KEY)
+        .and_then(|raw_number| BlockNumber::decode(&mut &raw_number[..]).ok())
+        .unwrap_or_default()
+}
+
+/// Check the current block against the validity window of a transaction
+/// Fails with `Future` before `valid_from` and with `Stale` after `valid_until`
+/// @return: number of blocks the transaction remains valid for, including the current one
+pub fn check_validity_window(transaction: &Transaction) -> Result<TransactionLongevity, InvalidTransaction> {
+    let current = block_number();
+    if let Some(valid_from) = transaction.valid_from {
+        ensure!(current >= valid_from, InvalidTransaction::Future);
+    }
+    match transaction.valid_until {
+        Some(valid_until) => {
+            ensure!(current <= valid_until, InvalidTransaction::Stale);
+            Ok(TransactionLongevity::from(valid_until - current) + 1)
+        },
+        None => Ok(TransactionLongevity::max_value()),
+    }
+}
+
+/// Check the outputs of a transaction against the output policy.
+/// Plain spendable outputs must be worth at least the dust limit, see `dust_limit`, so that the
+/// UTXO set is not filled with outputs that cost more to spend than they are worth.
+/// Fails with `Custom(DUST_OUTPUT_ERROR)` or `Custom(OUTPUT_DATA_TOO_LARGE_ERROR)`.
+pub fn check_outputs(transaction: &Transaction) -> Result<(), InvalidTransaction> {
+    let dust_limit = dust_limit();
+    for output in transaction.outputs.iter() {
+        match &output.data {
+            Some(data) => ensure!(
+                data.len() <= MAX_OUTPUT_DATA,
+                InvalidTransaction::Custom(OUTPUT_DATA_TOO_LARGE_ERROR)
+            ),
+            None => ensure!(
+                output.value >= dust_limit || output.confidential.is_some(),
+                InvalidTransaction::Custom(DUST_OUTPUT_ERROR)
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Minimum value of a spendable output. It is held in state, set in genesis and can be
+/// changed without a runtime upgrade by writing `DUST_LIMIT_KEY`.
+pub fn dust_limit() -> Value {
+    sp_io::storage::get(&DUST_LIMIT_KEY)
+        .and_then(|raw_limit| Value::decode(&mut &raw_limit[..]).ok())
+        .unwrap_or(DEFAULT_DUST_LIMIT)
+}
+
+/// Sum of the values of all UTXOs, including the hidden ones of confidential UTXOs,
+/// as recorded in state
+pub fn total_issuance() -> Value {
+    sp_io::storage::get(&TOTAL_ISSUANCE_KEY)
+        .and_then(|raw_issuance| Value::decode(&mut &raw_issuance[..]).ok())
+        .unwrap_or_default()
+}
+
+/// Record value added to the UTXO set out of nothing, e.g. by a coinbase
+pub fn mint(amount: Value) {
+    let issuance = total_issuance().saturating_add(amount);
+    sp_io::storage::set(&TOTAL_ISSUANCE_KEY, &issuance.encode());
+}
+
+/// Record value removed from the UTXO set for good, e.g. fees and data outputs
+pub fn burn(amount: Value) {
+    let issuance = total_issuance().saturating_sub(amount);
+    sp_io::storage::set(&TOTAL_ISSUANCE_KEY, &issuance.encode());
+}
+
+/// Value moved from plain into confidential UTXOs so far, as recorded in state.
+/// It is public even though the value of each confidential UTXO is not.
+pub fn shielded_value() -> Value {
+    sp_io::storage::get(&SHIELDED_VALUE_KEY)
+        .and_then(|raw_value| Value::decode(&mut &raw_value[..]).ok())
+        .unwrap_or_default()
+}
+
+/// Record `entering` value moving into confidential UTXOs and `leaving` value moving out
+pub fn shield(entering: Value, leaving: Value) {
+    let shielded = shielded_value().saturating_add(entering).saturating_sub(leaving);
+    sp_io::storage::set(&SHIELDED_VALUE_KEY, &shielded.encode());
+}
+
+/// Sum of the plain values of all UTXOs in storage, `None` if it overflows.
+/// UTXOs are stored under 32 byte keys, finding them reads the whole state, so this is only
+/// meant for offline consistency checks. Entries that are no UTXO are skipped.
+pub fn utxo_set_value() -> Option<Value> {
+    let mut total: Value = 0;
+    let mut key = Vec::new();
+    while let Some(next_key) = sp_io::storage::next_key(&key) {
+        let utxo = (next_key.len() == 32)
+            .then(|| sp_io::storage::get(&next_key))
+            .flatten()
+            .and_then(|utxo_bytes| TransactionOutput::decode(&mut &utxo_bytes[..]).ok());
+        if let Some(utxo) = utxo {
+            total = total.checked_add(utxo.value)?;
+        }
+        key = next_key;
+    }
+    Some(total)
+}
+
+/// Check that a replacement paying `replacement_fee` pays at least one `fee_increment` more
+/// than the pending transaction paying `pending_fee`. The pool only asks for the next whole
+/// increment, this keeps wallets from bumping by less than one.
+pub fn check_replacement(
+    pending_fee: Value,
+    replacement_fee: Value,
+    fee_increment: Value,
+) -> Result<(), &'static str> {
+    ensure!(
+        replacement_fee >= pending_fee.saturating_add(fee_increment.max(1)),
+        "Replacement has to pay at least one fee increment more"
+    );
+    Ok(())
+}
+
+/// Fee step a replacement transaction has to pay on top of the one it replaces, at least 1
+pub fn fee_increment() -> Value {
+    sp_io::storage::get(&FEE_INCREMENT_KEY)
+        .and_then(|raw_increment| Value::decode(&mut &raw_increment[..]).ok())
+        .unwrap_or(DEFAULT_FEE_INCREMENT)
+        .max(1)
+}
+
+/// Hash of the genesis block of the chain this runtime is running on
+pub fn genesis_hash() -> Result<H256, &'static str> {
+    let raw_hash = sp_io::storage::get(&GENESIS_HASH_KEY).ok_or("Genesis hash is not known")?;
+    H256::decode(&mut &raw_hash[..]).map_err(|_| "Invalid genesis hash in storage")
+}
+
+/// Build the message signed by the input at `index`
+/// Signatures, revealed keys and redeemers are always stripped, then inputs and outputs are
+/// dropped according to the input's `SigHash`:
+/// ANYONECANPAY keeps only the signed input,
+/// NONE drops every output,
+/// SINGLE keeps only the output with the same index as the input.
+/// The result is bound to one chain and transaction format by prefixing it with
+/// `SIGNING_CONTEXT`, the genesis hash and the runtime's transaction version.
+/// @return: scale encoded payload
+pub fn get_signing_payload(
+    transaction: &Transaction,
+    index: usize,
+    genesis_hash: &H256,
+    transaction_version: u32,
+) -> Result<Vec<u8>, &'static str> {
+    let sighash = transaction.inputs.get(index).ok_or("Input index out of range")?.sighash;
+    let mut tx = transaction.clone();
+    for input in tx.inputs.iter_mut() {
+        input.sigscript = H512::zero();
+        input.pubkey = None;
+        input.redeemer = None;
+    }
+
+    if sighash.anyone_can_pay() {
+        tx.inputs = vec![tx.inputs[index].clone()];
+    }
+
+    match sighash {
+        SigHash::All | SigHash::AllAnyoneCanPay => {},
+        SigHash::None | SigHash::NoneAnyoneCanPay => tx.outputs.clear(),
+        SigHash::Single | SigHash::SingleAnyoneCanPay => {
+            let output = tx.outputs.get(index).cloned().ok_or("SigHash Single without a matching output")?;
+            tx.outputs = vec![output];
+        },
+    }
+
+    Ok((SIGNING_CONTEXT, genesis_hash, transaction_version, tx).encode())
+}
+
 /// Make changes to storage
 /// A key in storage is a hash of a transaction with no input signatures +
 /// its order in the TransactionOutput Vec in Order to avoid duplications.
 fn update_storage(transaction: &mut Transaction) -> DispatchResult {
+    // Plain value spent and created, the fee and the value of data outputs are burned
+    let mut plain_input: Value = 0;
+    let mut plain_output: Value = 0;
+    let mut data_value: Value = 0;
+
     // Remove UTXOS which were spent && strip signatures from inputs
     // To prep for storing deterministic keys.
     for input in transaction.inputs.iter_mut() {
         input.sigscript = H512::zero();
+        if let Some(utxo_bytes) = sp_io::storage::get(&input.outpoint.encode()) {
+            let utxo = TransactionOutput::decode(&mut &utxo_bytes[..])
+                .expect("Should never happen; QED");
+            plain_input = plain_input.saturating_add(utxo.value);
+        }
         sp_io::storage::clear(&input.outpoint.encode());
+        commitment::remove(&mut commitment::RuntimeStore, &input.outpoint);
     }
 
     // Add new utxos to storage
+    let stripped_transaction = get_stripped_transaction(transaction);
     let mut output_index: u64 = 0;
     for output in transaction.outputs.iter() {
-        let key = BlakeTwo256::hash_of(&(&transaction.encode(), output_index));
+        let key = BlakeTwo256::hash_of(&(&stripped_transaction, output_index));
         output_index = output_index.checked_add(1).ok_or("output index overflow")?;
+        if !output.is_spendable() {
+            data_value = data_value.saturating_add(output.value);
+            continue;
+        }
+        plain_output = plain_output.saturating_add(output.value);
         sp_io::storage::set(&key.encode(), &output.encode());
-        info!(target: "frameless", "🖼️ Storing UTXO {:?} at key {:?}", output, key);
+        commitment::insert(&mut commitment::RuntimeStore, &key, output);
+        info!(
+            target: "frameless",
+            "🖼️ Storing UTXO of value {} owned by {} at key {:?}",
+            output.value,
+            Address(output),
+            key
+        );
     }
 
+    match transaction.fee {
+        Some(fee) => {
+            // The rest of the plain value moved into confidential outputs, or out of them
+            let burned = fee.saturating_add(data_value);
+            burn(burned);
+            shield(plain_input, plain_output.saturating_add(burned));
+        },
+        None => burn(plain_input.saturating_sub(plain_output)),
+    }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+//! Verification of the proofs served by the UTXO node.
+//!
+//! A light wallet only needs the header of a block, obtained from a source it trusts, to check
+//! the answer of an untrusted RPC node against the header's `state_root` or `extrinsics_root`.
+
+use parity_scale_codec::{Compact, Decode, Encode};
+use sp_core::{Blake2Hasher, H256};
+use sp_trie::{LayoutV0, LayoutV1, MemoryDB, StorageProof, TrieDBMut, TrieMut};
+
+/// Why a proof was rejected
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+	/// The proof does not contain the trie nodes needed to read the key from the root
+	IncompleteProof,
+
+	/// The proven value is not a valid encoding of the expected type
+	InvalidValue,
+
+	/// The proof does not show the claimed value under the key
+	InvalidProof,
+}
+
+/// Read `key` from the state with root `state_root` using the nodes of a read proof,
+/// e.g. the `proof` returned by `utxo_getUtxoProof` or `state_getReadProof`.
+/// Returns `None` if the proof shows that the key is not in the state.
+pub fn verify_read_proof(
+	state_root: &H256,
+	proof: Vec<Vec<u8>>,
+	key: &[u8],
+) -> Result<Option<Vec<u8>>, Error> {
+	let db = StorageProof::new(proof).into_memory_db::<Blake2Hasher>();
+	sp_trie::read_trie_value::<LayoutV1<Blake2Hasher>, _>(&db, state_root, key)
+		.map_err(|_| Error::IncompleteProof)
+}
+
+/// Read the UTXO at `outpoint` from the state with root `state_root`.
+/// `T` is the `TransactionOutput` of the runtime. Returns `None` if the proof shows that the
+/// outpoint is spent or never existed.
+pub fn verify_utxo_proof<T: Decode>(
+	state_root: &H256,
+	outpoint: &H256,
+	proof: Vec<Vec<u8>>,
+) -> Result<Option<T>, Error> {
+	// UTXOs are stored under their raw outpoint
+	match verify_read_proof(state_root, proof, outpoint.as_bytes())? {
+		Some(raw_utxo) => T::decode(&mut &raw_utxo[..]).map(Some).map_err(|_| Error::InvalidValue),
+		None => Ok(None),
+	}
+}
+
+/// Id of a transaction: the hash of its encoded extrinsic, as returned by `author_submitExtrinsic`
+pub fn transaction_id(encoded_extrinsic: &[u8]) -> H256 {
+	sp_core::hashing::blake2_256(encoded_extrinsic).into()
+}
+
+/// Extrinsics root of a block with the given extrinsics and a proof for the one at `index`,
+/// `None` if there is no extrinsic at `index`
+pub fn inclusion_proof(
+	encoded_extrinsics: &[Vec<u8>],
+	index: u32,
+) -> Option<(H256, Vec<Vec<u8>>)> {
+	if index as usize >= encoded_extrinsics.len() {
+		return None
+	}
+
+	// Extrinsics are stored under their SCALE compact encoded index
+	let mut db = MemoryDB::<Blake2Hasher>::default();
+	let mut root = H256::default();
+	{
+		let mut trie = TrieDBMut::<LayoutV0<Blake2Hasher>>::new(&mut db, &mut root);
+		for (i, extrinsic) in encoded_extrinsics.iter().enumerate() {
+			trie.insert(&Compact(i as u32).encode(), extrinsic)
+				.expect("Inserting into an in-memory trie does not fail; qed");
+		}
+	}
+	let key = Compact(index).encode();
+	let proof = sp_trie::generate_trie_proof::<LayoutV0<Blake2Hasher>, _, _, _>(&db, root, &[key])
+		.expect("All nodes of the trie are in memory; qed");
+	Some((root, proof))
+}
+
+/// Check that `encoded_extrinsic` is the extrinsic at `index` of the block with the given
+/// `extrinsics_root`, using a proof returned by `utxo_getInclusionProof`
+pub fn verify_inclusion_proof(
+	extrinsics_root: &H256,
+	index: u32,
+	encoded_extrinsic: &[u8],
+	proof: &[Vec<u8>],
+) -> Result<(), Error> {
+	let key = Compact(index).encode();
+	sp_trie::verify_trie_proof::<LayoutV0<Blake2Hasher>, _, _, _>(
+		extrinsics_root,
+		proof,
+		&[(key, Some(encoded_extrinsic))],
+	)
+	.map_err(|_| Error::InvalidProof)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// State trie holding `entries`, with every node of the trie as the proof
+	fn state(entries: &[(Vec<u8>, Vec<u8>)]) -> (H256, Vec<Vec<u8>>) {
+		let mut db = MemoryDB::<Blake2Hasher>::default();
+		let mut root = H256::default();
+		{
+			let mut trie = TrieDBMut::<LayoutV1<Blake2Hasher>>::new(&mut db, &mut root);
+			for (key, value) in entries {
+				trie.insert(key, value).unwrap();
+			}
+		}
+		let proof = db.drain().into_iter().map(|(_, (node, _))| node).collect();
+		(root, proof)
+	}
+
+	#[test]
+	fn verifies_utxo_proof() {
+		let outpoint = H256::repeat_byte(1);
+		let utxo = (100u128, H256::repeat_byte(2), Option::<Vec<u8>>::None);
+		let (root, proof) = state(&[
+			(outpoint.encode(), utxo.encode()),
+			(b"block_number".to_vec(), 5u32.encode()),
+		]);
+
+		assert_eq!(verify_utxo_proof(&root, &outpoint, proof.clone()), Ok(Some(utxo)));
+		assert_eq!(
+			verify_utxo_proof::<(u128, H256, Option<Vec<u8>>)>(
+				&root,
+				&H256::repeat_byte(3),
+				proof.clone()
+			),
+			Ok(None)
+		);
+		assert_eq!(
+			verify_utxo_proof::<u128>(&H256::repeat_byte(4), &outpoint, proof),
+			Err(Error::IncompleteProof)
+		);
+		assert_eq!(
+			verify_utxo_proof::<u128>(&root, &outpoint, Vec::new()),
+			Err(Error::IncompleteProof)
+		);
+	}
+
+	#[test]
+	fn verifies_inclusion_proof() {
+		let extrinsics: Vec<Vec<u8>> = (0u8..5).map(|i| vec![i; 40]).collect();
+		let (root, proof) = inclusion_proof(&extrinsics, 3).unwrap();
+		assert_eq!(
+			root,
+			<LayoutV0<Blake2Hasher> as sp_trie::TrieConfiguration>::ordered_trie_root(&extrinsics)
+		);
+
+		assert_eq!(verify_inclusion_proof(&root, 3, &extrinsics[3], &proof), Ok(()));
+		assert_eq!(
+			verify_inclusion_proof(&root, 2, &extrinsics[3], &proof),
+			Err(Error::InvalidProof)
+		);
+		assert_eq!(
+			verify_inclusion_proof(&root, 3, &extrinsics[2], &proof),
+			Err(Error::InvalidProof)
+		);
+		assert_eq!(inclusion_proof(&extrinsics, 5), None);
+		assert_eq!(
+			transaction_id(&extrinsics[0]),
+			H256::from(sp_core::hashing::blake2_256(&extrinsics[0]))
+		);
+	}
+}
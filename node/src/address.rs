@@ -0,0 +1,276 @@
+//! Human readable owner addresses.
+//!
+//! The owner of a UTXO is an sr25519 public key. It is shown as an SS58 address, which carries
+//! a checksum and the network prefix of the chain, see `chain_spec::SS58_PREFIX`. Wherever an
+//! owner is taken, the raw key is still accepted as hex.
+//!
+//! Outputs locked to a pubkey hash are owned by the 20 byte hash instead. Its address is built
+//! the same way as an SS58 address, with the hash as payload, so it is shorter than the address
+//! of a key and cannot be mistaken for one. Stealth addresses are built the same way, with the
+//! scan and the spend key as payload.
+
+use sp_core::{
+	crypto::{default_ss58_version, Ss58AddressFormat, Ss58Codec},
+	hashing::blake2_512,
+	sr25519::Public,
+	H160, H256,
+};
+use utxo_frameless_runtime::utxo::Lock;
+
+use crate::stealth::StealthAddress;
+
+/// Prefix of the data checksummed in SS58 addresses
+const SS58_CHECKSUM_PREFIX: &[u8] = b"SS58PRE";
+
+/// Parse an owner given as an SS58 address of the current network or as a 32 byte hex key,
+/// with or without `0x` prefix
+pub fn parse_owner(s: &str) -> Result<H256, String> {
+	if s.starts_with("0x") || (s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())) {
+		let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("{}: {}", s, e))?;
+		if bytes.len() != 32 {
+			return Err(format!("{}: expected 32 bytes, got {}", s, bytes.len()))
+		}
+		return Ok(H256::from_slice(&bytes))
+	}
+
+	let (public, format) = Public::from_ss58check_with_version(s)
+		.map_err(|e| format!("{}: invalid address: {:?}", s, e))?;
+	check_network(s, format)?;
+	Ok(H256::from(public.0))
+}
+
+/// Parse the owner of an output together with its lock. Addresses and hex keys as taken by
+/// `parse_owner` lock the output to the key, pubkey hash addresses and 20 byte hex hashes lock
+/// it to the hash and `script:VALIDATOR_HASH[:DATUM]`, both in hex, locks it to a validator
+/// script. The returned key is zero for pubkey hash and script locks.
+pub fn parse_lock(s: &str) -> Result<(H256, Lock), String> {
+	if let Some(script) = s.strip_prefix("script:") {
+		let (validator, datum) = script.split_once(':').unwrap_or((script, ""));
+		let validator = hex::decode(validator.trim_start_matches("0x"))
+			.ok()
+			.filter(|bytes| bytes.len() == 32)
+			.ok_or_else(|| format!("{}: expected a 32 byte hex validator hash", s))?;
+		let datum =
+			hex::decode(datum.trim_start_matches("0x")).map_err(|e| format!("{}: {}", s, e))?;
+		return Ok((H256::zero(), Lock::Script { validator: H256::from_slice(&validator), datum }))
+	}
+	let hex = s.trim_start_matches("0x");
+	if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		let bytes = hex::decode(hex).map_err(|e| format!("{}: {}", s, e))?;
+		return Ok((H256::zero(), Lock::PubkeyHash(H160::from_slice(&bytes))))
+	}
+	match decode_payload(s) {
+		Some(payload) if payload.payload.len() == 20 =>
+			Ok((H256::zero(), Lock::PubkeyHash(H160::from_slice(&check_payload(s, payload)?)))),
+		_ => Ok((parse_owner(s)?, Lock::Pubkey)),
+	}
+}
+
+/// Parse a stealth address of the current network, `None` if `s` is no stealth address at all
+pub fn parse_stealth_address(s: &str) -> Option<Result<StealthAddress, String>> {
+	let payload = decode_payload(s).filter(|payload| payload.payload.len() == 64)?;
+	Some(check_payload(s, payload).map(|payload| StealthAddress {
+		scan: H256::from_slice(&payload[..32]),
+		spend: H256::from_slice(&payload[32..]),
+	}))
+}
+
+/// SS58 address of `owner` in the format of the current network
+pub fn format_owner(owner: &H256) -> String {
+	Public::from_h256(*owner).to_ss58check()
+}
+
+/// Address of the pubkey hash `hash` on the network with the given format
+pub fn format_pubkey_hash(hash: &H160, format: Ss58AddressFormat) -> String {
+	encode_payload(hash.as_bytes(), format)
+}
+
+/// Stealth address on the network with the given format
+pub fn format_stealth_address(address: &StealthAddress, format: Ss58AddressFormat) -> String {
+	encode_payload(&[address.scan.as_bytes(), address.spend.as_bytes()].concat(), format)
+}
+
+fn encode_payload(payload: &[u8], format: Ss58AddressFormat) -> String {
+	let mut data = encode_prefix(u16::from(format));
+	data.extend_from_slice(payload);
+	let checksum = blake2_512(&[SS58_CHECKSUM_PREFIX, &data[..]].concat());
+	data.extend_from_slice(&checksum[..2]);
+	bs58::encode(data).into_string()
+}
+
+/// Network prefix, payload and checksum of an address
+struct Payload {
+	prefix: Vec<u8>,
+	payload: Vec<u8>,
+	checksum: Vec<u8>,
+}
+
+/// Split an address into its parts, `None` if `s` is no base58 string
+fn decode_payload(s: &str) -> Option<Payload> {
+	let mut data = bs58::decode(s).into_vec().ok()?;
+	let prefix_len = prefix_len(&data);
+	if data.len() < prefix_len + 2 {
+		return None
+	}
+	let checksum = data.split_off(data.len() - 2);
+	let payload = data.split_off(prefix_len);
+	Some(Payload { prefix: data, payload, checksum })
+}
+
+/// Verify the checksum and network of a decoded address, returning its payload
+fn check_payload(s: &str, payload: Payload) -> Result<Vec<u8>, String> {
+	let body = [&payload.prefix[..], &payload.payload[..]].concat();
+	if blake2_512(&[SS58_CHECKSUM_PREFIX, &body[..]].concat())[..2] != payload.checksum[..] {
+		return Err(format!("{}: invalid address checksum", s))
+	}
+	check_network(s, Ss58AddressFormat::custom(decode_prefix(&payload.prefix)))?;
+	Ok(payload.payload)
+}
+
+fn check_network(s: &str, format: Ss58AddressFormat) -> Result<(), String> {
+	if format != default_ss58_version() {
+		return Err(format!(
+			"{}: address of network {}, expected {}",
+			s,
+			u16::from(format),
+			u16::from(default_ss58_version())
+		))
+	}
+	Ok(())
+}
+
+/// Network prefixes below 64 take one byte, larger ones two
+fn prefix_len(data: &[u8]) -> usize {
+	match data.first() {
+		Some(first) if *first >= 64 => 2,
+		_ => 1,
+	}
+}
+
+/// SS58 encoding of a network prefix, the same as used by `Ss58Codec`
+fn encode_prefix(prefix: u16) -> Vec<u8> {
+	let prefix = prefix & 0b0011_1111_1111_1111;
+	match prefix {
+		0..=63 => vec![prefix as u8],
+		_ => {
+			let first = ((prefix & 0b0000_0000_1111_1100) as u8) >> 2;
+			let second = ((prefix >> 8) as u8) | ((prefix & 0b0000_0000_0000_0011) as u8) << 6;
+			vec![first | 0b0100_0000, second]
+		},
+	}
+}
+
+fn decode_prefix(prefix: &[u8]) -> u16 {
+	match prefix {
+		[first] => *first as u16,
+		[first, second] => {
+			let lower = (first << 2) | (second >> 6);
+			let upper = second & 0b0011_1111;
+			(lower as u16) | ((upper as u16) << 8)
+		},
+		_ => unreachable!("prefix_len only splits off one or two bytes; qed"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain_spec::SS58_PREFIX;
+	use sp_core::crypto::set_default_ss58_version;
+
+	const KEY: [u8; 32] = [7; 32];
+	const HASH: [u8; 20] = [9; 20];
+
+	fn set_network() {
+		set_default_ss58_version(Ss58AddressFormat::custom(SS58_PREFIX));
+	}
+
+	/// `s` with its last checksum byte flipped
+	fn corrupt_checksum(s: &str) -> String {
+		let mut data = bs58::decode(s).into_vec().unwrap();
+		*data.last_mut().unwrap() ^= 1;
+		bs58::encode(data).into_string()
+	}
+
+	#[test]
+	fn prefix_encoding_matches_ss58_codec() {
+		for prefix in [0, 2, 42, SS58_PREFIX, 63, 64, 65, 255, 256, 1000, 16383] {
+			let format = Ss58AddressFormat::custom(prefix);
+			let address = Public::from_raw(KEY).to_ss58check_with_version(format);
+			let data = bs58::decode(&address).into_vec().unwrap();
+
+			let encoded = encode_prefix(prefix);
+			assert_eq!(encoded.len(), if prefix < 64 { 1 } else { 2 });
+			assert_eq!(prefix_len(&data), encoded.len());
+			assert_eq!(&data[..encoded.len()], &encoded[..]);
+			assert_eq!(decode_prefix(&encoded), prefix);
+		}
+	}
+
+	#[test]
+	fn parse_lock_of_hex_key() {
+		set_network();
+		let hex_key = hex::encode(KEY);
+		for s in [hex_key.clone(), format!("0x{}", hex_key)] {
+			assert_eq!(parse_lock(&s), Ok((H256::from(KEY), Lock::Pubkey)));
+		}
+		assert!(parse_lock(&format!("0x{}", hex::encode([7; 31]))).is_err());
+	}
+
+	#[test]
+	fn parse_lock_of_address() {
+		set_network();
+		let address = format_owner(&H256::from(KEY));
+		assert_eq!(parse_lock(&address), Ok((H256::from(KEY), Lock::Pubkey)));
+		assert!(parse_lock(&corrupt_checksum(&address)).is_err());
+	}
+
+	#[test]
+	fn parse_lock_of_pubkey_hash() {
+		set_network();
+		let expected = Ok((H256::zero(), Lock::PubkeyHash(H160::from(HASH))));
+		let hex_hash = hex::encode(HASH);
+		assert_eq!(parse_lock(&hex_hash), expected);
+		assert_eq!(parse_lock(&format!("0x{}", hex_hash)), expected);
+
+		let address = format_pubkey_hash(&H160::from(HASH), default_ss58_version());
+		assert_eq!(parse_lock(&address), expected);
+		assert_eq!(
+			parse_lock(&corrupt_checksum(&address)),
+			Err(format!("{}: invalid address checksum", corrupt_checksum(&address)))
+		);
+	}
+
+	#[test]
+	fn parse_lock_of_script() {
+		set_network();
+		let validator = H256::repeat_byte(3);
+		let hex_validator = hex::encode(validator);
+		assert_eq!(
+			parse_lock(&format!("script:{}:0x0102", hex_validator)),
+			Ok((H256::zero(), Lock::Script { validator, datum: vec![1, 2] }))
+		);
+		assert_eq!(
+			parse_lock(&format!("script:0x{}", hex_validator)),
+			Ok((H256::zero(), Lock::Script { validator, datum: Vec::new() }))
+		);
+		assert!(parse_lock(&format!("script:{}", hex::encode([3; 20]))).is_err());
+		assert!(parse_lock(&format!("script:{}:zz", hex_validator)).is_err());
+	}
+
+	#[test]
+	fn parse_lock_rejects_wrong_network() {
+		set_network();
+		let other = Ss58AddressFormat::custom(42);
+		let address = Public::from_raw(KEY).to_ss58check_with_version(other);
+		assert_eq!(
+			parse_lock(&address),
+			Err(format!("{}: address of network 42, expected {}", address, SS58_PREFIX))
+		);
+		let address = format_pubkey_hash(&H160::from(HASH), other);
+		assert_eq!(
+			parse_lock(&address),
+			Err(format!("{}: address of network 42, expected {}", address, SS58_PREFIX))
+		);
+	}
+}
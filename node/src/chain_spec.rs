@@ -1,6 +1,18 @@
-use utxo_frameless_runtime::GenesisConfig as FramelessGenesisConfig;
-use sp_core::H256;
+use crate::address;
+use utxo_frameless_runtime::{
+	utxo::{Lock, TransactionOutput, Value},
+	GenesisConfig as FramelessGenesisConfig,
+};
+use sc_service::{config::MultiaddrWithPeerId, Properties};
+use serde::Deserialize;
+use sp_consensus_aura::sr25519::AuthorityId as AuraId;
+use sp_core::{
+	crypto::{Ss58AddressFormat, Ss58Codec},
+	sr25519, Pair, Public, H256,
+};
+use sp_finality_grandpa::AuthorityId as GrandpaId;
 use hex_literal::hex;
+use std::{path::Path, time::Duration};
 
 // The URL for the telemetry server.
 // const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
@@ -8,27 +20,184 @@ use hex_literal::hex;
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
 pub type ChainSpec = sc_service::GenericChainSpec<FramelessGenesisConfig>;
 
-// /// Generate a crypto pair from seed.
-// pub fn get_from_seed<TPublic: Public>(seed: &str) -> <TPublic::Pair as Pair>::Public {
-// 	TPublic::Pair::from_string(&format!("//{}", seed), None)
-// 		.expect("static values are valid; qed")
-// 		.public()
-// }
+/// Chain spec property holding the Grandpa gossip duration in milliseconds
+pub const GRANDPA_GOSSIP_DURATION_PROPERTY: &str = "grandpaGossipDuration";
 
-// type AccountPublic = <Signature as Verify>::Signer;
+/// Chain spec property holding the number of blocks between Grandpa justifications
+pub const GRANDPA_JUSTIFICATION_PERIOD_PROPERTY: &str = "grandpaJustificationPeriod";
 
-// /// Generate an account ID from seed.
-// pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId
-// where
-// 	AccountPublic: From<<TPublic::Pair as Pair>::Public>,
-// {
-// 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
-// }
+/// Gossip duration used when the chain spec does not set one
+pub const DEFAULT_GRANDPA_GOSSIP_DURATION: u64 = 333;
 
-// /// Generate an Aura authority key.
-// pub fn authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
-// 	(get_from_seed::<AuraId>(s), get_from_seed::<GrandpaId>(s))
-// }
+/// Justification period used when the chain spec does not set one
+pub const DEFAULT_GRANDPA_JUSTIFICATION_PERIOD: u32 = 512;
+
+/// Grandpa timings of a deployment, tuned through the chain spec properties so they can
+/// change without recompiling the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrandpaTimings {
+	pub gossip_duration: Duration,
+	pub justification_period: u32,
+}
+
+impl GrandpaTimings {
+	/// Read the timings from chain spec properties, missing entries take their default
+	pub fn from_properties(properties: &Properties) -> Result<Self, String> {
+		let property = |name: &str, default: u64| match properties.get(name) {
+			Some(value) => value
+				.as_u64()
+				.ok_or_else(|| format!("Chain spec property {} must be a non-negative integer", name)),
+			None => Ok(default),
+		};
+		let gossip_duration =
+			property(GRANDPA_GOSSIP_DURATION_PROPERTY, DEFAULT_GRANDPA_GOSSIP_DURATION)?;
+		let justification_period = property(
+			GRANDPA_JUSTIFICATION_PERIOD_PROPERTY,
+			DEFAULT_GRANDPA_JUSTIFICATION_PERIOD.into(),
+		)?;
+		Ok(Self {
+			gossip_duration: Duration::from_millis(gossip_duration),
+			justification_period: justification_period.try_into().map_err(|_| {
+				format!("Chain spec property {} is too large", GRANDPA_JUSTIFICATION_PERIOD_PROPERTY)
+			})?,
+		})
+	}
+}
+
+/// Chain spec property holding the symbol of the token
+pub const TOKEN_SYMBOL_PROPERTY: &str = "tokenSymbol";
+
+/// Chain spec property holding the number of decimals of the token
+pub const TOKEN_DECIMALS_PROPERTY: &str = "tokenDecimals";
+
+/// Chain spec property holding the SS58 prefix of addresses
+pub const SS58_FORMAT_PROPERTY: &str = "ss58Format";
+
+/// Token symbol of the chain specs built into the node
+pub const TOKEN_SYMBOL: &str = "UTXO";
+
+/// Token decimals of the chain specs built into the node, a UTXO of value 100 holds 1.00 UTXO
+pub const TOKEN_DECIMALS: u8 = 2;
+
+/// SS58 prefix of the chain specs built into the node
+pub const SS58_PREFIX: u16 = 57;
+
+/// How amounts and owner keys of a chain are displayed, read from its chain spec properties
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainProperties {
+	pub token_symbol: String,
+	pub token_decimals: u8,
+	pub ss58_format: Ss58AddressFormat,
+}
+
+impl Default for ChainProperties {
+	fn default() -> Self {
+		Self {
+			token_symbol: TOKEN_SYMBOL.into(),
+			token_decimals: TOKEN_DECIMALS,
+			ss58_format: Ss58AddressFormat::custom(SS58_PREFIX),
+		}
+	}
+}
+
+impl ChainProperties {
+	/// Read the properties of a chain spec, missing entries take the defaults of this node
+	pub fn from_properties(properties: &Properties) -> Result<Self, String> {
+		let mut chain_properties = Self::default();
+		if let Some(symbol) = properties.get(TOKEN_SYMBOL_PROPERTY) {
+			chain_properties.token_symbol = symbol
+				.as_str()
+				.ok_or_else(|| format!("Chain spec property {} must be a string", TOKEN_SYMBOL_PROPERTY))?
+				.into();
+		}
+		if let Some(decimals) = properties.get(TOKEN_DECIMALS_PROPERTY) {
+			chain_properties.token_decimals = decimals
+				.as_u64()
+				.and_then(|decimals| u8::try_from(decimals).ok())
+				.ok_or_else(|| format!("Chain spec property {} must be a u8", TOKEN_DECIMALS_PROPERTY))?;
+		}
+		if let Some(prefix) = properties.get(SS58_FORMAT_PROPERTY) {
+			let prefix = prefix
+				.as_u64()
+				.and_then(|prefix| u16::try_from(prefix).ok())
+				.ok_or_else(|| format!("Chain spec property {} must be a u16", SS58_FORMAT_PROPERTY))?;
+			chain_properties.ss58_format = Ss58AddressFormat::custom(prefix);
+		}
+		Ok(chain_properties)
+	}
+
+	/// `value` in tokens, e.g. `1.05 UTXO` for 105 with two decimals
+	pub fn format_amount(&self, value: Value) -> String {
+		let decimals = self.token_decimals as usize;
+		let digits = format!("{:0>width$}", value, width = decimals + 1);
+		let (units, fraction) = digits.split_at(digits.len() - decimals);
+		if fraction.is_empty() {
+			format!("{} {}", units, self.token_symbol)
+		} else {
+			format!("{}.{} {}", units, fraction, self.token_symbol)
+		}
+	}
+
+	/// Value of `output`, or its commitment if the amount is confidential
+	pub fn format_value(&self, output: &TransactionOutput) -> String {
+		match &output.confidential {
+			Some(confidential) => format!("confidential {:?}", confidential.commitment),
+			None => self.format_amount(output.value),
+		}
+	}
+
+	/// Address of the owner of `output`: the SS58 address of its key, the address of the
+	/// hash it is locked to, or the validator hash and datum of a script output
+	pub fn format_owner(&self, output: &TransactionOutput) -> String {
+		match &output.lock {
+			Lock::Pubkey =>
+				sr25519::Public::from_h256(output.pubkey).to_ss58check_with_version(self.ss58_format),
+			Lock::PubkeyHash(hash) => address::format_pubkey_hash(hash, self.ss58_format),
+			Lock::Script { validator, datum } =>
+				format!("script {:?} datum: 0x{}", validator, hex::encode(datum)),
+		}
+	}
+}
+
+/// Properties of the chain specs built into the node
+fn properties() -> Properties {
+	let mut properties = Properties::new();
+	properties.insert(TOKEN_SYMBOL_PROPERTY.into(), TOKEN_SYMBOL.into());
+	properties.insert(TOKEN_DECIMALS_PROPERTY.into(), TOKEN_DECIMALS.into());
+	properties.insert(SS58_FORMAT_PROPERTY.into(), SS58_PREFIX.into());
+	properties
+		.insert(GRANDPA_GOSSIP_DURATION_PROPERTY.into(), DEFAULT_GRANDPA_GOSSIP_DURATION.into());
+	properties.insert(
+		GRANDPA_JUSTIFICATION_PERIOD_PROPERTY.into(),
+		DEFAULT_GRANDPA_JUSTIFICATION_PERIOD.into(),
+	);
+	properties
+}
+
+/// Peer id of a node started with `--node-key` `LOCAL_BOOTNODE_KEY`
+pub const LOCAL_BOOTNODE_PEER_ID: &str = "12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp";
+
+/// Well-known node key of the bootnode of the local testnet. Never use it outside of
+/// a local network.
+pub const LOCAL_BOOTNODE_KEY: &str =
+	"0000000000000000000000000000000000000000000000000000000000000001";
+
+/// Generate a crypto pair from seed.
+pub fn get_from_seed<TPublic: Public>(seed: &str) -> <TPublic::Pair as Pair>::Public {
+	TPublic::Pair::from_string(&format!("//{}", seed), None)
+		.expect("static values are valid; qed")
+		.public()
+}
+
+/// Generate an Aura authority key.
+pub fn authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
+	(get_from_seed::<AuraId>(s), get_from_seed::<GrandpaId>(s))
+}
+
+/// UTXO owner key of a development account, e.g. `//Bob`
+pub fn owner_from_seed(seed: &str) -> [u8; 32] {
+	get_from_seed::<sr25519::Public>(seed).0
+}
 
 pub fn development_config() -> Result<ChainSpec, String> {
 	Ok(ChainSpec::from_genesis(
@@ -36,41 +205,89 @@ pub fn development_config() -> Result<ChainSpec, String> {
 		"dev",
 		sc_service::ChainType::Development,
 		|| testnet_genesis(
+			vec![authority_keys_from_seed("Alice")],
 			vec![
 				// Alice
 				hex!("d2bf4b844dfefd6772a8843e669f943408966a977e3ae2af1dd78e0f55f4df67"),
 			],
+			false,
 		),
 		vec![],
 		None,
 		None,
 		None,
+		Some(properties()),
+		None,
+	))
+}
+
+/// The development chain mined with proof of work, run with `--pow`. Other chains reject
+/// coinbases.
+pub fn development_pow_config() -> Result<ChainSpec, String> {
+	Ok(ChainSpec::from_genesis(
+		"Development PoW",
+		"dev_pow",
+		sc_service::ChainType::Development,
+		|| testnet_genesis(
+			vec![authority_keys_from_seed("Alice")],
+			vec![
+				// Alice
+				hex!("d2bf4b844dfefd6772a8843e669f943408966a977e3ae2af1dd78e0f55f4df67"),
+			],
+			true,
+		),
+		vec![],
+		None,
 		None,
 		None,
+		Some(properties()),
+		None,
 	))
 }
 
+/// Three validators, Alice, Bob and Charlie, with Alice as the bootnode.
+/// `scripts/local-testnet.sh` starts the network on one machine.
 pub fn local_testnet_config() -> Result<ChainSpec, String> {
+	let bootnode: MultiaddrWithPeerId = format!("/ip4/127.0.0.1/tcp/30333/p2p/{}", LOCAL_BOOTNODE_PEER_ID)
+		.parse()
+		.map_err(|e| format!("Invalid bootnode address: {:?}", e))?;
+
 	Ok(ChainSpec::from_genesis(
 		"Local Testnet",
 		"local_testnet",
 		sc_service::ChainType::Local,
 		|| testnet_genesis(
 			vec![
-				// Alice
+				authority_keys_from_seed("Alice"),
+				authority_keys_from_seed("Bob"),
+				authority_keys_from_seed("Charlie"),
+			],
+			vec![
+				// Alice of the examples in the README
 				hex!("d2bf4b844dfefd6772a8843e669f943408966a977e3ae2af1dd78e0f55f4df67"),
+				owner_from_seed("Alice"),
+				owner_from_seed("Bob"),
+				owner_from_seed("Charlie"),
+				owner_from_seed("Dave"),
+				owner_from_seed("Eve"),
+				owner_from_seed("Ferdie"),
 			],
+			false,
 		),
-		vec![],
-		None,
-		None,
+		vec![bootnode],
 		None,
+		Some("utxo-local"),
 		None,
+		Some(properties()),
 		None,
 	))
 }
 
-fn testnet_genesis(endowed_utxos: Vec<[u8; 32]>) -> FramelessGenesisConfig {
+fn testnet_genesis(
+	authorities: Vec<(AuraId, GrandpaId)>,
+	endowed_utxos: Vec<[u8; 32]>,
+	proof_of_work: bool,
+) -> FramelessGenesisConfig {
 	FramelessGenesisConfig {
 		  genesis_utxos: endowed_utxos
 			.iter()
@@ -78,8 +295,112 @@ fn testnet_genesis(endowed_utxos: Vec<[u8; 32]>) -> FramelessGenesisConfig {
 				utxo_frameless_runtime::utxo::TransactionOutput {
 					value: 100 as utxo_frameless_runtime::utxo::Value,
 					pubkey: H256::from_slice(x),
+					lock: utxo_frameless_runtime::utxo::Lock::Pubkey,
+					data: None,
+					confidential: None,
 				}
 			)
-			.collect()
+			.collect(),
+		limits: Default::default(),
+		fee_increment: utxo_frameless_runtime::utxo::DEFAULT_FEE_INCREMENT,
+		dust_limit: utxo_frameless_runtime::utxo::DEFAULT_DUST_LIMIT,
+		difficulty: utxo_frameless_runtime::pow::DEFAULT_DIFFICULTY.into(),
+		proof_of_work,
+		slot_duration: utxo_frameless_runtime::DEFAULT_SLOT_DURATION,
+		authorities,
+	}
+}
+
+/// One entry of a genesis allocation file
+#[derive(Debug, Deserialize)]
+pub struct Allocation {
+	/// Public key owning the allocated UTXO, as an address or hex key
+	#[serde(deserialize_with = "deserialize_owner")]
+	pub owner: H256,
+
+	/// Value of the allocated UTXO
+	pub amount: Value,
+}
+
+/// Read genesis allocations from a JSON or CSV file, depending on its extension.
+/// JSON files hold an array of `{"owner": "6mdv..", "amount": 100}` objects, CSV files hold
+/// `owner,amount` lines with an optional `owner,amount` header and `#` comments.
+/// Every entry becomes its own UTXO, also when several entries are identical.
+pub fn read_allocations(path: &Path) -> Result<Vec<TransactionOutput>, String> {
+	let content = std::fs::read_to_string(path)
+		.map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+	let allocations = match path.extension().and_then(|extension| extension.to_str()) {
+		Some("json") => serde_json::from_str::<Vec<Allocation>>(&content)
+			.map_err(|e| format!("Invalid allocation file {}: {}", path.display(), e))?,
+		Some("csv") => parse_csv_allocations(&content)?,
+		_ => return Err(format!("{}: expected a .json or .csv allocation file", path.display())),
+	};
+
+	let mut total: Value = 0;
+	for (index, allocation) in allocations.iter().enumerate() {
+		if allocation.amount == 0 {
+			return Err(format!(
+				"Allocation {} to {} has a zero amount",
+				index,
+				address::format_owner(&allocation.owner)
+			))
+		}
+		total = total
+			.checked_add(allocation.amount)
+			.ok_or("Total value of the allocations overflows")?;
 	}
+	if allocations.is_empty() {
+		return Err(format!("{} holds no allocations", path.display()))
+	}
+	log::info!("Read {} genesis allocations worth {} in total", allocations.len(), total);
+
+	Ok(allocations
+		.into_iter()
+		.map(|allocation| TransactionOutput {
+			value: allocation.amount,
+			pubkey: allocation.owner,
+			lock: Lock::Pubkey,
+			data: None,
+			confidential: None,
+		})
+		.collect())
+}
+
+fn deserialize_owner<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<H256, D::Error> {
+	let owner = String::deserialize(deserializer)?;
+	address::parse_owner(&owner).map_err(serde::de::Error::custom)
+}
+
+fn parse_csv_allocations(content: &str) -> Result<Vec<Allocation>, String> {
+	let mut allocations = Vec::new();
+	for (number, line) in content.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+		if line.is_empty() || line.starts_with('#') || line == "owner,amount" {
+			continue
+		}
+		let invalid = |reason: String| format!("Line {}: {}", number, reason);
+		let (owner, amount) = line
+			.split_once(',')
+			.ok_or_else(|| invalid("expected owner,amount".into()))?;
+		let owner = address::parse_owner(owner.trim()).map_err(invalid)?;
+		let amount =
+			amount.trim().parse().map_err(|e| invalid(format!("invalid amount {}: {}", amount, e)))?;
+		allocations.push(Allocation { owner, amount });
+	}
+	Ok(allocations)
+}
+
+/// Copy of `chain_spec` whose genesis UTXOs are replaced by `allocations`.
+/// Raw chain specs have no genesis config left to change.
+pub fn with_allocations(
+	chain_spec: &dyn sc_service::ChainSpec,
+	allocations: Vec<TransactionOutput>,
+) -> Result<Box<dyn sc_service::ChainSpec>, String> {
+	let mut json: serde_json::Value =
+		serde_json::from_str(&chain_spec.as_json(false)?).map_err(|e| e.to_string())?;
+	let genesis_utxos = json
+		.pointer_mut("/genesis/runtime/genesis_utxos")
+		.ok_or("Genesis allocations cannot be applied to a raw chain spec")?;
+	*genesis_utxos = serde_json::to_value(allocations).map_err(|e| e.to_string())?;
+	let bytes = serde_json::to_vec(&json).map_err(|e| e.to_string())?;
+	Ok(Box::new(ChainSpec::from_json_bytes(bytes)?))
 }
@@ -1,4 +1,6 @@
+use crate::service::Sealing;
 use sc_cli::RunCmd;
+use std::path::PathBuf;
 
 #[derive(Debug, clap::Parser)]
 pub struct Cli {
@@ -7,6 +9,22 @@ pub struct Cli {
 
 	#[clap(flatten)]
 	pub run: RunCmd,
+
+	/// Seal blocks on demand instead of running Aura and Grandpa, meant for `--dev` chains and
+	/// tests: `instant` seals a block for every new transaction, `manual` seals a block on every
+	/// `engine_createBlock` RPC call and `interval=MILLIS` seals a block every MILLIS milliseconds.
+	#[clap(long)]
+	pub sealing: Option<Sealing>,
+
+	/// Run proof of work consensus instead of Aura and Grandpa. The chain spec has to enable
+	/// proof of work, e.g. `--chain dev-pow`.
+	#[clap(long, conflicts_with = "sealing")]
+	pub pow: bool,
+
+	/// Mine blocks on the CPU when running with `--pow`, paying the coinbase rewards to this
+	/// address of the chain or hex encoded sr25519 public key
+	#[clap(long, requires = "pow")]
+	pub miner: Option<String>,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -16,7 +34,7 @@ pub enum Subcommand {
 	Key(sc_cli::KeySubcommand),
 
 	/// Build a chain specification.
-	BuildSpec(sc_cli::BuildSpecCmd),
+	BuildSpec(BuildSpecCmd),
 
 	/// Validate blocks.
 	CheckBlock(sc_cli::CheckBlockCmd),
@@ -36,10 +54,34 @@ pub enum Subcommand {
 	/// Revert the chain to a previous state.
 	Revert(sc_cli::RevertCmd),
 
+	/// Partially signed transaction utilities
+	#[clap(subcommand)]
+	Psbt(crate::psbt::PsbtSubcommand),
+
+	/// Wallet utilities
+	#[clap(subcommand)]
+	Wallet(crate::wallet::WalletSubcommand),
+
+	/// Aggregate signature utilities, to spend outputs locked to the aggregate key of several
+	/// participants
+	#[clap(subcommand)]
+	Musig(crate::musig::MusigSubcommand),
+
 	/// Custom -- extend it as you wish.
 	Custom(CustomCommand),
 }
 
+#[derive(Debug, clap::Parser)]
+pub struct BuildSpecCmd {
+	#[clap(flatten)]
+	pub base: sc_cli::BuildSpecCmd,
+
+	/// JSON or CSV file of per-key genesis allocations, replacing the genesis UTXOs of the
+	/// chain spec. Every entry becomes its own UTXO.
+	#[clap(long)]
+	pub genesis_allocations: Option<PathBuf>,
+}
+
 #[derive(Debug, clap::Parser)]
 pub struct CustomCommand {
 	/// The salt to use in the transaction. If none is supplied, a "random" one will be chosen
@@ -1,5 +1,5 @@
 use crate::{
-	chain_spec,
+	address, chain_spec,
 	cli::{Cli, Subcommand},
 	service,
 };
@@ -35,6 +35,7 @@ impl SubstrateCli for Cli {
 	fn load_spec(&self, id: &str) -> Result<Box<dyn sc_service::ChainSpec>, String> {
 		Ok(match id {
 			"dev" => Box::new(chain_spec::development_config()?),
+			"dev-pow" => Box::new(chain_spec::development_pow_config()?),
 			"" | "local" => Box::new(chain_spec::local_testnet_config()?),
 			path =>
 				Box::new(chain_spec::ChainSpec::from_json_file(std::path::PathBuf::from(path))?),
@@ -48,13 +49,28 @@ impl SubstrateCli for Cli {
 
 /// Parse and run command line arguments
 pub fn run() -> sc_cli::Result<()> {
+	// Owner addresses given on the command line are checked against the prefix of the built in
+	// chains, the node itself switches to the prefix of the chain it runs below
+	sp_core::crypto::set_default_ss58_version(chain_spec::ChainProperties::default().ss58_format);
 	let cli = Cli::from_args();
 
 	match &cli.subcommand {
 		Some(Subcommand::Key(cmd)) => cmd.run(&cli),
 		Some(Subcommand::BuildSpec(cmd)) => {
-			let runner = cli.create_runner(cmd)?;
-			runner.sync_run(|config| cmd.run(config.chain_spec, config.network))
+			let runner = cli.create_runner(&cmd.base)?;
+			runner.sync_run(|config| {
+				let properties =
+					chain_spec::ChainProperties::from_properties(&config.chain_spec.properties())?;
+				sp_core::crypto::set_default_ss58_version(properties.ss58_format);
+				let chain_spec = match &cmd.genesis_allocations {
+					Some(path) => chain_spec::with_allocations(
+						&*config.chain_spec,
+						chain_spec::read_allocations(path)?,
+					)?,
+					None => config.chain_spec,
+				};
+				cmd.base.run(chain_spec, config.network)
+			})
 		},
 		Some(Subcommand::CheckBlock(cmd)) => {
 			let runner = cli.create_runner(cmd)?;
@@ -102,13 +118,27 @@ pub fn run() -> sc_cli::Result<()> {
 				Ok((cmd.run(client, backend, Some(aux_revert)), task_manager))
 			})
 		},
+		Some(Subcommand::Psbt(cmd)) => cmd.run(&cli),
+		Some(Subcommand::Wallet(cmd)) => cmd.run(&cli),
+		Some(Subcommand::Musig(cmd)) => cmd.run(),
 		Some(Subcommand::Custom(_)) => {
 			todo!();
 		},
 		None => {
 			let runner = cli.create_runner(&cli.run)?;
+			// Keys in logs and RPCs are shown in the address format of the chain
+			let properties =
+				chain_spec::ChainProperties::from_properties(&runner.config().chain_spec.properties())?;
+			sp_core::crypto::set_default_ss58_version(properties.ss58_format);
+			let miner = cli.miner.as_deref().map(address::parse_owner).transpose()?;
+			let (sealing, pow) = (cli.sealing, cli.pow);
 			runner.run_node_until_exit(|config| async move {
-				service::new_full(config).map_err(sc_cli::Error::Service)
+				match sealing {
+					Some(sealing) => service::new_manual_seal(config, sealing),
+					None if pow => service::new_pow(config, miner),
+					None => service::new_full(config),
+				}
+				.map_err(sc_cli::Error::Service)
 			})
 		},
 	}
@@ -1,2 +1,6 @@
+pub mod address;
 pub mod chain_spec;
+pub mod pow;
+pub mod rpc;
 pub mod service;
+pub mod stealth;
@@ -1,11 +1,18 @@
 //! Substrate Node Template CLI library.
 #![warn(missing_docs)]
 
+mod address;
 mod chain_spec;
 #[macro_use]
 mod service;
 mod cli;
 mod command;
+mod musig;
+mod pow;
+mod psbt;
+mod rpc;
+mod stealth;
+mod wallet;
 
 fn main() -> sc_cli::Result<()> {
 	command::run()
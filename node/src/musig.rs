@@ -0,0 +1,289 @@
+//! Command line utilities to sign an input locked to an aggregate key.
+//!
+//! Every participant runs `start`, `reveal` and `sign` in turn, passing the message each one
+//! prints to all other participants before moving on. The signing session of a participant
+//! holds its secret nonce and is kept in a file only it reads; it is deleted once the partial
+//! signature is made, so that the nonce is never used twice. Anyone holding the partial
+//! signatures of all participants can then `aggregate` them into the signature of the input.
+
+use rand::Rng;
+use sc_cli::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use sp_core::{sr25519::Pair, Pair as PairT, H256};
+use std::{fs, path::PathBuf};
+use utxo_frameless_runtime::{
+	musig::{self, NonceCommitment, NonceReveal, PartialSignature, Session},
+	psbt::PartiallySignedTransaction,
+	utxo::{self, Lock},
+};
+
+use crate::{
+	address::parse_owner,
+	psbt::{print_psbt, read_psbt},
+	stealth::secret_scalar,
+};
+
+/// Aggregate signature utilities
+#[derive(Debug, clap::Subcommand)]
+pub enum MusigSubcommand {
+	/// Start signing an input as one of the participants: save the signing session and print
+	/// the nonce commitment to send to the others
+	Start(StartCmd),
+
+	/// Print the nonce to send to the others, once the commitments of all participants are in
+	Reveal(RevealCmd),
+
+	/// Print the partial signature to send to the others, once the nonces of all participants
+	/// are in. The session is deleted afterwards.
+	Sign(SignCmd),
+
+	/// Combine the partial signatures of all participants into the signature of the input
+	Aggregate(AggregateCmd),
+}
+
+impl MusigSubcommand {
+	/// Run the musig subcommand
+	pub fn run(&self) -> Result<()> {
+		match self {
+			MusigSubcommand::Start(cmd) => cmd.run(),
+			MusigSubcommand::Reveal(cmd) => cmd.run(),
+			MusigSubcommand::Sign(cmd) => cmd.run(),
+			MusigSubcommand::Aggregate(cmd) => cmd.run(),
+		}
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct StartCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+
+	/// Index of the input to sign
+	#[clap(long)]
+	pub input: usize,
+
+	/// Address or hex key of every participant, including this one
+	#[clap(long = "key", required = true, parse(try_from_str = parse_owner))]
+	pub keys: Vec<H256>,
+
+	/// Secret URI of the key of this participant, e.g. a seed phrase or `//Alice`
+	#[clap(long)]
+	pub suri: String,
+
+	/// File to save the signing session to. It holds the secret nonce of this participant.
+	#[clap(long)]
+	pub session: PathBuf,
+}
+
+impl StartCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbt)?;
+		let pair = Pair::from_string(&self.suri, None)
+			.map_err(|e| format!("Invalid secret URI: {:?}", e))?;
+		if self.session.exists() {
+			return Err(format!("Session file {:?} already exists", self.session).into())
+		}
+
+		let aggregate_key = musig::aggregate_key(&self.keys)?;
+		let message = signing_message(&mut psbt, self.input, aggregate_key)?;
+		let mut nonce_seed = [0u8; 64];
+		rand::thread_rng().fill(&mut nonce_seed[..]);
+		let session =
+			Session::new(self.keys.clone(), H256::from(pair.public().0), message, &nonce_seed)?;
+		write_json(&self.session, &session)?;
+		print_json(&session.commitment()?)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct RevealCmd {
+	/// Signing session file of this participant
+	pub session: PathBuf,
+
+	/// File with the nonce commitment of a participant, one for every participant
+	#[clap(long = "commitment", required = true)]
+	pub commitments: Vec<PathBuf>,
+}
+
+impl RevealCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut session: Session = read_json(&self.session, "signing session")?;
+		let commitments = self
+			.commitments
+			.iter()
+			.map(|path| read_json::<NonceCommitment>(path, "nonce commitment"))
+			.collect::<Result<Vec<_>>>()?;
+
+		session.add_commitments(&commitments)?;
+		write_json(&self.session, &session)?;
+		print_json(&session.reveal()?)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct SignCmd {
+	/// Signing session file of this participant
+	pub session: PathBuf,
+
+	/// File with the nonce of a participant, one for every participant
+	#[clap(long = "nonce", required = true)]
+	pub nonces: Vec<PathBuf>,
+
+	/// Secret URI of the key of this participant, e.g. a seed phrase or `//Alice`
+	#[clap(long)]
+	pub suri: String,
+}
+
+impl SignCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut session: Session = read_json(&self.session, "signing session")?;
+		let nonces = self
+			.nonces
+			.iter()
+			.map(|path| read_json::<NonceReveal>(path, "nonce"))
+			.collect::<Result<Vec<_>>>()?;
+		let pair = Pair::from_string(&self.suri, None)
+			.map_err(|e| format!("Invalid secret URI: {:?}", e))?;
+
+		session.add_nonces(&nonces)?;
+		let partial = session.sign(&secret_scalar(&pair))?;
+		// A second signature with the same nonce would reveal the secret key
+		fs::remove_file(&self.session)?;
+		print_json(&partial)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct AggregateCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+
+	/// Index of the signed input
+	#[clap(long)]
+	pub input: usize,
+
+	/// File with the partial signature of a participant, one for every participant
+	#[clap(long = "partial", required = true)]
+	pub partials: Vec<PathBuf>,
+}
+
+impl AggregateCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbt)?;
+		let partials = self
+			.partials
+			.iter()
+			.map(|path| read_json::<PartialSignature>(path, "partial signature"))
+			.collect::<Result<Vec<_>>>()?;
+
+		let keys: Vec<_> = partials.iter().map(|partial| partial.signer).collect();
+		let message = signing_message(&mut psbt, self.input, musig::aggregate_key(&keys)?)?;
+		let signature = musig::aggregate_signature(&message, &partials)?;
+		psbt.add_signature(self.input, signature)?;
+		print_psbt(&psbt)
+	}
+}
+
+/// Payload signed for input `index`, revealing the aggregate key if the input is locked to
+/// its hash
+fn signing_message(
+	psbt: &mut PartiallySignedTransaction,
+	index: usize,
+	aggregate_key: H256,
+) -> Result<Vec<u8>> {
+	let prevout = psbt.inputs.get(index).ok_or("Input index out of range")?.prevout.clone();
+	match prevout.lock {
+		Lock::Pubkey if prevout.pubkey == aggregate_key => (),
+		Lock::PubkeyHash(hash) if hash == utxo::pubkey_hash(&aggregate_key) =>
+			psbt.reveal_pubkey(index, aggregate_key)?,
+		_ => return Err(format!("Input #{} is not locked to the aggregate key", index).into()),
+	}
+	Ok(psbt.signing_payload(index)?)
+}
+
+fn read_json<T: DeserializeOwned>(path: &PathBuf, what: &str) -> Result<T> {
+	let json = fs::read_to_string(path)?;
+	serde_json::from_str(&json).map_err(|e| format!("Invalid {} {:?}: {}", what, path, e).into())
+}
+
+fn write_json(path: &PathBuf, value: &impl Serialize) -> Result<()> {
+	let json = serde_json::to_string_pretty(value)
+		.map_err(|e| format!("Failed to serialize {:?}: {}", path, e))?;
+	Ok(fs::write(path, json)?)
+}
+
+fn print_json(value: &impl Serialize) -> Result<()> {
+	let json = serde_json::to_string_pretty(value)
+		.map_err(|e| format!("Failed to serialize message: {}", e))?;
+	println!("{}", json);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::sr25519::{Public, Signature};
+
+	/// Run every signing round of `message` between the participants with secret URIs `suris`,
+	/// returning their aggregate key and partial signatures
+	fn sign(suris: &[&str], message: &[u8]) -> (H256, Vec<PartialSignature>) {
+		let pairs: Vec<_> =
+			suris.iter().map(|suri| Pair::from_string(suri, None).unwrap()).collect();
+		let keys: Vec<_> = pairs.iter().map(|pair| H256::from(pair.public().0)).collect();
+
+		let mut sessions: Vec<_> = keys
+			.iter()
+			.map(|key| {
+				let mut nonce_seed = [0u8; 64];
+				rand::thread_rng().fill(&mut nonce_seed[..]);
+				Session::new(keys.clone(), *key, message.to_vec(), &nonce_seed).unwrap()
+			})
+			.collect();
+		let commitments: Vec<_> =
+			sessions.iter().map(|session| session.commitment().unwrap()).collect();
+		for session in sessions.iter_mut() {
+			session.add_commitments(&commitments).unwrap();
+		}
+		let nonces: Vec<_> = sessions.iter().map(|session| session.reveal().unwrap()).collect();
+		for session in sessions.iter_mut() {
+			session.add_nonces(&nonces).unwrap();
+		}
+		let partials = sessions
+			.iter()
+			.zip(pairs.iter())
+			.map(|(session, pair)| session.sign(&secret_scalar(pair)).unwrap())
+			.collect();
+
+		let aggregate_key = musig::aggregate_key(&keys).unwrap();
+		assert_eq!(sessions[0].aggregate_key(), Ok(aggregate_key));
+		(aggregate_key, partials)
+	}
+
+	#[test]
+	fn aggregate_signature_verifies_against_aggregate_key() {
+		let message = b"spend the shared output";
+		for suris in [&["//Alice", "//Bob"][..], &["//Alice", "//Bob", "//Charlie"][..]] {
+			let (aggregate_key, partials) = sign(suris, message);
+			let signature = musig::aggregate_signature(message, &partials).unwrap();
+
+			let signature = Signature::from_raw(signature.0);
+			let aggregate_key = Public::from_h256(aggregate_key);
+			assert!(Pair::verify(&signature, message, &aggregate_key));
+			assert!(!Pair::verify(&signature, b"spend another output", &aggregate_key));
+		}
+	}
+
+	#[test]
+	fn aggregate_signature_needs_every_participant() {
+		let message = b"spend the shared output";
+		let (_, partials) = sign(&["//Alice", "//Bob", "//Charlie"], message);
+
+		assert_eq!(
+			musig::aggregate_signature(message, &partials[..2]),
+			Err("Partial signatures were made with different nonces")
+		);
+		let mut forged = partials.clone();
+		forged[2].signature = partials[1].signature;
+		assert_eq!(musig::aggregate_signature(message, &forged), Err("Invalid partial signature"));
+	}
+}
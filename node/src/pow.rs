@@ -0,0 +1,108 @@
+//! Proof of work consensus: the Blake2 seal algorithm and the pieces of the CPU miner.
+//!
+//! The seal of a block is the SCALE encoded `U256` nonce found by the miner. It is valid if the
+//! Blake2 hash of the block's pre-hash and the nonce, read as a number, times the difficulty
+//! stored in the runtime fits in 256 bits.
+
+use crate::service::FullClient;
+use sc_consensus_pow::{Error, PowAlgorithm};
+use sp_api::ProvideRuntimeApi;
+use sp_consensus_pow::{DifficultyApi, Seal};
+use sp_core::{hashing::blake2_256, Decode, Encode, H256, U256};
+use sp_inherents::{InherentData, InherentIdentifier};
+use sp_runtime::generic::BlockId;
+use std::{sync::Arc, task::Poll};
+use utxo_frameless_runtime::{opaque::Block, pow::COINBASE_INHERENT_IDENTIFIER};
+
+/// Work done by `nonce` on the block with pre-hash `pre_hash`
+pub fn work(pre_hash: &H256, nonce: U256) -> H256 {
+	blake2_256(&(pre_hash, nonce).encode()).into()
+}
+
+/// Whether `work` meets `difficulty`
+pub fn meets_difficulty(work: &H256, difficulty: U256) -> bool {
+	let (_, overflowed) = U256::from(work.as_bytes()).overflowing_mul(difficulty);
+	!overflowed
+}
+
+/// Try `rounds` nonces starting at `nonce`, which is left at the first untried one.
+/// Returns the seal if one of them meets `difficulty`.
+pub fn mine(pre_hash: &H256, difficulty: U256, nonce: &mut U256, rounds: usize) -> Option<Seal> {
+	for _ in 0..rounds {
+		let candidate = *nonce;
+		*nonce = nonce.overflowing_add(U256::one()).0;
+		if meets_difficulty(&work(pre_hash, candidate), difficulty) {
+			return Some(candidate.encode())
+		}
+	}
+	None
+}
+
+/// Return to the executor once, so a task busy mining does not keep it from stopping the task
+pub async fn yield_now() {
+	let mut yielded = false;
+	futures::future::poll_fn(|cx| {
+		if yielded {
+			return Poll::Ready(())
+		}
+		yielded = true;
+		cx.waker().wake_by_ref();
+		Poll::Pending
+	})
+	.await
+}
+
+/// Blake2 proof of work with the difficulty held in the runtime
+#[derive(Clone)]
+pub struct Blake2Algorithm {
+	client: Arc<FullClient>,
+}
+
+impl Blake2Algorithm {
+	pub fn new(client: Arc<FullClient>) -> Self {
+		Self { client }
+	}
+}
+
+impl PowAlgorithm<Block> for Blake2Algorithm {
+	type Difficulty = U256;
+
+	fn difficulty(&self, parent: H256) -> Result<U256, Error<Block>> {
+		self.client.runtime_api().difficulty(&BlockId::Hash(parent)).map_err(|e| {
+			Error::Environment(format!("Fetching the difficulty from the runtime failed: {:?}", e))
+		})
+	}
+
+	fn verify(
+		&self,
+		_parent: &BlockId<Block>,
+		pre_hash: &H256,
+		_pre_digest: Option<&[u8]>,
+		seal: &Seal,
+		difficulty: U256,
+	) -> Result<bool, Error<Block>> {
+		let nonce = match U256::decode(&mut &seal[..]) {
+			Ok(nonce) => nonce,
+			Err(_) => return Ok(false),
+		};
+		Ok(meets_difficulty(&work(pre_hash, nonce), difficulty))
+	}
+}
+
+/// Provides the public key the coinbase of a mined block pays its reward to
+pub struct CoinbaseInherentDataProvider(pub H256);
+
+#[async_trait::async_trait]
+impl sp_inherents::InherentDataProvider for CoinbaseInherentDataProvider {
+	fn provide_inherent_data(&self, data: &mut InherentData) -> Result<(), sp_inherents::Error> {
+		data.put_data(COINBASE_INHERENT_IDENTIFIER, &self.0)
+	}
+
+	async fn try_handle_error(
+		&self,
+		_identifier: &InherentIdentifier,
+		_error: &[u8],
+	) -> Option<Result<(), sp_inherents::Error>> {
+		None
+	}
+}
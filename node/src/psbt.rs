@@ -0,0 +1,466 @@
+//! Command line utilities to build and sign partially signed transactions.
+//!
+//! Partially signed transactions are stored as JSON so that they can be passed
+//! between the parties funding a transaction. Every command prints its result to stdout.
+
+use sc_cli::{Result, SubstrateCli};
+use sp_core::{
+	sr25519::{Pair, Public, Signature},
+	Encode, Pair as PairT, H256, H512,
+};
+use sp_runtime::traits::Extrinsic;
+use std::{fs, path::PathBuf};
+use utxo_frameless_runtime::{
+	psbt::PartiallySignedTransaction,
+	script::Redeemer,
+	utxo::{self, Lock, SigHash, Transaction, TransactionInput, TransactionOutput, Value},
+	BasicExtrinsic,
+};
+
+use crate::{
+	address::{parse_lock, parse_stealth_address},
+	chain_spec::ChainProperties,
+	stealth::{StealthAddress, StealthPayment},
+};
+
+/// Partially signed transaction utilities
+#[derive(Debug, clap::Subcommand)]
+pub enum PsbtSubcommand {
+	/// Create a new partially signed transaction
+	Create(CreateCmd),
+
+	/// Sign every input owned by the given key
+	Sign(SignCmd),
+
+	/// Spend an input locked to a script with the validator and its arguments
+	Redeem(RedeemCmd),
+
+	/// Merge the signatures of several copies of the same partially signed transaction
+	Combine(CombineCmd),
+
+	/// Print a human readable summary
+	Inspect(InspectCmd),
+
+	/// Print the fully signed transaction as an extrinsic ready for `author_submitExtrinsic`
+	Finalize(FinalizeCmd),
+}
+
+impl PsbtSubcommand {
+	/// Run the psbt subcommand
+	pub fn run(&self, cli: &impl SubstrateCli) -> Result<()> {
+		match self {
+			PsbtSubcommand::Create(cmd) => cmd.run(),
+			PsbtSubcommand::Sign(cmd) => cmd.run(),
+			PsbtSubcommand::Redeem(cmd) => cmd.run(),
+			PsbtSubcommand::Combine(cmd) => cmd.run(),
+			PsbtSubcommand::Inspect(cmd) => cmd.run(cli),
+			PsbtSubcommand::Finalize(cmd) => cmd.run(),
+		}
+	}
+}
+
+/// An input together with the UTXO it spends
+#[derive(Debug, Clone)]
+pub struct InputArg {
+	input: TransactionInput,
+	prevout: TransactionOutput,
+}
+
+/// An output to create, paid to its owner or to a stealth address
+#[derive(Debug, Clone)]
+pub enum OutputArg {
+	Output(TransactionOutput),
+	Stealth(Value, StealthAddress),
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CreateCmd {
+	/// Input to spend, as `OUTPOINT:VALUE:OWNER[:SIGHASH]`. VALUE and OWNER describe the UTXO
+	/// being spent, OWNER is given as for `--output`. SIGHASH is one of all, none, single,
+	/// all-anyone-can-pay, none-anyone-can-pay or single-anyone-can-pay and defaults to all.
+	#[clap(long = "input", required = true, parse(try_from_str = parse_input))]
+	pub inputs: Vec<InputArg>,
+
+	/// Output to create, as `VALUE:OWNER`. OWNER is an address or hex key, a pubkey hash
+	/// address or 20 byte hex hash to only reveal the key when the output is spent, a
+	/// stealth address to pay a one-time key only the receiver can link to it, or
+	/// `script:VALIDATOR_HASH[:DATUM]` to lock the output to a validator script.
+	#[clap(long = "output", required = true, parse(try_from_str = parse_output))]
+	pub outputs: Vec<OutputArg>,
+
+	/// Hex encoded data, at most 80 bytes, carried by an additional unspendable output of value 0
+	#[clap(long)]
+	pub data: Option<String>,
+
+	/// First block the transaction may be included in
+	#[clap(long)]
+	pub valid_from: Option<u32>,
+
+	/// Last block the transaction may be included in. Wallets can safely rebroadcast a new
+	/// version of the payment once this block has passed.
+	#[clap(long)]
+	pub valid_until: Option<u32>,
+
+	/// Genesis hash of the chain the transaction is for, as returned by `chain_getBlockHash(0)`.
+	/// Signatures are only valid on this chain.
+	#[clap(long, parse(try_from_str = parse_h256))]
+	pub genesis_hash: H256,
+
+	/// Free form note passed along to the other signers
+	#[clap(long)]
+	pub metadata: Option<String>,
+}
+
+impl CreateCmd {
+	pub fn run(&self) -> Result<()> {
+		// Outputs paying stealth addresses share one ephemeral key, announced after them
+		let mut payment = None;
+		let mut outputs = Vec::new();
+		for (index, output) in self.outputs.iter().enumerate() {
+			outputs.push(match output {
+				OutputArg::Output(output) => output.clone(),
+				OutputArg::Stealth(value, address) => TransactionOutput {
+					value: *value,
+					pubkey: payment
+						.get_or_insert_with(StealthPayment::random)
+						.one_time_key(address, index as u64)?,
+					lock: Lock::Pubkey,
+					data: None,
+					confidential: None,
+				},
+			});
+		}
+		if let Some(payment) = &payment {
+			outputs.push(payment.announcement());
+		}
+		if let Some(data) = &self.data {
+			let data = hex::decode(data.trim_start_matches("0x"))
+				.map_err(|e| format!("Invalid data {}: {}", data, e))?;
+			outputs.push(TransactionOutput {
+				value: 0,
+				pubkey: H256::zero(),
+				lock: Lock::Pubkey,
+				data: Some(data),
+				confidential: None,
+			});
+		}
+
+		let transaction = Transaction {
+			inputs: self.inputs.iter().map(|arg| arg.input.clone()).collect(),
+			outputs,
+			valid_from: self.valid_from,
+			valid_until: self.valid_until,
+			fee: None,
+		};
+		let prevouts = self.inputs.iter().map(|arg| arg.prevout.clone()).collect();
+
+		let mut psbt = PartiallySignedTransaction::new(transaction, prevouts, self.genesis_hash)?;
+		psbt.metadata = self.metadata.as_ref().map(|note| note.as_bytes().to_vec());
+		print_psbt(&psbt)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct SignCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+
+	/// Secret URI of the signing key, e.g. a seed phrase or `//Alice`
+	#[clap(long)]
+	pub suri: String,
+}
+
+impl SignCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbt)?;
+		let pair = Pair::from_string(&self.suri, None)
+			.map_err(|e| format!("Invalid secret URI: {:?}", e))?;
+
+		let signed = sign_psbt(&mut psbt, &pair)?;
+		if signed == 0 {
+			return Err("None of the inputs is owned by this key".into())
+		}
+		print_psbt(&psbt)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct RedeemCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+
+	/// Index of the input to spend
+	#[clap(long)]
+	pub input: usize,
+
+	/// Hex encoded validator script, hashing to the validator hash of the spent output
+	#[clap(long)]
+	pub validator: String,
+
+	/// Argument pushed on the stack before the validator runs, in order. Either hex data or
+	/// `sig:SURI` for the signature of the input by that key, e.g. `sig://Alice`.
+	#[clap(long = "arg")]
+	pub arguments: Vec<String>,
+}
+
+impl RedeemCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbt)?;
+		let validator = hex::decode(self.validator.trim_start_matches("0x"))
+			.map_err(|e| format!("Invalid validator {}: {}", self.validator, e))?;
+		let payload = psbt.signing_payload(self.input)?;
+
+		let mut arguments = Vec::new();
+		for argument in &self.arguments {
+			arguments.push(match argument.strip_prefix("sig:") {
+				Some(suri) => {
+					let pair = Pair::from_string(suri, None)
+						.map_err(|e| format!("Invalid secret URI: {:?}", e))?;
+					pair.sign(&payload).0.to_vec()
+				},
+				None => hex::decode(argument.trim_start_matches("0x"))
+					.map_err(|e| format!("Invalid argument {}: {}", argument, e))?,
+			});
+		}
+
+		psbt.add_redeemer(self.input, Redeemer { validator, arguments })?;
+		print_psbt(&psbt)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CombineCmd {
+	/// Partially signed transaction files to merge
+	#[clap(required = true, min_values = 2)]
+	pub psbts: Vec<PathBuf>,
+}
+
+impl CombineCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbts[0])?;
+		for path in self.psbts.iter().skip(1) {
+			psbt.merge(read_psbt(path)?)?;
+		}
+		print_psbt(&psbt)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct InspectCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+
+	/// Chain spec whose token symbol, decimals and SS58 prefix are used to display amounts
+	/// and owners: `dev`, `local` or a chain spec file
+	#[clap(long, default_value = "dev")]
+	pub chain: String,
+}
+
+impl InspectCmd {
+	pub fn run(&self, cli: &impl SubstrateCli) -> Result<()> {
+		let psbt = read_psbt(&self.psbt)?;
+		let properties = ChainProperties::from_properties(&cli.load_spec(&self.chain)?.properties())?;
+
+		println!("Genesis hash: {:?}", psbt.genesis_hash);
+		println!("Transaction version: {}", psbt.transaction_version);
+		println!(
+			"Valid from block: {:?}, until block: {:?}",
+			psbt.transaction.valid_from, psbt.transaction.valid_until
+		);
+		println!("Inputs:");
+		for (index, (input, psbt_input)) in
+			psbt.transaction.inputs.iter().zip(psbt.inputs.iter()).enumerate()
+		{
+			let status = match psbt_input.signature {
+				_ if psbt_input.redeemer.is_some() => "redeemed",
+				None => "unsigned",
+				Some(signature) if verify_input(&psbt, index, &signature)? => "signed",
+				Some(_) => "INVALID SIGNATURE",
+			};
+			println!(
+				"  #{} {:?} value: {} owner: {} sighash: {:?} ({})",
+				index,
+				input.outpoint,
+				properties.format_value(&psbt_input.prevout),
+				properties.format_owner(&psbt_input.prevout),
+				input.sighash,
+				status,
+			);
+		}
+
+		println!("Outputs:");
+		for (index, output) in psbt.transaction.outputs.iter().enumerate() {
+			match &output.data {
+				None => println!(
+					"  #{} value: {} owner: {}",
+					index,
+					properties.format_value(output),
+					properties.format_owner(output),
+				),
+				Some(data) => println!(
+					"  #{} value: {} data: 0x{}",
+					index,
+					properties.format_amount(output.value),
+					hex::encode(data),
+				),
+			}
+		}
+
+		match psbt.fee() {
+			Some(fee) => println!("Fee: {}", properties.format_amount(fee)),
+			None => println!("Fee: outputs exceed inputs"),
+		}
+		if let Some(metadata) = &psbt.metadata {
+			println!("Metadata: {}", String::from_utf8_lossy(metadata));
+		}
+		println!("Complete: {}", psbt.is_complete());
+		Ok(())
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct FinalizeCmd {
+	/// Partially signed transaction file
+	pub psbt: PathBuf,
+}
+
+impl FinalizeCmd {
+	pub fn run(&self) -> Result<()> {
+		let psbt = read_psbt(&self.psbt)?;
+		for (index, input) in psbt.inputs.iter().enumerate() {
+			if let Some(signature) = input.signature {
+				if !verify_input(&psbt, index, &signature)? {
+					return Err(format!("Invalid signature for input #{}", index).into())
+				}
+			}
+		}
+
+		let transaction = psbt.finalize()?;
+		let extrinsic = BasicExtrinsic::new(transaction, None)
+			.expect("Unsigned extrinsics can always be created; qed");
+		println!("0x{}", hex::encode(extrinsic.encode()));
+		Ok(())
+	}
+}
+
+/// Sign all inputs owned by `pair`, returning how many were signed.
+/// Inputs locked to the hash of its key get the key revealed.
+pub fn sign_psbt(psbt: &mut PartiallySignedTransaction, pair: &Pair) -> Result<usize> {
+	let owner = H256::from(pair.public().0);
+	let mut signed = 0;
+	for index in 0..psbt.inputs.len() {
+		let prevout = &psbt.inputs[index].prevout;
+		let reveal = match prevout.lock {
+			Lock::Pubkey if prevout.pubkey == owner => false,
+			Lock::PubkeyHash(hash) if hash == utxo::pubkey_hash(&owner) => true,
+			_ => continue,
+		};
+		if reveal {
+			psbt.reveal_pubkey(index, owner)?;
+		}
+		let signature = pair.sign(&psbt.signing_payload(index)?);
+		psbt.add_signature(index, H512::from(signature))?;
+		signed += 1;
+	}
+	Ok(signed)
+}
+
+fn verify_input(psbt: &PartiallySignedTransaction, index: usize, signature: &H512) -> Result<bool> {
+	let payload = psbt.signing_payload(index)?;
+	Ok(Pair::verify(
+		&Signature::from_raw(*signature.as_fixed_bytes()),
+		&payload,
+		&Public::from_h256(psbt.signer(index)?),
+	))
+}
+
+pub fn read_psbt(path: &PathBuf) -> Result<PartiallySignedTransaction> {
+	let json = fs::read_to_string(path)?;
+	serde_json::from_str(&json)
+		.map_err(|e| format!("Invalid partially signed transaction {:?}: {}", path, e).into())
+}
+
+pub fn print_psbt(psbt: &PartiallySignedTransaction) -> Result<()> {
+	let json = serde_json::to_string_pretty(psbt)
+		.map_err(|e| format!("Failed to serialize partially signed transaction: {}", e))?;
+	println!("{}", json);
+	Ok(())
+}
+
+/// Parse a 32 byte hex string, with or without `0x` prefix
+pub fn parse_h256(s: &str) -> std::result::Result<H256, String> {
+	let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("{}: {}", s, e))?;
+	if bytes.len() != 32 {
+		return Err(format!("{}: expected 32 bytes, got {}", s, bytes.len()))
+	}
+	Ok(H256::from_slice(&bytes))
+}
+
+fn parse_value(s: &str) -> std::result::Result<Value, String> {
+	s.parse::<Value>().map_err(|e| format!("Invalid value {}: {}", s, e))
+}
+
+fn parse_sighash(s: &str) -> std::result::Result<SigHash, String> {
+	Ok(match s {
+		"all" => SigHash::All,
+		"none" => SigHash::None,
+		"single" => SigHash::Single,
+		"all-anyone-can-pay" => SigHash::AllAnyoneCanPay,
+		"none-anyone-can-pay" => SigHash::NoneAnyoneCanPay,
+		"single-anyone-can-pay" => SigHash::SingleAnyoneCanPay,
+		_ => return Err(format!("Unknown sighash {}", s)),
+	})
+}
+
+fn parse_input(s: &str) -> std::result::Result<InputArg, String> {
+	let parts: Vec<&str> = s.splitn(3, ':').collect();
+	if parts.len() != 3 {
+		return Err(format!("Expected OUTPOINT:VALUE:OWNER[:SIGHASH], got {}", s))
+	}
+	// Script owners contain colons themselves
+	let (owner, sighash) = match parts[2].rsplit_once(':') {
+		Some((owner, sighash)) => match parse_sighash(sighash) {
+			Ok(sighash) => (owner, sighash),
+			Err(_) => (parts[2], SigHash::All),
+		},
+		None => (parts[2], SigHash::All),
+	};
+	let (pubkey, lock) = parse_lock(owner)?;
+
+	Ok(InputArg {
+		input: TransactionInput {
+			outpoint: parse_h256(parts[0])?,
+			sigscript: H512::zero(),
+			sighash,
+			pubkey: None,
+			redeemer: None,
+		},
+		prevout: TransactionOutput {
+			value: parse_value(parts[1])?,
+			pubkey,
+			lock,
+			data: None,
+			confidential: None,
+		},
+	})
+}
+
+fn parse_output(s: &str) -> std::result::Result<OutputArg, String> {
+	match s.split_once(':') {
+		Some((value, owner)) => {
+			let value = parse_value(value)?;
+			if let Some(address) = parse_stealth_address(owner) {
+				return Ok(OutputArg::Stealth(value, address?))
+			}
+			let (pubkey, lock) = parse_lock(owner)?;
+			Ok(OutputArg::Output(TransactionOutput {
+				value,
+				pubkey,
+				lock,
+				data: None,
+				confidential: None,
+			}))
+		},
+		None => Err(format!("Expected VALUE:OWNER, got {}", s)),
+	}
+}
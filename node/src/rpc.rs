@@ -0,0 +1,185 @@
+//! RPCs of the UTXO node, on top of the standard Substrate ones.
+
+use crate::{chain_spec::ChainProperties, service::FullClient};
+use futures::channel::mpsc;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use sc_consensus_manual_seal::{
+	rpc::{ManualSeal, ManualSealApi},
+	EngineCommand,
+};
+use sc_client_api::{BlockBackend, ProofProvider, StorageProvider};
+use serde::{Deserialize, Serialize};
+use sp_blockchain::HeaderBackend;
+use sp_core::{storage::StorageKey, Bytes, Decode, Encode, H256};
+use sp_runtime::{generic::BlockId, traits::Header as HeaderT};
+use std::sync::Arc;
+use utxo_frameless_runtime::utxo::TransactionOutput;
+
+/// RPC extensions of the node. `properties` of the chain are used to display amounts and owners.
+/// `command_sink` receives the commands of the manual seal RPCs such as `engine_createBlock`,
+/// when blocks are sealed manually.
+pub fn create_full(
+	client: Arc<FullClient>,
+	properties: ChainProperties,
+	command_sink: Option<mpsc::Sender<EngineCommand<H256>>>,
+) -> jsonrpc_core::IoHandler<sc_rpc::Metadata> {
+	let mut io = jsonrpc_core::IoHandler::default();
+	io.extend_with(UtxoApi::to_delegate(Utxo::new(client, properties)));
+	if let Some(command_sink) = command_sink {
+		io.extend_with(ManualSealApi::to_delegate(ManualSeal::new(command_sink)));
+	}
+	io
+}
+
+/// A UTXO together with the proof that it is in the state of a block
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UtxoProof {
+	/// Block the proof is for
+	pub block: H256,
+
+	/// State root of the block, to be compared with the header obtained from a trusted source
+	pub state_root: H256,
+
+	/// The UTXO, `None` if the outpoint is not in the UTXO set at this block
+	pub utxo: Option<TransactionOutput>,
+
+	/// Value of the UTXO in tokens of the chain, e.g. `1.00 UTXO`
+	pub formatted_value: Option<String>,
+
+	/// Owner of the UTXO as an SS58 address of the chain
+	pub owner_address: Option<String>,
+
+	/// Trie nodes proving the value stored under the outpoint
+	pub proof: Vec<Bytes>,
+}
+
+/// Proof that a transaction is included in a block
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProof {
+	/// Block including the transaction
+	pub block: H256,
+
+	/// Extrinsics root of the block, to be compared with the header obtained from a trusted source
+	pub extrinsics_root: H256,
+
+	/// Index of the transaction in the block
+	pub index: u32,
+
+	/// The encoded extrinsic of the transaction
+	pub extrinsic: Bytes,
+
+	/// Trie nodes proving the extrinsic at `index` against `extrinsics_root`
+	pub proof: Vec<Bytes>,
+}
+
+#[rpc]
+pub trait UtxoApi {
+	/// UTXO at `outpoint` with a read proof of its storage key, at block `at` or the best block.
+	/// Verify it with `utxo_light_client::verify_utxo_proof`.
+	#[rpc(name = "utxo_getUtxoProof")]
+	fn utxo_proof(&self, outpoint: H256, at: Option<H256>) -> Result<UtxoProof>;
+
+	/// Proof that the transaction with id `txid`, the hash of its encoded extrinsic, is included
+	/// in block `block`. Verify it with `utxo_light_client::verify_inclusion_proof`.
+	#[rpc(name = "utxo_getInclusionProof")]
+	fn inclusion_proof(&self, txid: H256, block: H256) -> Result<InclusionProof>;
+}
+
+/// Implementation of `UtxoApi` on top of the full client
+pub struct Utxo {
+	client: Arc<FullClient>,
+	properties: ChainProperties,
+}
+
+impl Utxo {
+	pub fn new(client: Arc<FullClient>, properties: ChainProperties) -> Self {
+		Self { client, properties }
+	}
+}
+
+fn client_error(e: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::InternalError,
+		message: "Unable to read from the client".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+fn unknown_block(block: H256) -> RpcError {
+	RpcError {
+		code: ErrorCode::InvalidParams,
+		message: format!("Unknown block {:?}", block),
+		data: None,
+	}
+}
+
+impl UtxoApi for Utxo {
+	fn utxo_proof(&self, outpoint: H256, at: Option<H256>) -> Result<UtxoProof> {
+		let block = at.unwrap_or_else(|| self.client.info().best_hash);
+		let id = BlockId::Hash(block);
+		let header =
+			self.client.header(id).map_err(client_error)?.ok_or_else(|| unknown_block(block))?;
+
+		// UTXOs are stored under their raw outpoint
+		let key = outpoint.encode();
+		let utxo = self
+			.client
+			.storage(&id, &StorageKey(key.clone()))
+			.map_err(client_error)?
+			.map(|data| TransactionOutput::decode(&mut &data.0[..]))
+			.transpose()
+			.map_err(client_error)?;
+		let proof = self
+			.client
+			.read_proof(&id, &mut std::iter::once(&key[..]))
+			.map_err(client_error)?;
+
+		Ok(UtxoProof {
+			block,
+			state_root: *header.state_root(),
+			formatted_value: utxo.as_ref().map(|utxo| self.properties.format_value(utxo)),
+			owner_address: utxo.as_ref().map(|utxo| self.properties.format_owner(utxo)),
+			utxo,
+			proof: proof.into_iter_nodes().map(Bytes).collect(),
+		})
+	}
+
+	fn inclusion_proof(&self, txid: H256, block: H256) -> Result<InclusionProof> {
+		let id = BlockId::Hash(block);
+		let header =
+			self.client.header(id).map_err(client_error)?.ok_or_else(|| unknown_block(block))?;
+		let extrinsics: Vec<Vec<u8>> = self
+			.client
+			.block_body(&id)
+			.map_err(client_error)?
+			.ok_or_else(|| unknown_block(block))?
+			.iter()
+			.map(Encode::encode)
+			.collect();
+
+		let index = extrinsics
+			.iter()
+			.position(|extrinsic| utxo_light_client::transaction_id(extrinsic) == txid)
+			.ok_or_else(|| RpcError {
+				code: ErrorCode::InvalidParams,
+				message: format!("Transaction {:?} is not in block {:?}", txid, block),
+				data: None,
+			})? as u32;
+		let (extrinsics_root, proof) = utxo_light_client::inclusion_proof(&extrinsics, index)
+			.expect("The transaction was found at index; qed");
+		if extrinsics_root != *header.extrinsics_root() {
+			return Err(client_error("Extrinsics do not match the extrinsics root of the header"))
+		}
+
+		Ok(InclusionProof {
+			block,
+			extrinsics_root,
+			index,
+			extrinsic: Bytes(extrinsics[index as usize].clone()),
+			proof: proof.into_iter().map(Bytes).collect(),
+		})
+	}
+}
@@ -1,15 +1,18 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
 
+use futures::{FutureExt, Stream};
 use utxo_frameless_runtime::{self, opaque::Block, RuntimeApi};
 use sc_client_api::{BlockBackend, ExecutorProvider};
 use sc_consensus_aura::{ImportQueueParams, SlotProportion, StartAuraParams};
+use sc_consensus_manual_seal::{EngineCommand, InstantSealParams, ManualSealParams};
 pub use sc_executor::NativeElseWasmExecutor;
 use sc_finality_grandpa::SharedVoterState;
 use sc_keystore::LocalKeystore;
 use sc_service::{error::Error as ServiceError, Configuration, TaskManager};
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
-use std::{sync::Arc, time::Duration};
+use sp_core::{H256, U256};
+use std::{pin::Pin, str::FromStr, sync::Arc, time::Duration};
 
 // Our native executor instance.
 pub struct ExecutorDispatch;
@@ -31,6 +34,40 @@ impl sc_executor::NativeExecutionDispatch for ExecutorDispatch {
 	}
 }
 
+/// How blocks are produced when running with `--sealing`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sealing {
+	/// A block is sealed as soon as a transaction enters the pool
+	Instant,
+	/// A block is sealed on every `engine_createBlock` RPC call
+	Manual,
+	/// A block is sealed every given number of milliseconds
+	Interval(u64),
+}
+
+impl FromStr for Sealing {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"instant" => Sealing::Instant,
+			"manual" => Sealing::Manual,
+			_ => match s.strip_prefix("interval=") {
+				Some(millis) => {
+					let interval: u64 =
+						millis.parse().map_err(|e| format!("Invalid interval {}: {}", millis, e))?;
+					// A zero interval would seal blocks in a busy loop
+					if interval == 0 {
+						return Err("The sealing interval must be at least 1 millisecond".into())
+					}
+					Sealing::Interval(interval)
+				},
+				None => return Err(format!("Expected instant, manual or interval=MILLIS, got {}", s)),
+			},
+		})
+	}
+}
+
 pub(crate) type FullClient =
 	sc_service::TFullClient<Block, RuntimeApi, NativeElseWasmExecutor<ExecutorDispatch>>;
 type FullBackend = sc_service::TFullBackend<Block>;
@@ -179,6 +216,9 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 				))),
 		};
 	}
+	let grandpa_timings =
+		crate::chain_spec::GrandpaTimings::from_properties(&config.chain_spec.properties())
+			.map_err(ServiceError::Other)?;
 	let grandpa_protocol_name = sc_finality_grandpa::protocol_standard_name(
 		&client.block_hash(0).ok().flatten().expect("Genesis block exists; qed"),
 		&config.chain_spec,
@@ -221,13 +261,22 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 	let enable_grandpa = !config.disable_grandpa;
 	let prometheus_registry = config.prometheus_registry().cloned();
 
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let properties =
+			crate::chain_spec::ChainProperties::from_properties(&config.chain_spec.properties())
+				.map_err(ServiceError::Other)?;
+
+		Box::new(move |_, _| Ok(crate::rpc::create_full(client.clone(), properties.clone(), None)))
+	};
+
 	let _rpc_handlers = sc_service::spawn_tasks(sc_service::SpawnTasksParams {
 		network: network.clone(),
 		client: client.clone(),
 		keystore: keystore_container.sync_keystore(),
 		task_manager: &mut task_manager,
 		transaction_pool: transaction_pool.clone(),
-		rpc_extensions_builder: Box::new(|_, _| Ok(())),
+		rpc_extensions_builder,
 		backend,
 		system_rpc_tx,
 		config,
@@ -291,9 +340,8 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		if role.is_authority() { Some(keystore_container.sync_keystore()) } else { None };
 
 	let grandpa_config = sc_finality_grandpa::Config {
-		// FIXME #1578 make this available through chainspec
-		gossip_duration: Duration::from_millis(333),
-		justification_period: 512,
+		gossip_duration: grandpa_timings.gossip_duration,
+		justification_period: grandpa_timings.justification_period,
 		name: Some(name),
 		observer_enabled: false,
 		keystore,
@@ -331,3 +379,298 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 	network_starter.start_network();
 	Ok(task_manager)
 }
+
+/// Builds a development node that seals blocks on demand instead of running Aura and Grandpa
+pub fn new_manual_seal(config: Configuration, sealing: Sealing) -> Result<TaskManager, ServiceError> {
+	let executor = NativeElseWasmExecutor::<ExecutorDispatch>::new(
+		config.wasm_method,
+		config.default_heap_pages,
+		config.max_runtime_instances,
+		config.runtime_cache_size,
+	);
+
+	let (client, backend, keystore_container, mut task_manager) =
+		sc_service::new_full_parts::<Block, RuntimeApi, _>(&config, None, executor)?;
+	let client = Arc::new(client);
+
+	let select_chain = sc_consensus::LongestChain::new(backend.clone());
+
+	let transaction_pool = sc_transaction_pool::BasicPool::new_full(
+		config.transaction_pool.clone(),
+		config.role.is_authority().into(),
+		config.prometheus_registry(),
+		task_manager.spawn_essential_handle(),
+		client.clone(),
+	);
+
+	let import_queue = sc_consensus_manual_seal::import_queue(
+		Box::new(client.clone()),
+		&task_manager.spawn_essential_handle(),
+		config.prometheus_registry(),
+	);
+
+	let (network, system_rpc_tx, network_starter) =
+		sc_service::build_network(sc_service::BuildNetworkParams {
+			config: &config,
+			client: client.clone(),
+			transaction_pool: transaction_pool.clone(),
+			spawn_handle: task_manager.spawn_handle(),
+			import_queue,
+			block_announce_validator_builder: None,
+			warp_sync: None,
+		})?;
+
+	let prometheus_registry = config.prometheus_registry().cloned();
+
+	// Commands of the `engine_*` RPCs, only used when sealing manually
+	let (command_sink, commands_stream) = futures::channel::mpsc::channel(1024);
+
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let properties =
+			crate::chain_spec::ChainProperties::from_properties(&config.chain_spec.properties())
+				.map_err(ServiceError::Other)?;
+		let command_sink = (sealing == Sealing::Manual).then(|| command_sink);
+
+		Box::new(move |_, _| {
+			Ok(crate::rpc::create_full(client.clone(), properties.clone(), command_sink.clone()))
+		})
+	};
+
+	let _rpc_handlers = sc_service::spawn_tasks(sc_service::SpawnTasksParams {
+		network,
+		client: client.clone(),
+		keystore: keystore_container.sync_keystore(),
+		task_manager: &mut task_manager,
+		transaction_pool: transaction_pool.clone(),
+		rpc_extensions_builder,
+		backend,
+		system_rpc_tx,
+		config,
+		telemetry: None,
+	})?;
+
+	let proposer_factory = sc_basic_authorship::ProposerFactory::new(
+		task_manager.spawn_handle(),
+		client.clone(),
+		transaction_pool.clone(),
+		prometheus_registry.as_ref(),
+		None,
+	);
+
+	let create_inherent_data_providers = move |_, ()| async move {
+		let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
+		Ok::<_, Box<dyn std::error::Error + Send + Sync>>(timestamp)
+	};
+
+	let authorship_future = match sealing {
+		Sealing::Instant =>
+			sc_consensus_manual_seal::run_instant_seal(InstantSealParams {
+				block_import: client.clone(),
+				env: proposer_factory,
+				client,
+				pool: transaction_pool,
+				select_chain,
+				consensus_data_provider: None,
+				create_inherent_data_providers,
+			})
+			.boxed(),
+		Sealing::Manual | Sealing::Interval(_) => {
+			let commands_stream: Pin<Box<dyn Stream<Item = EngineCommand<H256>> + Send>> =
+				match sealing {
+					Sealing::Interval(millis) => Box::pin(futures::stream::unfold((), move |()| {
+						async move {
+							futures_timer::Delay::new(Duration::from_millis(millis)).await;
+							let command = EngineCommand::SealNewBlock {
+								create_empty: true,
+								finalize: true,
+								parent_hash: None,
+								sender: None,
+							};
+							Some((command, ()))
+						}
+					})),
+					_ => Box::pin(commands_stream),
+				};
+
+			sc_consensus_manual_seal::run_manual_seal(ManualSealParams {
+				block_import: client.clone(),
+				env: proposer_factory,
+				client,
+				pool: transaction_pool,
+				commands_stream,
+				select_chain,
+				consensus_data_provider: None,
+				create_inherent_data_providers,
+			})
+			.boxed()
+		},
+	};
+
+	// the authoring task is considered essential, i.e. if it
+	// fails we take down the service with it.
+	task_manager.spawn_essential_handle().spawn_blocking(
+		"manual-seal",
+		Some("block-authoring"),
+		authorship_future,
+	);
+
+	network_starter.start_network();
+	Ok(task_manager)
+}
+
+/// Builds a full node running proof of work consensus instead of Aura and Grandpa.
+/// With a `miner` key it also mines on the CPU, paying the coinbase reward of every block it
+/// mines to that key.
+pub fn new_pow(config: Configuration, miner: Option<H256>) -> Result<TaskManager, ServiceError> {
+	let executor = NativeElseWasmExecutor::<ExecutorDispatch>::new(
+		config.wasm_method,
+		config.default_heap_pages,
+		config.max_runtime_instances,
+		config.runtime_cache_size,
+	);
+
+	let (client, backend, keystore_container, mut task_manager) =
+		sc_service::new_full_parts::<Block, RuntimeApi, _>(&config, None, executor)?;
+	let client = Arc::new(client);
+
+	let select_chain = sc_consensus::LongestChain::new(backend.clone());
+
+	let transaction_pool = sc_transaction_pool::BasicPool::new_full(
+		config.transaction_pool.clone(),
+		config.role.is_authority().into(),
+		config.prometheus_registry(),
+		task_manager.spawn_essential_handle(),
+		client.clone(),
+	);
+
+	let algorithm = crate::pow::Blake2Algorithm::new(client.clone());
+
+	// Imported blocks have their coinbase timestamp checked against the local clock
+	let pow_block_import = sc_consensus_pow::PowBlockImport::new(
+		client.clone(),
+		client.clone(),
+		algorithm.clone(),
+		0,
+		select_chain.clone(),
+		move |_, ()| async move {
+			let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
+			Ok::<_, Box<dyn std::error::Error + Send + Sync>>(timestamp)
+		},
+		sp_consensus::AlwaysCanAuthor,
+	);
+
+	let import_queue = sc_consensus_pow::import_queue(
+		Box::new(pow_block_import.clone()),
+		None,
+		algorithm.clone(),
+		&task_manager.spawn_essential_handle(),
+		config.prometheus_registry(),
+	)?;
+
+	let (network, system_rpc_tx, network_starter) =
+		sc_service::build_network(sc_service::BuildNetworkParams {
+			config: &config,
+			client: client.clone(),
+			transaction_pool: transaction_pool.clone(),
+			spawn_handle: task_manager.spawn_handle(),
+			import_queue,
+			block_announce_validator_builder: None,
+			warp_sync: None,
+		})?;
+
+	let prometheus_registry = config.prometheus_registry().cloned();
+
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let properties =
+			crate::chain_spec::ChainProperties::from_properties(&config.chain_spec.properties())
+				.map_err(ServiceError::Other)?;
+
+		Box::new(move |_, _| Ok(crate::rpc::create_full(client.clone(), properties.clone(), None)))
+	};
+
+	let _rpc_handlers = sc_service::spawn_tasks(sc_service::SpawnTasksParams {
+		network: network.clone(),
+		client: client.clone(),
+		keystore: keystore_container.sync_keystore(),
+		task_manager: &mut task_manager,
+		transaction_pool: transaction_pool.clone(),
+		rpc_extensions_builder,
+		backend,
+		system_rpc_tx,
+		config,
+		telemetry: None,
+	})?;
+
+	if let Some(miner) = miner {
+		let proposer_factory = sc_basic_authorship::ProposerFactory::new(
+			task_manager.spawn_handle(),
+			client.clone(),
+			transaction_pool,
+			prometheus_registry.as_ref(),
+			None,
+		);
+
+		let can_author_with =
+			sp_consensus::CanAuthorWithNativeVersion::new(client.executor().clone());
+
+		// The worker builds blocks on the best block and imports them once sealed,
+		// the nonce search itself runs in a blocking task of its own below.
+		let (worker, worker_task) = sc_consensus_pow::start_mining_worker(
+			Box::new(pow_block_import),
+			client,
+			select_chain,
+			algorithm,
+			proposer_factory,
+			network.clone(),
+			network,
+			None,
+			move |_, ()| async move {
+				let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
+				let coinbase = crate::pow::CoinbaseInherentDataProvider(miner);
+				Ok::<_, Box<dyn std::error::Error + Send + Sync>>((timestamp, coinbase))
+			},
+			// Time to wait for a new block before building a fresh one with newer transactions
+			Duration::from_secs(10),
+			// Time allowed to build a block
+			Duration::from_secs(10),
+			can_author_with,
+		);
+
+		task_manager
+			.spawn_essential_handle()
+			.spawn_blocking("pow", Some("block-authoring"), worker_task);
+
+		let miner = async move {
+			let mut nonce = U256::from(rand::random::<u64>());
+			loop {
+				let metadata = match worker.metadata() {
+					Some(metadata) => metadata,
+					None => {
+						futures_timer::Delay::new(Duration::from_millis(500)).await;
+						continue
+					},
+				};
+				// Check for a new block to mine on after every batch of nonces
+				let seal =
+					crate::pow::mine(&metadata.pre_hash, metadata.difficulty, &mut nonce, 10_000);
+				match seal {
+					Some(seal) =>
+						if !worker.submit(seal).await {
+							log::warn!(target: "pow", "Mined seal was rejected by the mining worker");
+						},
+					// Lets the task manager stop the miner between batches
+					None => crate::pow::yield_now().await,
+				}
+			}
+		};
+		// Essential, so the node stops if the miner does and the miner stops with the node
+		task_manager
+			.spawn_essential_handle()
+			.spawn_blocking("pow-miner", Some("block-authoring"), miner);
+	}
+
+	network_starter.start_network();
+	Ok(task_manager)
+}
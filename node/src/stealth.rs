@@ -0,0 +1,235 @@
+//! Stealth payments.
+//!
+//! A stealth address publishes two keys of the receiver: a scan key `A = a·G` and a spend key
+//! `B = b·G`. The sender picks an ephemeral secret `r`, announces `R = r·G` in a data output of
+//! the transaction and pays output `i` to the one-time key `P = B + H(r·A, i)·G`. The receiver
+//! finds its outputs by checking `B + H(a·R, i)·G` against the outputs of every transaction
+//! and spends them with the one-time secret `b + H(a·R, i)`. Without the scan secret the
+//! one-time keys cannot be linked to the address or to each other.
+//!
+//! The runtime sees ordinary outputs locked to a key, nothing about stealth payments is
+//! checked on chain.
+
+use curve25519_dalek::{
+	constants::RISTRETTO_BASEPOINT_TABLE,
+	ristretto::{CompressedRistretto, RistrettoPoint},
+	scalar::Scalar,
+};
+use rand::Rng;
+use sp_core::{
+	hashing::{blake2_256, blake2_512},
+	sr25519::Pair,
+	Encode, Pair as PairT, H256,
+};
+use utxo_frameless_runtime::utxo::{Lock, Transaction, TransactionOutput};
+
+/// Prefix of the data output announcing the ephemeral key of a stealth payment
+pub const EPHEMERAL_KEY_PREFIX: &[u8] = b"stealth";
+
+/// Hard derivation of the wallet's secret URI holding the scan key
+pub const SCAN_KEY_DERIVATION: &str = "//stealth-scan";
+
+/// Domain separator of the hash turning a shared secret into a key tweak
+const TWEAK_CONTEXT: &[u8] = b"frameless-utxo-stealth";
+
+/// Published keys of a stealth address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StealthAddress {
+	/// Key senders derive the shared secret with
+	pub scan: H256,
+
+	/// Key the one-time keys are derived from
+	pub spend: H256,
+}
+
+/// A stealth payment being built. All outputs of a transaction share its ephemeral key.
+pub struct StealthPayment {
+	secret: Scalar,
+	ephemeral_key: H256,
+}
+
+impl StealthPayment {
+	/// Start a payment with a fresh ephemeral key
+	pub fn random() -> Self {
+		let mut seed = [0u8; 64];
+		rand::thread_rng().fill(&mut seed[..]);
+		let secret = Scalar::from_bytes_mod_order_wide(&seed);
+		Self { secret, ephemeral_key: public_key(&secret) }
+	}
+
+	/// One-time key paying `address` in output `index` of the transaction
+	pub fn one_time_key(&self, address: &StealthAddress, index: u64) -> Result<H256, String> {
+		let shared = self.secret * point(&address.scan)?;
+		let key = point(&address.spend)? + &tweak(&shared, index) * &RISTRETTO_BASEPOINT_TABLE;
+		Ok(H256(key.compress().to_bytes()))
+	}
+
+	/// Data output announcing the ephemeral key to the receivers
+	pub fn announcement(&self) -> TransactionOutput {
+		TransactionOutput {
+			value: 0,
+			pubkey: H256::zero(),
+			lock: Lock::Pubkey,
+			data: Some([EPHEMERAL_KEY_PREFIX, self.ephemeral_key.as_bytes()].concat()),
+			confidential: None,
+		}
+	}
+}
+
+/// Ephemeral key announced by a transaction, `None` if it does not pay stealth addresses
+pub fn ephemeral_key(transaction: &Transaction) -> Option<H256> {
+	transaction.outputs.iter().find_map(|output| {
+		let key = output.data.as_ref()?.strip_prefix(EPHEMERAL_KEY_PREFIX)?;
+		(key.len() == 32).then(|| H256::from_slice(key))
+	})
+}
+
+/// Receiving side of a stealth address
+pub struct StealthWallet {
+	scan: Scalar,
+	spend: Pair,
+}
+
+impl StealthWallet {
+	/// Wallet of the secret URI `suri`. Its key is the spend key, the scan key is its
+	/// `SCAN_KEY_DERIVATION`, so the scan secret can be handed to a scanner without
+	/// giving away the funds.
+	pub fn from_suri(suri: &str) -> Result<Self, String> {
+		let pair = |suri: &str| {
+			Pair::from_string(suri, None).map_err(|e| format!("Invalid secret URI: {:?}", e))
+		};
+		let scan = pair(&format!("{}{}", suri, SCAN_KEY_DERIVATION))?;
+		Ok(Self { scan: secret_scalar(&scan), spend: pair(suri)? })
+	}
+
+	/// Address senders pay this wallet to
+	pub fn address(&self) -> StealthAddress {
+		StealthAddress { scan: public_key(&self.scan), spend: H256::from(self.spend.public().0) }
+	}
+
+	/// Indices of the outputs of `transaction` paying this wallet, with their one-time keys.
+	/// Confidential outputs are skipped, their amounts cannot be opened from the scan key.
+	pub fn detect(&self, transaction: &Transaction) -> Vec<(usize, Pair)> {
+		let shared = match ephemeral_key(transaction).and_then(|key| point(&key).ok()) {
+			Some(ephemeral_key) => self.scan * ephemeral_key,
+			None => return Vec::new(),
+		};
+		transaction
+			.outputs
+			.iter()
+			.enumerate()
+			.filter(|(_, output)| {
+				output.is_spendable() &&
+					output.lock == Lock::Pubkey &&
+					output.confidential.is_none()
+			})
+			.filter_map(|(index, output)| {
+				let pair = self.one_time_pair(&shared, index as u64);
+				(H256::from(pair.public().0) == output.pubkey).then(|| (index, pair))
+			})
+			.collect()
+	}
+
+	fn one_time_pair(&self, shared: &RistrettoPoint, index: u64) -> Pair {
+		let tweak = tweak(shared, index);
+		let secret = secret_scalar(&self.spend) + tweak;
+		// The signing nonce seed is tweaked as well, so one-time keys never share it
+		let nonce = blake2_256(&[&self.spend.to_raw_vec()[32..], &tweak.as_bytes()[..]].concat());
+		Pair::from_seed_slice(&[&secret.as_bytes()[..], &nonce[..]].concat())
+			.expect("A canonical scalar and a nonce form a valid secret key; qed")
+	}
+}
+
+/// Decompress a public key
+fn point(key: &H256) -> Result<RistrettoPoint, String> {
+	CompressedRistretto(key.to_fixed_bytes())
+		.decompress()
+		.ok_or_else(|| format!("{:?} is not a valid sr25519 public key", key))
+}
+
+fn public_key(secret: &Scalar) -> H256 {
+	H256((secret * &RISTRETTO_BASEPOINT_TABLE).compress().to_bytes())
+}
+
+/// Secret scalar of a key pair, the first half of its raw secret key
+pub fn secret_scalar(pair: &Pair) -> Scalar {
+	let mut key = [0u8; 32];
+	key.copy_from_slice(&pair.to_raw_vec()[..32]);
+	Scalar::from_canonical_bytes(key).expect("sr25519 secret keys hold canonical scalars; qed")
+}
+
+/// Tweak of the one-time key of output `index`
+fn tweak(shared: &RistrettoPoint, index: u64) -> Scalar {
+	let preimage = (TWEAK_CONTEXT, shared.compress().to_bytes(), index).encode();
+	Scalar::from_bytes_mod_order_wide(&blake2_512(&preimage))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::sr25519::Public;
+
+	fn output(pubkey: H256) -> TransactionOutput {
+		TransactionOutput { value: 100, pubkey, lock: Lock::Pubkey, data: None, confidential: None }
+	}
+
+	/// Transaction paying `keys` in its first outputs, announcing the ephemeral key last
+	fn transaction(payment: &StealthPayment, keys: &[H256]) -> Transaction {
+		let mut outputs: Vec<_> = keys.iter().map(|key| output(*key)).collect();
+		outputs.push(payment.announcement());
+		Transaction { inputs: Vec::new(), outputs, valid_from: None, valid_until: None, fee: None }
+	}
+
+	#[test]
+	fn detect_finds_the_outputs_paid_to_the_wallet() {
+		let wallet = StealthWallet::from_suri("//Alice").unwrap();
+		let payment = StealthPayment::random();
+		let key = payment.one_time_key(&wallet.address(), 1).unwrap();
+		let other = H256::from(Pair::from_string("//Bob", None).unwrap().public().0);
+		let transaction = transaction(&payment, &[other, key]);
+
+		assert_eq!(ephemeral_key(&transaction), Some(payment.ephemeral_key));
+		let detected = wallet.detect(&transaction);
+		assert_eq!(detected.len(), 1);
+		assert_eq!(detected[0].0, 1);
+		assert_eq!(H256::from(detected[0].1.public().0), key);
+	}
+
+	#[test]
+	fn detect_needs_the_scan_key() {
+		let wallet = StealthWallet::from_suri("//Alice").unwrap();
+		let payment = StealthPayment::random();
+		let key = payment.one_time_key(&wallet.address(), 0).unwrap();
+		let transaction = transaction(&payment, &[key]);
+
+		// Same spend key, but the scan key of another wallet
+		let mut other = StealthWallet::from_suri("//Alice").unwrap();
+		other.scan = StealthWallet::from_suri("//Bob").unwrap().scan;
+		assert!(other.detect(&transaction).is_empty());
+		assert!(StealthWallet::from_suri("//Bob").unwrap().detect(&transaction).is_empty());
+	}
+
+	#[test]
+	fn one_time_keys_differ_per_output() {
+		let address = StealthWallet::from_suri("//Alice").unwrap().address();
+		let payment = StealthPayment::random();
+		let first = payment.one_time_key(&address, 0).unwrap();
+		let second = payment.one_time_key(&address, 1).unwrap();
+		assert_ne!(first, second);
+		assert_ne!(first, address.spend);
+		assert_ne!(first, StealthPayment::random().one_time_key(&address, 0).unwrap());
+	}
+
+	#[test]
+	fn one_time_pair_signs_for_the_output_key() {
+		let wallet = StealthWallet::from_suri("//Alice").unwrap();
+		let payment = StealthPayment::random();
+		let key = payment.one_time_key(&wallet.address(), 0).unwrap();
+		let (_, pair) = wallet.detect(&transaction(&payment, &[key])).pop().unwrap();
+
+		let message = b"spend the stealth output";
+		let signature = pair.sign(message);
+		assert!(Pair::verify(&signature, message, &Public::from_h256(key)));
+		assert!(!Pair::verify(&signature, message, &wallet.spend.public()));
+	}
+}
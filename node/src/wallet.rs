@@ -0,0 +1,291 @@
+//! Command line utilities to manage pending spends, aggregate keys and stealth payments.
+//!
+//! Like the `psbt` subcommands, these work on partially signed transactions stored as JSON
+//! and print their result to stdout.
+
+use sc_cli::{CliConfiguration, DatabaseParams, PruningParams, Result, SharedParams, SubstrateCli};
+use sc_client_api::{BlockBackend, StorageProvider};
+use sc_service::PartialComponents;
+use sp_blockchain::HeaderBackend;
+use sp_core::{
+	crypto::default_ss58_version, sr25519::Pair, storage::StorageKey, Decode, Encode,
+	Pair as PairT, H256, H512,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{BlakeTwo256, Hash},
+};
+use std::{path::PathBuf, sync::Arc};
+use utxo_frameless_runtime::{
+	musig,
+	psbt::PartiallySignedTransaction,
+	utxo::{self, Lock, SigHash, Transaction, TransactionInput, TransactionOutput, Value},
+	BlockNumber,
+};
+
+use crate::{
+	address::{format_owner, format_pubkey_hash, format_stealth_address, parse_lock, parse_owner},
+	chain_spec::ChainProperties,
+	psbt::{print_psbt, read_psbt, sign_psbt},
+	service::{self, FullClient},
+	stealth::StealthWallet,
+};
+
+/// Wallet utilities
+#[derive(Debug, clap::Subcommand)]
+pub enum WalletSubcommand {
+	/// Rebuild a pending spend paying a higher fee and re-sign it, so it replaces the
+	/// pending version in the transaction pool
+	#[clap(name = "bumpfee")]
+	BumpFee(BumpFeeCmd),
+
+	/// Show the addresses of a key: the one locking outputs to the key and the shorter one
+	/// locking them to its hash, which keeps the key private until they are spent
+	Address(AddressCmd),
+
+	/// Show the key and addresses several participants lock outputs to when each of them has
+	/// to sign to spend them. The key is spent with `musig`.
+	AggregateKey(AggregateKeyCmd),
+
+	/// Show the stealth address of a wallet. Every payment to it goes to a new one-time key.
+	StealthAddress(StealthAddressCmd),
+
+	/// Find the outputs paid to the stealth address of a wallet in the local database and
+	/// optionally sweep them. The node must not be running.
+	Scan(ScanCmd),
+}
+
+impl WalletSubcommand {
+	/// Run the wallet subcommand
+	pub fn run(&self, cli: &impl SubstrateCli) -> Result<()> {
+		match self {
+			WalletSubcommand::BumpFee(cmd) => cmd.run(),
+			WalletSubcommand::Address(cmd) => cmd.run(),
+			WalletSubcommand::AggregateKey(cmd) => cmd.run(),
+			WalletSubcommand::StealthAddress(cmd) => cmd.run(),
+			WalletSubcommand::Scan(cmd) => {
+				let runner = cli.create_runner(cmd)?;
+				runner.sync_run(|config| {
+					let properties =
+						ChainProperties::from_properties(&config.chain_spec.properties())?;
+					let PartialComponents { client, .. } = service::new_partial(&config)?;
+					cmd.run(client, &properties)
+				})
+			},
+		}
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct BumpFeeCmd {
+	/// Partially signed transaction file of the pending spend
+	pub psbt: PathBuf,
+
+	/// Index of the output the additional fee is taken from
+	#[clap(long)]
+	pub change_output: usize,
+
+	/// Additional fee to pay, at least the fee increment of the chain
+	#[clap(long)]
+	pub amount: Value,
+
+	/// Fee increment currently held in the state of the chain, e.g. read with the
+	/// `state_getStorage` RPC of the `fee_increment` key, `0x6665655f696e6372656d656e74`
+	#[clap(long)]
+	pub fee_increment: Value,
+
+	/// Secret URI of the signing key, e.g. a seed phrase or `//Alice`. Inputs owned by other
+	/// keys have to be signed again with `psbt sign`.
+	#[clap(long)]
+	pub suri: String,
+}
+
+impl BumpFeeCmd {
+	pub fn run(&self) -> Result<()> {
+		let mut psbt = read_psbt(&self.psbt)?;
+		let pair = Pair::from_string(&self.suri, None)
+			.map_err(|e| format!("Invalid secret URI: {:?}", e))?;
+
+		psbt.bump_fee(self.change_output, self.amount, self.fee_increment)?;
+		if sign_psbt(&mut psbt, &pair)? == 0 {
+			return Err("None of the inputs is owned by this key".into())
+		}
+		print_psbt(&psbt)
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct AddressCmd {
+	/// Address or hex encoded sr25519 public key
+	#[clap(parse(try_from_str = parse_owner))]
+	pub owner: H256,
+}
+
+impl AddressCmd {
+	pub fn run(&self) -> Result<()> {
+		let hash = utxo::pubkey_hash(&self.owner);
+		println!("Address: {}", format_owner(&self.owner));
+		println!("Pubkey hash: {:?}", hash);
+		println!("Pubkey hash address: {}", format_pubkey_hash(&hash, default_ss58_version()));
+		Ok(())
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct AggregateKeyCmd {
+	/// Address or hex encoded sr25519 public key of every participant, in any order
+	#[clap(required = true, parse(try_from_str = parse_owner))]
+	pub keys: Vec<H256>,
+}
+
+impl AggregateKeyCmd {
+	pub fn run(&self) -> Result<()> {
+		let key = musig::aggregate_key(&self.keys)?;
+		let hash = utxo::pubkey_hash(&key);
+		println!("Aggregate key: {:?}", key);
+		println!("Address: {}", format_owner(&key));
+		println!("Pubkey hash address: {}", format_pubkey_hash(&hash, default_ss58_version()));
+		Ok(())
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct StealthAddressCmd {
+	/// Secret URI of the wallet, e.g. a seed phrase or `//Alice`
+	#[clap(long)]
+	pub suri: String,
+}
+
+impl StealthAddressCmd {
+	pub fn run(&self) -> Result<()> {
+		let address = StealthWallet::from_suri(&self.suri)?.address();
+		println!("Stealth address: {}", format_stealth_address(&address, default_ss58_version()));
+		println!("Scan key: {:?}", address.scan);
+		println!("Spend key: {:?}", address.spend);
+		Ok(())
+	}
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct ScanCmd {
+	/// Secret URI of the wallet, e.g. a seed phrase or `//Alice`
+	#[clap(long)]
+	pub suri: String,
+
+	/// First block to scan
+	#[clap(long, default_value = "0")]
+	pub from: BlockNumber,
+
+	/// Spend every unspent output found to this owner and print the signed partially signed
+	/// transaction instead of the outputs. Takes the owners of `psbt create --output`, except
+	/// stealth addresses.
+	#[clap(long, parse(try_from_str = parse_lock))]
+	pub sweep_to: Option<(H256, Lock)>,
+
+	/// Fee paid by the sweep
+	#[clap(long, default_value = "0")]
+	pub fee: Value,
+
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+
+	#[clap(flatten)]
+	pub pruning_params: PruningParams,
+}
+
+impl ScanCmd {
+	pub fn run(&self, client: Arc<FullClient>, properties: &ChainProperties) -> Result<()> {
+		let wallet = StealthWallet::from_suri(&self.suri)?;
+		let info = client.info();
+		let best = BlockId::Hash(info.best_hash);
+
+		let mut unspent = Vec::new();
+		for number in self.from..=info.best_number {
+			let hash =
+				client.hash(number)?.ok_or_else(|| format!("Block {} is missing", number))?;
+			let extrinsics = client.block_body(&BlockId::Hash(hash))?.unwrap_or_default();
+			for extrinsic in extrinsics {
+				let transaction = Transaction::decode(&mut &extrinsic.encode()[..])
+					.map_err(|e| format!("Invalid extrinsic in block {}: {}", number, e))?;
+				let stripped = utxo::get_stripped_transaction(&transaction);
+				for (index, pair) in wallet.detect(&transaction) {
+					let outpoint = BlakeTwo256::hash_of(&(&stripped, index as u64));
+					let output = transaction.outputs[index].clone();
+					let spent = client.storage(&best, &StorageKey(outpoint.encode()))?.is_none();
+					if self.sweep_to.is_none() {
+						println!(
+							"{:?} value: {} block: {}{}",
+							outpoint,
+							properties.format_amount(output.value),
+							number,
+							if spent { " (spent)" } else { "" },
+						);
+					}
+					if !spent {
+						unspent.push((outpoint, output, pair));
+					}
+				}
+			}
+		}
+
+		let (pubkey, lock) = match &self.sweep_to {
+			Some(owner) => owner.clone(),
+			None => return Ok(()),
+		};
+		if unspent.is_empty() {
+			return Err("No unspent stealth outputs to sweep".into())
+		}
+		let mut total: Value = 0;
+		for (_, output, _) in unspent.iter() {
+			total =
+				total.checked_add(output.value).ok_or("Total value of the outputs overflows")?;
+		}
+		let value = total.checked_sub(self.fee).ok_or("Fee exceeds the value of the outputs")?;
+
+		let transaction = Transaction {
+			inputs: unspent
+				.iter()
+				.map(|(outpoint, ..)| TransactionInput {
+					outpoint: *outpoint,
+					sigscript: H512::zero(),
+					sighash: SigHash::All,
+					pubkey: None,
+					redeemer: None,
+				})
+				.collect(),
+			outputs: vec![TransactionOutput {
+				value,
+				pubkey,
+				lock,
+				data: None,
+				confidential: None,
+			}],
+			valid_from: None,
+			valid_until: None,
+			fee: None,
+		};
+		let prevouts = unspent.iter().map(|(_, output, _)| output.clone()).collect();
+		let mut psbt = PartiallySignedTransaction::new(transaction, prevouts, info.genesis_hash)?;
+		for (_, _, pair) in unspent.iter() {
+			sign_psbt(&mut psbt, pair)?;
+		}
+		print_psbt(&psbt)
+	}
+}
+
+impl CliConfiguration for ScanCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+}
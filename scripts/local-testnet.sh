@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+# Start a three validator network (Alice, Bob and Charlie) of the local testnet chain spec
+# on this machine. Alice is the bootnode, its peer id follows from the well-known node key.
+# Logs and chain data go to $BASE (default /tmp/utxo-local), stop the network with Ctrl-C.
+set -euo pipefail
+
+NODE=${NODE:-./target/release/utxo-node}
+BASE=${BASE:-/tmp/utxo-local}
+BOOTNODE=/ip4/127.0.0.1/tcp/30333/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp
+
+mkdir -p "$BASE"
+trap 'kill $(jobs -p) 2>/dev/null' EXIT
+
+start() {
+	local name=$1 offset=$2
+	shift 2
+	"$NODE" --chain local --"$name" --validator \
+		--base-path "$BASE/$name" \
+		--port $((30333 + offset)) --ws-port $((9944 + offset)) --rpc-port $((9933 + offset)) \
+		"$@" > "$BASE/$name.log" 2>&1 &
+	echo "$name: rpc http://localhost:$((9933 + offset)), log $BASE/$name.log"
+}
+
+start alice 0 --node-key 0000000000000000000000000000000000000000000000000000000000000001
+start bob 1 --bootnodes "$BOOTNODE"
+start charlie 2 --bootnodes "$BOOTNODE"
+
+wait